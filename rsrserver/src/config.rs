@@ -0,0 +1,563 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{cidr::Cidr, error::ConfigError, irc::CapConfig};
+
+/// Everything read from the TOML configuration file.
+///
+/// The whole file is parsed and validated before any of it is put to
+/// use, so a broken file is rejected as a unit and never leaves the
+/// server half reconfigured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+
+    #[serde(default)]
+    pub listen: Vec<ListenConfig>,
+
+    /// Certificates served to TLS clients asking for one of their
+    /// names, instead of the listener's own.
+    #[serde(default, rename = "certificate")]
+    pub certificates: Vec<CertificateConfig>,
+
+    #[serde(default)]
+    pub admin: AdminConfig,
+
+    #[serde(default)]
+    pub limits: LimitsConfig,
+
+    #[serde(default)]
+    pub flood: FloodConfig,
+
+    #[serde(default)]
+    pub connections: ConnectionsConfig,
+
+    #[serde(default)]
+    pub caps: CapConfig,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    /// Privilege classes that opers are assigned to, by name.
+    #[serde(default, rename = "class")]
+    pub classes: HashMap<String, ClassConfig>,
+
+    #[serde(default, rename = "oper")]
+    pub opers: Vec<OperConfig>,
+
+    /// Gateways allowed to pass on the addresses of their users
+    /// through WEBIRC.
+    #[serde(default, rename = "webirc")]
+    pub gateways: Vec<WebircConfig>,
+
+    /// File this configuration was loaded from.
+    #[serde(skip)]
+    pub path: PathBuf,
+
+    /// Lines of the message of the day, read from `server.motd`.
+    #[serde(skip)]
+    pub motd: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Name of this server. Can not be changed by a rehash.
+    pub name: String,
+    /// Name of the IRC network this server belongs to. Can not be
+    /// changed by a rehash.
+    pub network: String,
+    /// MOTD file, relative to the configuration file.
+    pub motd: Option<PathBuf>,
+}
+
+/// A listener, serving TLS if it has a certificate and key and
+/// plaintext otherwise. Listeners are bound and closed by a rehash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenConfig {
+    /// `host:port` to listen on. Every address the host resolves to
+    /// is bound.
+    pub addr: String,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Speak the IRCv3 WebSocket transport instead of plain IRC.
+    #[serde(default)]
+    pub websocket: bool,
+    /// Origins browsers may open a WebSocket from. Any origin is
+    /// allowed if empty.
+    #[serde(default)]
+    pub origins: Vec<String>,
+    /// Expect a PROXY protocol (v1 or v2) header ahead of every
+    /// connection, and take the client address from it.
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Proxies in front of the listener. Only these may send PROXY
+    /// protocol headers, if set, and only their `X-Forwarded-For` and
+    /// `X-Forwarded-Proto` headers are trusted on a WebSocket listener.
+    #[serde(default)]
+    pub proxies: Vec<IpAddr>,
+}
+
+impl ListenConfig {
+    pub fn is_tls(&self) -> bool {
+        self.cert.is_some()
+    }
+}
+
+/// A certificate picked by the server name a TLS client asks for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CertificateConfig {
+    /// Server names to serve the certificate for. `*.example.com`
+    /// matches a single label in front of `example.com`.
+    pub names: Vec<String>,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Lines sent in reply to `ADMIN`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub location: String,
+    pub institution: String,
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Seconds of silence from a client before it is sent a PING.
+    pub idle_timeout: u64,
+    /// Seconds a client has to answer a PING before it is dropped.
+    pub ping_timeout: u64,
+    /// Longest line accepted from a client, in bytes.
+    pub max_line: usize,
+    /// Bytes waiting to be sent to a client before it is disconnected
+    /// for being too slow to read them. Read when a client connects.
+    pub sendq: usize,
+    /// Messages buffered on the server-wide bus. Only read at startup.
+    pub server_bus_capacity: usize,
+    /// Messages buffered for each channel before members too slow to
+    /// keep up miss some. Read when a channel is created.
+    pub channel_capacity: usize,
+    /// Maximum number of targets of a single PRIVMSG or NOTICE.
+    pub targmax: usize,
+    /// Seconds before a PDS grant expires that the client is warned.
+    pub grant_warning: u64,
+    /// Longest a PDS grant is honoured for, in seconds, however far
+    /// out its token claims to expire.
+    pub max_grant: u64,
+    /// Seconds a client has to complete its TLS handshake. Only read
+    /// when a listener is bound.
+    pub handshake_timeout: u64,
+    /// TLS handshakes in progress at once, per listener. Further
+    /// connections wait to be accepted. Only read when a listener is
+    /// bound.
+    pub max_handshakes: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: 30,
+            ping_timeout: 8,
+            max_line: 10240,
+            sendq: 1024 * 1024,
+            server_bus_capacity: 1024,
+            channel_capacity: 1024,
+            targmax: 4,
+            grant_warning: 60,
+            max_grant: 24 * 60 * 60,
+            handshake_timeout: 10,
+            max_handshakes: 256,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout)
+    }
+
+    pub fn ping_timeout(&self) -> Duration {
+        Duration::from_secs(self.ping_timeout)
+    }
+
+    pub fn grant_warning(&self) -> Duration {
+        Duration::from_secs(self.grant_warning)
+    }
+
+    pub fn max_grant(&self) -> Duration {
+        Duration::from_secs(self.max_grant)
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.handshake_timeout)
+    }
+}
+
+/// Fake lag. Once a client has sent `burst` lines at once, its lines
+/// are only processed at `rate` per second, and the rest wait unread.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FloodConfig {
+    pub burst: u32,
+    pub rate: f64,
+    /// Seconds a client may fall behind before it is disconnected for
+    /// Excess Flood.
+    pub max_lag: u64,
+    /// What a line counts as, by command. Commands not listed count as
+    /// a single line.
+    pub costs: HashMap<String, u32>,
+    /// Oper classes whose members are never lagged.
+    pub exempt_classes: Vec<String>,
+    /// Accounts that are never lagged, such as those of bots.
+    pub exempt_accounts: Vec<String>,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            burst: 20,
+            rate: 2.0,
+            max_lag: 30,
+            costs: HashMap::new(),
+            exempt_classes: Vec::new(),
+            exempt_accounts: Vec::new(),
+        }
+    }
+}
+
+impl FloodConfig {
+    /// What a line of `command` counts as.
+    pub fn cost(&self, command: &str) -> u32 {
+        self.costs.get(command).copied().unwrap_or(1)
+    }
+
+    pub fn max_lag(&self) -> Duration {
+        Duration::from_secs(self.max_lag)
+    }
+}
+
+/// Limits on connections from a single address or range, checked
+/// before anything is read from a new connection.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionsConfig {
+    /// Connections open at once from a single address.
+    pub per_ip: usize,
+    /// Connections open at once from a single range of addresses.
+    pub per_range: usize,
+    /// Prefix length of the IPv4 ranges `per_range` applies to.
+    pub ipv4_range: u8,
+    /// Prefix length of the IPv6 ranges `per_range` applies to.
+    pub ipv6_range: u8,
+    /// Connections a single address may make in `throttle_window`
+    /// seconds. Connecting more often refuses it for `throttle_ban`
+    /// seconds.
+    pub throttle: u32,
+    pub throttle_window: u64,
+    pub throttle_ban: u64,
+    /// Addresses and ranges, such as those of gateways and bouncers,
+    /// that none of these limits apply to.
+    pub exempt: Vec<Cidr>,
+}
+
+impl Default for ConnectionsConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: 10,
+            per_range: 50,
+            ipv4_range: 24,
+            ipv6_range: 64,
+            throttle: 10,
+            throttle_window: 60,
+            throttle_ban: 300,
+            exempt: Vec::new(),
+        }
+    }
+}
+
+impl ConnectionsConfig {
+    pub fn throttle_window(&self) -> Duration {
+        Duration::from_secs(self.throttle_window)
+    }
+
+    pub fn throttle_ban(&self) -> Duration {
+        Duration::from_secs(self.throttle_ban)
+    }
+}
+
+/// Settings for `rsr.chat/plc-oauthbearer`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Directory of `<did>.json` documents to resolve DIDs from,
+    /// relative to the configuration file. OAUTHBEARER is refused
+    /// unless some resolver is set up. Only read at startup.
+    pub documents: Option<PathBuf>,
+    /// `aud` claim that PDS tokens must carry. Defaults to
+    /// `did:web:` followed by the server name.
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassConfig {
+    pub privileges: HashSet<Privilege>,
+}
+
+/// Actions reserved for opers whose class grants them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Privilege {
+    Rehash,
+    Kill,
+    Wallops,
+    /// NOTICE to `$mask` targets, reaching every user on the server.
+    GlobalNotice,
+    /// Adding and removing server bans, and listing them.
+    Ban,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperConfig {
+    pub name: String,
+    pub password: String,
+    pub class: String,
+    /// `user@host` masks the oper may log in from. Any host is
+    /// allowed if empty.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// SHA-256 fingerprint of the client certificate the oper has to
+    /// connect with, in hex.
+    pub certfp: Option<String>,
+}
+
+impl OperConfig {
+    pub fn check_password(&self, password: &str) -> bool {
+        passwords_match(&self.password, password)
+    }
+
+    /// Whether a client presenting `certfp`, if anything, may log in
+    /// as this oper.
+    pub fn check_certfp(&self, certfp: Option<&str>) -> bool {
+        match (&self.certfp, certfp) {
+            (None, _) => true,
+            (Some(expected), Some(given)) => expected.eq_ignore_ascii_case(given),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// A WEBIRC gateway, such as a web client or a bouncer.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebircConfig {
+    /// Name the gateway identifies itself with.
+    pub name: String,
+    pub password: String,
+    /// IP masks the gateway connects from.
+    pub hosts: Vec<String>,
+}
+
+impl WebircConfig {
+    pub fn check_password(&self, password: &str) -> bool {
+        passwords_match(&self.password, password)
+    }
+}
+
+/// Compare passwords without leaking the position of the first
+/// mismatch through timing.
+fn passwords_match(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+
+    expected.len() == given.len() && expected.iter().zip(given).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+impl Config {
+    /// Read, parse and validate the configuration file at `path`,
+    /// along with the MOTD file it refers to.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        let mut config: Config = toml::from_str(&text)?;
+        config.path = path.to_owned();
+        config.validate()?;
+
+        if let Some(motd) = &config.server.motd {
+            let motd = config.relative(motd);
+            let text = fs::read_to_string(&motd).map_err(|source| ConfigError::Read { path: motd, source })?;
+            config.motd = Some(text.lines().map(str::to_owned).collect());
+        }
+
+        Ok(config)
+    }
+
+    /// Resolve a path given in the configuration file against the
+    /// directory the file is in.
+    pub fn relative(&self, path: &Path) -> PathBuf {
+        self.path.parent().unwrap_or(Path::new("")).join(path)
+    }
+
+    /// `aud` claim expected in PDS tokens.
+    pub fn audience(&self) -> String {
+        match &self.auth.audience {
+            Some(audience) => audience.clone(),
+            None => format!("did:web:{}", self.server.name),
+        }
+    }
+
+    /// Whether a client logged in as `oper` or into `account` is
+    /// exempt from fake lag.
+    pub fn flood_exempt(&self, oper: Option<&str>, account: Option<&str>) -> bool {
+        let class = oper.and_then(|oper| self.opers.iter().find(|o| o.name == oper)).map(|o| &o.class);
+
+        class.is_some_and(|class| self.flood.exempt_classes.contains(class))
+            || account.is_some_and(|account| self.flood.exempt_accounts.iter().any(|a| a == account))
+    }
+
+    /// Whether the named oper's class grants `privilege`.
+    pub fn has_privilege(&self, oper: &str, privilege: Privilege) -> bool {
+        self.opers
+            .iter()
+            .find(|o| o.name == oper)
+            .and_then(|o| self.classes.get(&o.class))
+            .is_some_and(|class| class.privileges.contains(&privilege))
+    }
+
+    /// Check everything serde can not, reporting every problem at once.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+
+        for (key, value) in [("server.name", &self.server.name), ("server.network", &self.server.network)] {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                errors.push(format!("{key} must be non-empty and contain no whitespace"));
+            }
+        }
+
+        if self.listen.is_empty() {
+            errors.push("at least one [[listen]] block is required".to_owned());
+        }
+
+        for listener in &self.listen {
+            if listener.cert.is_some() != listener.key.is_some() {
+                errors.push(format!("listener {} needs both cert and key, or neither", listener.addr));
+            }
+
+            if !listener.websocket && !listener.origins.is_empty() {
+                errors.push(format!("listener {} only takes origins with websocket = true", listener.addr));
+            }
+
+            if !listener.websocket && !listener.proxy_protocol && !listener.proxies.is_empty() {
+                errors.push(format!(
+                    "listener {} only takes proxies with websocket or proxy_protocol = true",
+                    listener.addr
+                ));
+            }
+        }
+
+        for certificate in &self.certificates {
+            if certificate.names.is_empty() {
+                errors.push(format!("certificate {} needs at least one name", certificate.cert.display()));
+            }
+        }
+
+        let limits = &self.limits;
+        for (key, value) in [
+            ("limits.idle_timeout", limits.idle_timeout as usize),
+            ("limits.ping_timeout", limits.ping_timeout as usize),
+            ("limits.server_bus_capacity", limits.server_bus_capacity),
+            ("limits.channel_capacity", limits.channel_capacity),
+            ("limits.targmax", limits.targmax),
+            ("limits.max_grant", limits.max_grant as usize),
+            ("limits.handshake_timeout", limits.handshake_timeout as usize),
+            ("limits.max_handshakes", limits.max_handshakes),
+        ] {
+            if value == 0 {
+                errors.push(format!("{key} must be greater than 0"));
+            }
+        }
+
+        if limits.max_line < 512 {
+            errors.push("limits.max_line must be at least 512".to_owned());
+        }
+
+        if limits.sendq < limits.max_line {
+            errors.push("limits.sendq must be at least limits.max_line".to_owned());
+        }
+
+        let flood = &self.flood;
+        if flood.burst == 0 || flood.max_lag == 0 {
+            errors.push("flood.burst and flood.max_lag must be greater than 0".to_owned());
+        }
+
+        if !flood.rate.is_finite() || flood.rate < 0.01 {
+            errors.push("flood.rate must be at least 0.01".to_owned());
+        }
+
+        for class in &flood.exempt_classes {
+            if !self.classes.contains_key(class) {
+                errors.push(format!("flood.exempt_classes has unknown class {class}"));
+            }
+        }
+
+        let connections = &self.connections;
+        for (key, value) in [
+            ("connections.per_ip", connections.per_ip),
+            ("connections.per_range", connections.per_range),
+            ("connections.throttle", connections.throttle as usize),
+            ("connections.throttle_window", connections.throttle_window as usize),
+        ] {
+            if value == 0 {
+                errors.push(format!("{key} must be greater than 0"));
+            }
+        }
+
+        if connections.ipv4_range > 32 || connections.ipv6_range > 128 {
+            errors.push("connections.ipv4_range and connections.ipv6_range must be valid prefix lengths".to_owned());
+        }
+
+        let mut names = HashSet::new();
+        for oper in &self.opers {
+            if !names.insert(oper.name.as_str()) {
+                errors.push(format!("oper {} is defined more than once", oper.name));
+            }
+
+            if !self.classes.contains_key(&oper.class) {
+                errors.push(format!("oper {} has unknown class {}", oper.name, oper.class));
+            }
+
+            if let Some(certfp) = &oper.certfp
+                && (certfp.len() != 64 || !certfp.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                errors.push(format!("oper {} needs a certfp of 64 hex digits", oper.name));
+            }
+        }
+
+        for gateway in &self.gateways {
+            if gateway.hosts.is_empty() {
+                errors.push(format!("webirc gateway {} needs at least one host", gateway.name));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(errors)),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use k256::ecdsa::signature::Verifier;
+
+use crate::error::DidError;
+
+/// Multicodec prefix of a compressed secp256k1 public key.
+const SECP256K1_PUB: [u8; 2] = [0xe7, 0x01];
+
+/// Multicodec prefix of a compressed P-256 public key.
+const P256_PUB: [u8; 2] = [0x80, 0x24];
+
+/// A key that atproto allows signing with.
+#[derive(Debug, Clone)]
+pub enum PublicKey {
+    /// Used with `ES256K` tokens.
+    K256(k256::ecdsa::VerifyingKey),
+    /// Used with `ES256` tokens.
+    P256(p256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    /// Decode a `Multikey`, the base58btc multibase encoding of a
+    /// multicodec-prefixed compressed point.
+    pub fn from_multikey(multibase: &str) -> Result<Self, DidError> {
+        let encoded = multibase.strip_prefix('z').ok_or(DidError::InvalidKey)?;
+        let bytes = bs58::decode(encoded).into_vec().map_err(|_| DidError::InvalidKey)?;
+
+        match bytes.split_at_checked(2) {
+            Some((prefix, point)) if prefix == SECP256K1_PUB => k256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+                .map(Self::K256)
+                .map_err(|_| DidError::InvalidKey),
+            Some((prefix, point)) if prefix == P256_PUB => p256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+                .map(Self::P256)
+                .map_err(|_| DidError::InvalidKey),
+            _ => Err(DidError::InvalidKey),
+        }
+    }
+
+    /// The JWT `alg` of signatures made with this key.
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            Self::K256(_) => "ES256K",
+            Self::P256(_) => "ES256",
+        }
+    }
+
+    /// Verify a 64 byte `r || s` signature over the SHA-256 digest of
+    /// `msg`. High-S signatures are rejected, as atproto requires.
+    pub fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match self {
+            // k256 only accepts low-S signatures to begin with.
+            Self::K256(key) => k256::ecdsa::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify(msg, &signature).is_ok()),
+            Self::P256(key) => p256::ecdsa::Signature::from_slice(signature)
+                .is_ok_and(|signature| signature.normalize_s().is_none() && key.verify(msg, &signature).is_ok()),
+        }
+    }
+}
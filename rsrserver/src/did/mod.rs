@@ -0,0 +1,111 @@
+/*!
+    Decentralized identifiers as used by atproto PDSes.
+
+    A user authenticating through `rsr.chat/plc-oauthbearer` presents a
+    token signed by the key published in their `did:plc` or `did:web`
+    document. Documents are looked up through a [DidResolver]: either a
+    local directory of documents, such as a mirror of the PLC
+    directory, or a fixed set of documents for testing.
+*/
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use crate::error::DidError;
+
+mod key;
+pub use key::PublicKey;
+
+mod resolver;
+pub use resolver::FileResolver;
+#[cfg(test)]
+pub use resolver::MemoryResolver;
+
+mod token;
+pub use token::Grant;
+
+/// Looks up the document of a DID.
+///
+/// Resolvers are stored as trait objects in [crate::irc::Network], so
+/// this returns a boxed future rather than an `impl Future`.
+pub trait DidResolver: Send + Sync {
+    /// Fetch the document of `did`, which has already been checked to
+    /// be a syntactically valid `did:plc` or `did:web`.
+    fn resolve<'a>(&'a self, did: &'a str) -> BoxFuture<'a, Result<DidDocument, DidError>>;
+}
+
+/// The parts of a DID document needed to verify tokens.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    pub id: String,
+
+    /// Handles of the user, as `at://` URIs.
+    #[serde(default)]
+    pub also_known_as: Vec<String>,
+
+    #[serde(default)]
+    pub verification_method: Vec<VerificationMethod>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    /// Either the full `did#fragment` or only `#fragment`.
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub controller: String,
+    pub public_key_multibase: Option<String>,
+}
+
+impl DidDocument {
+    /// The `#atproto` key, which the user's PDS signs tokens with.
+    pub fn signing_key(&self) -> Result<PublicKey, DidError> {
+        let method = self
+            .verification_method
+            .iter()
+            .find(|method| {
+                method
+                    .id
+                    .strip_prefix(self.id.as_str())
+                    .unwrap_or(&method.id)
+                    == "#atproto"
+            })
+            .ok_or(DidError::NoSigningKey)?;
+
+        // Only keys published as Multikey, and controlled by the DID
+        // itself rather than delegated to another one, are accepted.
+        if method.kind != "Multikey" || method.controller != self.id {
+            return Err(DidError::InvalidKey);
+        }
+
+        let multibase = method.public_key_multibase.as_deref().ok_or(DidError::NoSigningKey)?;
+        PublicKey::from_multikey(multibase)
+    }
+
+    /// The first handle of the user, without the `at://` scheme.
+    pub fn handle(&self) -> Option<&str> {
+        self.also_known_as.iter().find_map(|aka| aka.strip_prefix("at://"))
+    }
+}
+
+/// Check that `did` is a `did:plc` or `did:web` identifier. Anything
+/// accepted here is also safe to use as a file name.
+pub fn validate(did: &str) -> Result<(), DidError> {
+    let valid = if let Some(id) = did.strip_prefix("did:plc:") {
+        id.len() == 24 && id.bytes().all(|b| matches!(b, b'a'..=b'z' | b'2'..=b'7'))
+    } else if let Some(id) = did.strip_prefix("did:web:") {
+        !id.is_empty()
+            && !id.starts_with('.')
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'%' | b':'))
+    } else {
+        false
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(DidError::UnsupportedDid(did.to_owned())),
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use futures::future::BoxFuture;
+
+use crate::{
+    did::{DidDocument, DidResolver},
+    error::DidError,
+};
+
+/// Serves documents from a directory holding one `<did>.json` file
+/// per DID, such as a mirror of the PLC directory. Files are read on
+/// every lookup, so documents can be updated without a restart.
+pub struct FileResolver {
+    dir: PathBuf,
+}
+
+impl FileResolver {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl DidResolver for FileResolver {
+    fn resolve<'a>(&'a self, did: &'a str) -> BoxFuture<'a, Result<DidDocument, DidError>> {
+        Box::pin(async move {
+            let path = self.dir.join(format!("{did}.json"));
+
+            let text = match tokio::fs::read_to_string(&path).await {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(DidError::NotFound(did.to_owned()));
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            Ok(serde_json::from_str(&text)?)
+        })
+    }
+}
+
+/// Serves a fixed set of documents held in memory.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryResolver {
+    documents: HashMap<String, DidDocument>,
+}
+
+#[cfg(test)]
+impl MemoryResolver {
+    /// Add or replace the document of `document.id`.
+    pub fn insert(&mut self, document: DidDocument) {
+        self.documents.insert(document.id.clone(), document);
+    }
+}
+
+#[cfg(test)]
+impl DidResolver for MemoryResolver {
+    fn resolve<'a>(&'a self, did: &'a str) -> BoxFuture<'a, Result<DidDocument, DidError>> {
+        let document = self
+            .documents
+            .get(did)
+            .cloned()
+            .ok_or_else(|| DidError::NotFound(did.to_owned()));
+
+        Box::pin(async move { document })
+    }
+}
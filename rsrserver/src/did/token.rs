@@ -0,0 +1,102 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{
+    did::{self, DidResolver},
+    error::{DidError, TokenError},
+};
+
+/// Allowance for clocks of the PDS and this server disagreeing.
+const CLOCK_SKEW: TimeDelta = TimeDelta::seconds(30);
+
+/// A verified PDS grant: the user controls `did` until `expires`.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub did: String,
+    /// Handle claimed by the DID document, which is not verified.
+    pub handle: Option<String>,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    aud: String,
+    exp: i64,
+    #[serde(default)]
+    nbf: Option<i64>,
+    #[serde(default)]
+    iat: Option<i64>,
+}
+
+impl Grant {
+    /// Verify a JWT issued for `audience` and signed with the `#atproto`
+    /// key of the DID in its `iss` claim.
+    pub async fn verify(token: &str, audience: &str, resolver: &dyn DidResolver) -> Result<Self, TokenError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(claims), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TokenError::Malformed("expected three segments"));
+        };
+
+        let signed = &token[..header.len() + 1 + claims.len()];
+        let header: Header = decode_json(header)?;
+        let claims: Claims = decode_json(claims)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed("signature is not base64url"))?;
+
+        if claims.aud != audience {
+            return Err(TokenError::WrongAudience);
+        }
+
+        let now = Utc::now();
+        let expires = DateTime::from_timestamp(claims.exp, 0).ok_or(TokenError::Malformed("exp out of range"))?;
+        if expires <= now {
+            return Err(TokenError::Expired);
+        }
+
+        if let Some(not_before) = claims.nbf.or(claims.iat)
+            && not_before > (now + CLOCK_SKEW).timestamp()
+        {
+            return Err(TokenError::NotYetValid);
+        }
+
+        // Resolving is the expensive part, so it comes last.
+        did::validate(&claims.iss)?;
+        let document = resolver.resolve(&claims.iss).await?;
+        if document.id != claims.iss {
+            return Err(DidError::Mismatch(document.id).into());
+        }
+
+        let key = document.signing_key()?;
+        if key.algorithm() != header.alg {
+            return Err(TokenError::UnsupportedAlgorithm(header.alg));
+        }
+
+        if !key.verify(signed.as_bytes(), &signature) {
+            return Err(TokenError::BadSignature);
+        }
+
+        Ok(Self {
+            handle: document.handle().map(str::to_owned),
+            did: claims.iss,
+            expires,
+        })
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(segment: &str) -> Result<T, TokenError> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| TokenError::Malformed("segment is not base64url"))?;
+
+    serde_json::from_slice(&bytes).map_err(|_| TokenError::Malformed("segment is not the expected JSON"))
+}
@@ -34,14 +34,107 @@ pub enum IrcSessionError {
     #[error("Channel Map unexpectedly closed")]
     ChannelEOF,
 
-    #[error("Unsupported CAP Version")]
-    UnsupportedCap,
-
     #[error("Client issued QUIT command. Reason: {0}")]
     ClientQUIT(String),
+
+    #[error("{0}")]
+    Killed(String),
+
+    #[error("Banned ({0})")]
+    Banned(String),
+
+    #[error("Excess Flood")]
+    ExcessFlood,
+
+    #[error("SendQ exceeded")]
+    SendQExceeded,
+
+    #[error("Server shutting down: {0}")]
+    Shutdown(String),
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Unknown capability: {0} (expected one of: {known})", known = crate::irc::ALL_CAPS.trim_end())]
+    UnknownCapability(String),
+
+    #[error("Invalid value for capability: {0}")]
+    InvalidCapabilityValue(String),
+
+    #[error("Cannot read {}: {source}", path.display())]
+    Read {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Cannot parse configuration: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+
+    #[error("{0} can not be changed without a restart")]
+    Immutable(&'static str),
 }
 
 pub enum StorageError<E> {
     
     Backend(E),
-}
\ No newline at end of file
+}
+
+impl<E> From<E> for StorageError<E> {
+    fn from(error: E) -> Self {
+        StorageError::Backend(error)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DidError {
+    #[error("Not a did:plc or did:web identifier: {0}")]
+    UnsupportedDid(String),
+
+    #[error("No document found for {0}")]
+    NotFound(String),
+
+    #[error("Cannot read DID document: {0}")]
+    Read(#[from] std::io::Error),
+
+    #[error("Invalid DID document: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    #[error("DID document is for {0}, not the requested DID")]
+    Mismatch(String),
+
+    #[error("DID document has no atproto signing key")]
+    NoSigningKey,
+
+    #[error("Unsupported or malformed public key")]
+    InvalidKey,
+}
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("Malformed token: {0}")]
+    Malformed(&'static str),
+
+    #[error("Unsupported signing algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+
+    #[error("Token is not for this server")]
+    WrongAudience,
+
+    #[error("Token has expired")]
+    Expired,
+
+    #[error("Token is not valid yet")]
+    NotYetValid,
+
+    #[error("Token signature does not match the DID's signing key")]
+    BadSignature,
+
+    #[error("No DID resolver is configured")]
+    NoResolver,
+
+    #[error("DID resolution failed: {0}")]
+    Resolve(#[from] DidError),
+}
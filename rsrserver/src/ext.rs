@@ -1,5 +1,14 @@
 pub trait StrExt {
     fn slice_at_most(&self, bytes: usize) -> &str;
+
+    /// Fold the string according to the `rfc1459` casemapping
+    /// advertised in ISUPPORT, where `{}|^` are the lowercase
+    /// forms of `[]\~`.
+    fn irc_casefold(&self) -> String;
+
+    /// Match against a mask where `*` stands for any run of
+    /// characters and `?` for exactly one, ignoring case.
+    fn matches_mask(&self, mask: &str) -> bool;
 }
 impl StrExt for &str {
     fn slice_at_most(&self, bytes: usize) -> &str {
@@ -10,4 +19,48 @@ impl StrExt for &str {
             .map(|i| &self[..i])
             .unwrap_or("")
     }
+
+    fn irc_casefold(&self) -> String {
+        self.chars()
+            .map(|c| match c {
+                '[' => '{',
+                ']' => '}',
+                '\\' => '|',
+                '~' => '^',
+                c => c.to_ascii_lowercase(),
+            })
+            .collect()
+    }
+
+    fn matches_mask(&self, mask: &str) -> bool {
+        let text = self.irc_casefold().chars().collect::<Vec<_>>();
+        let mask = mask.irc_casefold().chars().collect::<Vec<_>>();
+
+        // Greedy match, backtracking to the last `*` on a mismatch.
+        let (mut t, mut m) = (0, 0);
+        let mut star = None;
+
+        while t < text.len() {
+            match mask.get(m) {
+                Some('*') => {
+                    star = Some((m, t));
+                    m += 1;
+                }
+                Some(&c) if c == '?' || c == text[t] => {
+                    t += 1;
+                    m += 1;
+                }
+                _ => match star {
+                    Some((sm, st)) => {
+                        star = Some((sm, st + 1));
+                        m = sm + 1;
+                        t = st + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        mask[m..].iter().all(|&c| c == '*')
+    }
 }
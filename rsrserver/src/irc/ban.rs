@@ -0,0 +1,198 @@
+use std::{net::IpAddr, sync::RwLock};
+
+use chrono::{TimeDelta, Utc};
+
+use crate::{
+    cidr::Cidr,
+    error::{IrcResult, IrcSessionError},
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply},
+    storage::{Ban, BanKind, Storage},
+};
+
+/// Every server ban in effect. Kept in memory, so that connections can
+/// be checked without a trip to storage; [crate::storage::Storage] only
+/// keeps them across restarts.
+#[derive(Default)]
+pub struct Bans {
+    bans: RwLock<Vec<Ban>>,
+}
+
+/// A client, as far as bans are concerned.
+pub struct BanTarget<'a> {
+    pub ip: IpAddr,
+    pub user: &'a str,
+    pub host: &'a str,
+    pub account: Option<&'a str>,
+    pub certfp: Option<&'a str>,
+}
+
+impl Ban {
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Utc::now())
+    }
+
+    pub fn matches(&self, target: &BanTarget<'_>) -> bool {
+        match self.kind {
+            BanKind::Host => {
+                let user = target.user;
+                format!("{user}@{}", target.host).as_str().matches_mask(&self.mask)
+                    || format!("{user}@{}", target.ip).as_str().matches_mask(&self.mask)
+            }
+            BanKind::Ip => self.matches_ip(target.ip),
+            BanKind::Account => target.account.is_some_and(|account| account.eq_ignore_ascii_case(&self.mask)),
+            BanKind::Did => target.account == Some(self.mask.as_str()),
+            BanKind::Certfp => target.certfp.is_some_and(|certfp| certfp.eq_ignore_ascii_case(&self.mask)),
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        self.kind == BanKind::Ip && self.mask.parse::<Cidr>().is_ok_and(|range| range.contains(ip))
+    }
+
+    fn matches_account(&self, account: &str) -> bool {
+        match self.kind {
+            BanKind::Account => account.eq_ignore_ascii_case(&self.mask),
+            BanKind::Did => account == self.mask,
+            _ => false,
+        }
+    }
+}
+
+impl BanKind {
+    /// `mask` in the form bans of this kind are kept in, or why it
+    /// can not be one.
+    pub fn normalize(&self, mask: &str) -> Result<String, String> {
+        match self {
+            BanKind::Host if mask.contains(char::is_whitespace) => Err(format!("{mask} is not a user@host mask")),
+            BanKind::Host if mask.contains('@') => Ok(mask.to_owned()),
+            BanKind::Host => Ok(format!("*@{mask}")),
+            BanKind::Ip => mask.parse::<Cidr>().map(|range| range.to_string()),
+            BanKind::Account if mask.is_empty() || mask.contains(char::is_whitespace) => {
+                Err(format!("{mask} is not an account name"))
+            }
+            BanKind::Account => Ok(mask.to_owned()),
+            BanKind::Did if mask.starts_with("did:") && !mask.contains(char::is_whitespace) => Ok(mask.to_owned()),
+            BanKind::Did => Err(format!("{mask} is not a DID")),
+            BanKind::Certfp if mask.len() == 64 && mask.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Ok(mask.to_ascii_lowercase())
+            }
+            BanKind::Certfp => Err(format!("{mask} is not a SHA-256 fingerprint in hex")),
+        }
+    }
+}
+
+impl Bans {
+    /// Put bans read from storage into effect, dropping those that
+    /// have expired since.
+    pub fn load(&self, bans: Vec<Ban>) {
+        *self.bans.write().unwrap() = bans.into_iter().filter(|ban| !ban.is_expired()).collect();
+    }
+
+    /// Put a ban into effect, replacing any other of the same kind and
+    /// mask.
+    pub fn add(&self, ban: Ban) {
+        let mut bans = self.bans.write().unwrap();
+        bans.retain(|b| !(b.kind == ban.kind && b.mask == ban.mask));
+        bans.push(ban);
+    }
+
+    /// Lift the ban of `kind` on `mask`. Returns it, unless there was
+    /// none.
+    pub fn remove(&self, kind: BanKind, mask: &str) -> Option<Ban> {
+        let mut bans = self.bans.write().unwrap();
+        let i = bans.iter().position(|ban| ban.kind == kind && ban.mask == mask)?;
+        Some(bans.remove(i))
+    }
+
+    /// Every ban that has not expired, oldest first.
+    pub fn active(&self) -> Vec<Ban> {
+        let mut bans = self.bans.write().unwrap();
+        bans.retain(|ban| !ban.is_expired());
+        bans.clone()
+    }
+
+    /// The first ban in effect that matches `target`.
+    pub fn find(&self, target: &BanTarget<'_>) -> Option<Ban> {
+        self.find_by(|ban| ban.matches(target))
+    }
+
+    /// The first IP ban in effect on `ip`. This is all that is known
+    /// of a client when its connection is accepted.
+    pub fn find_ip(&self, ip: IpAddr) -> Option<Ban> {
+        self.find_by(|ban| ban.matches_ip(ip))
+    }
+
+    /// The first account or DID ban in effect on `account`.
+    pub fn find_account(&self, account: &str) -> Option<Ban> {
+        self.find_by(|ban| ban.matches_account(account))
+    }
+
+    fn find_by(&self, f: impl Fn(&Ban) -> bool) -> Option<Ban> {
+        self.bans.read().unwrap().iter().find(|ban| !ban.is_expired() && f(ban)).cloned()
+    }
+}
+
+/// Parse how long a ban lasts, such as `30m` or `1d12h`. A number
+/// without a unit is in minutes, and `0` or `perm` is forever.
+pub fn parse_duration(text: &str) -> Option<Option<TimeDelta>> {
+    if text == "0" || text.eq_ignore_ascii_case("perm") {
+        return Some(None);
+    }
+
+    if let Ok(minutes) = text.parse::<u32>() {
+        return Some(Some(TimeDelta::minutes(minutes.into())));
+    }
+
+    let mut total = TimeDelta::zero();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let n: i64 = rest[..digits].parse().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            's' => TimeDelta::try_seconds(n),
+            'm' => TimeDelta::try_minutes(n),
+            'h' => TimeDelta::try_hours(n),
+            'd' => TimeDelta::try_days(n),
+            'w' => TimeDelta::try_weeks(n),
+            _ => None,
+        }?;
+        total = total.checked_add(&unit)?;
+        rest = &rest[digits + 1..];
+    }
+
+    (total > TimeDelta::zero()).then_some(Some(total))
+}
+
+impl<'a, T, S> IrcContext<'a, T, S>
+where
+    T: GenericStateExt,
+    S: Storage,
+{
+    /// The first ban this client matches, by everything known of it.
+    pub fn find_ban(&self) -> Option<Ban> {
+        let session = self.session();
+        self.network().bans().find(&BanTarget {
+            ip: session.client_addr().ip(),
+            user: self.user(),
+            host: session.host(),
+            account: session.account(),
+            certfp: session.certfp(),
+        })
+    }
+
+    /// Disconnect the client if it matches a ban.
+    pub async fn check_bans(&mut self) -> IrcResult<()> {
+        match self.find_ban() {
+            Some(ban) => self.refuse_banned(&ban).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Tell the client it is banned, and end its connection.
+    pub async fn refuse_banned(&mut self, ban: &Ban) -> IrcResult<()> {
+        tracing::info!(source = self.source(), kind = ban.kind.name(), mask = ban.mask, "banned client refused");
+        self.reply(Reply::YoureBannedCreep { reason: &ban.reason }).await?;
+        Err(IrcSessionError::Banned(ban.reason.clone()))
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::{
+    error::{IrcResult, IrcSessionError},
+    irc::{Capabilities, Control, GenericStateExt, IrcContext, Relay, Reply, command::Cap},
+    storage::Storage,
+};
+
+/// Messages sent over the server-wide bus that every connection
+/// subscribes to. Orders for a single connection go through
+/// [crate::irc::Network::control] instead.
+#[derive(Debug, Clone)]
+pub enum ServerMessage {
+    /// Capabilities the server has started advertising.
+    CapNew(Capabilities),
+    /// Capabilities the server has stopped advertising.
+    CapDel(Capabilities),
+    /// A ban was added. Every connection it matches is closed.
+    BanAdded,
+    /// `WALLOPS` for every client with user mode +w.
+    Wallops { source: Arc<str>, text: Arc<str> },
+    /// A NOTICE to every registered client, addressed to a `$mask`.
+    GlobalNotice {
+        source: Arc<str>,
+        mask: Arc<str>,
+        text: Arc<str>,
+    },
+    /// A new configuration has been put into effect by `source`,
+    /// which is either an oper or this server.
+    Rehashed { source: Arc<str> },
+    /// The server is going away. Every connection is closed.
+    Shutdown { reason: Arc<str> },
+}
+
+impl ServerMessage {
+    /// The `CAP NEW`/`CAP DEL` messages announcing a change to the set
+    /// of capabilities advertised by the server.
+    pub fn cap_changes((new, del): (Capabilities, Capabilities)) -> impl Iterator<Item = ServerMessage> {
+        let new = (!new.is_empty()).then_some(ServerMessage::CapNew(new));
+        let del = (!del.is_empty()).then_some(ServerMessage::CapDel(del));
+        new.into_iter().chain(del)
+    }
+}
+
+impl<'a, T, S> IrcContext<'a, T, S>
+where
+    T: GenericStateExt,
+    S: Storage,
+{
+    /// React to a message from the server bus. Messages that end the
+    /// connection are turned into an error.
+    pub async fn handle_server_message(&mut self, msg: ServerMessage) -> IrcResult<()> {
+        match msg {
+            ServerMessage::CapNew(caps) => Cap::notify_new(self, caps).await,
+            ServerMessage::CapDel(caps) => Cap::notify_del(self, caps).await,
+            ServerMessage::BanAdded => self.check_bans().await,
+            ServerMessage::Wallops { source, text } => {
+                if !self.session().wallops() {
+                    return Ok(());
+                }
+
+                let line = Relay::Wallops { text: &text }.render(&source);
+                self.send_client_unchecked(line).await
+            }
+            ServerMessage::GlobalNotice { source, mask, text } => {
+                if !self.registered() {
+                    return Ok(());
+                }
+
+                let line = Relay::Message {
+                    command: "NOTICE",
+                    target: &mask,
+                    text: &text,
+                    account: None,
+                }
+                .render(&source);
+                self.deliver(&line).await
+            }
+            ServerMessage::Rehashed { source } => self.rehashed(&source).await,
+            ServerMessage::Shutdown { reason } => Err(IrcSessionError::Shutdown(reason.to_string())),
+        }
+    }
+
+    /// Carry out an order given to this connection alone.
+    pub async fn handle_control(&mut self, order: Control) -> IrcResult<()> {
+        match order {
+            Control::Kill { source, reason } => {
+                let nick = self.nick().to_owned();
+                let line = Relay::Kill { target: &nick, reason: &reason }.render(&source);
+                self.send_client_unchecked(line).await?;
+
+                Err(IrcSessionError::Killed(format!("Killed ({source} ({reason}))")))
+            }
+            Control::SendQExceeded => Err(IrcSessionError::SendQExceeded),
+        }
+    }
+
+    /// Some messages from the server bus were lost because this
+    /// connection fell behind. Capabilities are the only state kept in
+    /// sync through the bus, so withdraw any that are no longer
+    /// advertised. A lost message may also have been a new ban.
+    pub async fn server_lagged(&mut self, missed: u64) -> IrcResult<()> {
+        tracing::warn!(id = ?self.session().id(), missed, "server bus lagged");
+        self.check_bans().await?;
+
+        let stale = self.session().caps().difference(self.network().caps().advertised());
+        match stale.is_empty() {
+            true => Ok(()),
+            false => Cap::notify_del(self, stale).await,
+        }
+    }
+
+    /// Drop oper status whose oper block was removed, and let the
+    /// remaining opers know that the configuration changed.
+    async fn rehashed(&mut self, source: &str) -> IrcResult<()> {
+        let Some(oper) = self.session().oper() else {
+            return Ok(());
+        };
+
+        if !self.network().config().opers.iter().any(|o| o.name == oper) {
+            self.session_mut().clear_oper();
+            return self
+                .reply(Reply::Notice { text: "Your oper block was removed by a rehash" })
+                .await;
+        }
+
+        let text = format!("{source} rehashed the server configuration");
+        self.reply(Reply::Notice { text: &text }).await
+    }
+}
@@ -1,3 +1,15 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::Deserialize;
+
+use crate::error::ConfigError;
+
 macro_rules! caps {
     // Entry point - start recursion with index 0
     [$(($cap:expr, $name:ident)),+ $(,)?] => {
@@ -26,6 +38,7 @@ macro_rules! caps {
      $(($str_cap:expr),)*;
     ) => {
         bitflags::bitflags! {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
             pub struct Capabilities: u64 {
                 $(
                     const $acc_name = 1 << $acc_idx;
@@ -36,14 +49,201 @@ macro_rules! caps {
         pub const ALL_CAPS: &str = concat!(
             $($str_cap, " "),*
         );
+
+        impl Capabilities {
+            /// Every capability paired with its name on the wire.
+            pub const NAMES: &'static [(&'static str, Capabilities)] = &[
+                $(($acc_cap, Capabilities::$acc_name),)*
+            ];
+        }
     };
 }
 
+/// Capability settings supplied by the server operator.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CapConfig {
+    /// Values advertised in `CAP LS 302`, keyed by capability name.
+    pub values: HashMap<String, String>,
+
+    /// Capabilities that must not be advertised.
+    pub disabled: Vec<String>,
+
+    /// Policy advertised through `sts`, which is only offered if set.
+    pub sts: Option<StsConfig>,
+}
+
+/// Strict Transport Security policy. Plaintext clients are pointed at
+/// a TLS port, and clients connected over TLS are told how long to
+/// keep using it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StsConfig {
+    /// TLS port plaintext clients must reconnect to.
+    pub port: u16,
+    /// Seconds clients remember the policy for. 0 revokes it.
+    pub duration: u64,
+    /// Whether the server agrees to be put on STS preload lists.
+    #[serde(default)]
+    pub preload: bool,
+}
+
+/// Server-wide registry of the capabilities offered to clients and the
+/// value each one carries in `CAP LS 302`.
+pub struct CapRegistry {
+    // Capabilities this server can offer at all.
+    supported: Capabilities,
+    // Bits of the capabilities not disabled by the config. Only those
+    // also supported are advertised.
+    enabled: AtomicU64,
+    values: RwLock<CapValues>,
+}
+
+#[derive(Default)]
+struct CapValues {
+    secure: HashMap<Capabilities, Arc<str>>,
+    // Values that differ for plaintext connections, such as `sts`.
+    plaintext: HashMap<Capabilities, Arc<str>>,
+}
+
+impl CapValues {
+    fn get(&self, cap: Capabilities, secure: bool) -> Option<&Arc<str>> {
+        match secure {
+            true => self.secure.get(&cap),
+            false => self.plaintext.get(&cap).or_else(|| self.secure.get(&cap)),
+        }
+    }
+}
+
+impl CapRegistry {
+    /// Create a registry advertising every implemented capability
+    /// except the ones disabled by `config`.
+    pub fn new(config: &CapConfig) -> Result<Self, ConfigError> {
+        let registry = Self {
+            supported: Capabilities::IMPLEMENTED,
+            enabled: AtomicU64::new(Capabilities::empty().bits()),
+            values: RwLock::new(CapValues::default()),
+        };
+
+        registry.apply(config)?;
+        Ok(registry)
+    }
+
+    /// Also offer `caps`, which depend on how the server is set up
+    /// rather than on the config, unless the config disables them.
+    pub fn support(&mut self, caps: Capabilities) {
+        self.supported |= caps;
+    }
+
+    /// Capabilities currently offered to clients.
+    pub fn advertised(&self) -> Capabilities {
+        Capabilities::from_bits_retain(self.enabled.load(Ordering::Acquire)) & self.supported
+    }
+
+    /// Atomically replace the advertised set and values with the ones in
+    /// `config`. Nothing changes if the config is invalid.
+    ///
+    /// Returns the capabilities clients must be sent `CAP NEW` for (newly
+    /// enabled, or enabled with a changed value) and `CAP DEL` for.
+    pub fn apply(&self, config: &CapConfig) -> Result<(Capabilities, Capabilities), ConfigError> {
+        let mut enabled = Capabilities::all();
+        for name in &config.disabled {
+            enabled.remove(Self::lookup(name)?);
+        }
+
+        let mut values = CapValues::default();
+        for (name, value) in &config.values {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Err(ConfigError::InvalidCapabilityValue(name.clone()));
+            }
+
+            values.secure.insert(Self::lookup(name)?, Arc::from(value.as_str()));
+        }
+
+        match &config.sts {
+            Some(sts) => {
+                let duration = match sts.preload {
+                    true => format!("duration={},preload", sts.duration),
+                    false => format!("duration={}", sts.duration),
+                };
+                values.secure.insert(Capabilities::CapSts, duration.into());
+                values.plaintext.insert(Capabilities::CapSts, format!("port={}", sts.port).into());
+            }
+            None => enabled.remove(Capabilities::CapSts),
+        }
+
+        let mut guard = self.values.write().expect("capability values poisoned");
+        let changed = Capabilities::NAMES
+            .iter()
+            .map(|(_, cap)| *cap)
+            .filter(|cap| [true, false].iter().any(|&secure| guard.get(*cap, secure) != values.get(*cap, secure)))
+            .fold(Capabilities::empty(), |acc, cap| acc | cap);
+        *guard = values;
+
+        let old = Capabilities::from_bits_retain(self.enabled.swap(enabled.bits(), Ordering::AcqRel));
+        drop(guard);
+
+        let (old, enabled) = (old & self.supported, enabled & self.supported);
+        let new = enabled.difference(old) | (changed & old & enabled);
+        Ok((new, old.difference(enabled)))
+    }
+
+    /// Render a space separated list of `caps`, optionally including
+    /// the `=value` suffix understood by CAP 302 clients. Some values
+    /// depend on whether the connection is `secure`.
+    pub fn render(&self, caps: Capabilities, with_values: bool, secure: bool) -> String {
+        let values = self.values.read().expect("capability values poisoned");
+
+        Capabilities::NAMES
+            .iter()
+            .filter(|(_, cap)| caps.contains(*cap))
+            .map(|(name, cap)| match values.get(*cap, secure) {
+                Some(value) if with_values => format!("{name}={value}"),
+                _ => (*name).to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn lookup(name: &str) -> Result<Capabilities, ConfigError> {
+        Capabilities::from_cap_name(name).ok_or_else(|| ConfigError::UnknownCapability(name.to_owned()))
+    }
+}
+
+impl Capabilities {
+    /// Capabilities the server implements, and so offers unless they
+    /// are disabled. The rest are only reserved names for now.
+    pub const IMPLEMENTED: Capabilities = Capabilities::CapAccountNotify
+        .union(Capabilities::CapAccountTag)
+        .union(Capabilities::CapCapNotify)
+        .union(Capabilities::CapEchoMessage)
+        .union(Capabilities::CapSasl)
+        .union(Capabilities::CapStandardReplies)
+        .union(Capabilities::CapSts);
+
+    /// Look up a single capability by its name on the wire.
+    pub fn from_cap_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, cap)| *cap)
+    }
+
+    /// Iterate over the wire names of every capability in this set.
+    pub fn cap_names(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMES
+            .iter()
+            .filter(move |(_, cap)| self.contains(*cap))
+            .map(|(name, _)| *name)
+    }
+}
+
 caps![
     ("account-notify", CapAccountNotify),
     ("account-tag", CapAccountTag),
     ("away-notify", CapAwayNotify),
     ("batch", CapBatch),
+    ("cap-notify", CapCapNotify),
     ("channel-rename", CapChannelRename),
     ("chathistory", CapChatHistory),
     ("echo-message", CapEchoMessage),
@@ -59,6 +259,7 @@ caps![
     ("sasl", CapSasl),
     ("server-time", CapServerTime),
     ("standard-replies", CapStandardReplies),
+    ("sts", CapSts),
     ("userhost-in-names", CapUserhostInNames),
     ("rsr.chat/massive-message", CapRsrvcMassiveMessage),   // - Message body up to 2048 bytes from 512.
     ("rsr.chat/plc-oauthbearer", CapRsrvcPlcOauthbearer),   // - Extension to SASL OAUTHBEARER
@@ -0,0 +1,410 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use dashmap::{DashMap, mapref::entry::Entry};
+use tokio::sync::broadcast::{self, Sender, WeakSender};
+
+use crate::{
+    ext::StrExt,
+    irc::{ChannelName, ClientId},
+};
+
+/// Maximum channel name length advertised through `CHANNELLEN`.
+pub const CHANNELLEN: usize = 50;
+
+/// Maximum number of channels a client may join, advertised
+/// through `CHANLIMIT`.
+pub const CHANLIMIT: usize = 30;
+
+/// Maximum topic length advertised through `TOPICLEN`.
+pub const TOPICLEN: usize = 307;
+
+/// Channel modes advertised in `RPL_MYINFO`.
+pub const CHANNEL_MODES: &str = "iklo";
+
+/// Maximum channel key length, advertised through `KEYLEN`.
+pub const KEYLEN: usize = 23;
+
+/// Server-wide registry of every channel with at least one member.
+///
+/// Channels are keyed by their casefolded name. Each one owns only a
+/// [WeakSender] of its broadcast stream, while members keep the strong
+/// [Sender] alive for as long as they are joined, so a channel whose
+/// sender can no longer be upgraded is empty and can be dropped.
+pub struct Channels {
+    channels: DashMap<ChannelName, Channel>,
+    // Messages buffered for each new channel before slow members
+    // start lagging behind.
+    capacity: AtomicUsize,
+}
+
+struct Channel {
+    name: ChannelName,
+    tx: WeakSender<Arc<Bytes>>,
+    members: HashMap<ClientId, Member>,
+    topic: Option<Topic>,
+    // Unix time the channel was created at, for `RPL_CREATIONTIME`.
+    created: i64,
+
+    invite_only: bool,
+    key: Option<String>,
+    limit: Option<usize>,
+    invites: HashSet<ClientId>,
+}
+
+struct Member {
+    nick: String,
+    op: bool,
+}
+
+/// The topic of a channel along with who set it and when.
+#[derive(Debug, Clone)]
+pub struct Topic {
+    pub text: String,
+    pub set_by: String,
+    pub set_at: i64,
+}
+
+/// Everything a client needs after successfully joining a channel.
+pub struct Joined {
+    /// Casefolded name used as the subscription key.
+    pub key: ChannelName,
+    /// Name of the channel as it was created.
+    pub name: ChannelName,
+    pub tx: Sender<Arc<Bytes>>,
+    pub topic: Option<Topic>,
+}
+
+/// The modes of a channel, as shown in `RPL_CHANNELMODEIS`.
+#[derive(Debug, Clone)]
+pub struct Modes {
+    pub invite_only: bool,
+    pub key: Option<String>,
+    pub limit: Option<usize>,
+    pub created: i64,
+}
+
+impl Modes {
+    /// Render the modes as a mode string and its arguments. The key is
+    /// replaced by `*` unless `show_key` is set.
+    pub fn render(&self, show_key: bool) -> (String, Vec<String>) {
+        let mut modes = String::from("+");
+        let mut args = Vec::new();
+
+        if self.invite_only {
+            modes.push('i');
+        }
+        if let Some(key) = &self.key {
+            modes.push('k');
+            args.push(if show_key { key.clone() } else { "*".to_owned() });
+        }
+        if let Some(limit) = self.limit {
+            modes.push('l');
+            args.push(limit.to_string());
+        }
+
+        (modes, args)
+    }
+}
+
+/// A single change to the modes of a channel.
+#[derive(Debug)]
+pub enum ModeChange {
+    InviteOnly(bool),
+    Key(Option<String>),
+    Limit(Option<usize>),
+    /// Give or take channel operator status.
+    Op(ClientId, bool),
+}
+
+#[derive(Debug)]
+pub enum JoinError {
+    InviteOnly,
+    BadKey,
+    Full,
+}
+
+impl Channels {
+    /// An empty registry whose channels buffer `capacity` messages.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            channels: DashMap::new(),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    /// Buffer `capacity` messages for channels created from now on.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Number of live channels.
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Join `name` as `nick`, creating the channel if it does not exist
+    /// yet. The creator of a channel is given operator status.
+    pub fn join(&self, id: ClientId, nick: &str, name: &str, key: Option<&str>) -> Result<Joined, JoinError> {
+        let folded: ChannelName = name.irc_casefold().into();
+        let capacity = self.capacity.load(Ordering::Relaxed);
+
+        let (mut channel, tx) = match self.channels.entry(Arc::clone(&folded)) {
+            Entry::Occupied(mut occupied) => match occupied.get().tx.upgrade() {
+                Some(tx) => (occupied.into_ref(), tx),
+                // Every member is gone but the channel was never
+                // collected. Start over as if it never existed.
+                None => {
+                    let (channel, tx) = Channel::new(name, capacity);
+                    occupied.insert(channel);
+                    (occupied.into_ref(), tx)
+                }
+            },
+            Entry::Vacant(vacant) => {
+                let (channel, tx) = Channel::new(name, capacity);
+                (vacant.insert(channel), tx)
+            }
+        };
+
+        let creating = channel.members.is_empty();
+        if !creating {
+            channel.check_join(id, key)?;
+        }
+
+        channel.invites.remove(&id);
+        channel.members.insert(id, Member {
+            nick: nick.to_owned(),
+            op: creating,
+        });
+
+        Ok(Joined {
+            key: folded,
+            name: Arc::clone(&channel.name),
+            tx,
+            topic: channel.topic.clone(),
+        })
+    }
+
+    /// Remove a member from a channel. The member's [Sender] should be
+    /// dropped beforehand so the channel can be collected if it was the
+    /// last one. Returns the name of the channel as it was created, or
+    /// None if the client was not a member.
+    pub fn part(&self, id: ClientId, key: &str) -> Option<ChannelName> {
+        let name = {
+            let mut channel = self.channels.get_mut(key)?;
+            channel.members.remove(&id)?;
+            Arc::clone(&channel.name)
+        };
+
+        self.collect_garbage(key);
+        Some(name)
+    }
+
+    /// Remove someone else from a channel. Unlike [Channels::part] the
+    /// channel is not collected, as the kicked member still holds its
+    /// [Sender] until it has seen the KICK. Returns false if the client
+    /// was not a member.
+    pub fn kick(&self, key: &str, id: ClientId) -> bool {
+        self.channels
+            .get_mut(key)
+            .is_some_and(|mut channel| channel.members.remove(&id).is_some())
+    }
+
+    /// Drop the channel if it has no members left, or if its last
+    /// [Sender] has died without its members parting.
+    pub fn collect_garbage(&self, key: &str) {
+        self.channels.remove_if(key, |_, channel| {
+            channel.members.is_empty() || channel.tx.upgrade().is_none()
+        });
+    }
+
+    /// The channel's name as created and the prefixed nicks of every
+    /// member, as listed in `RPL_NAMREPLY`.
+    pub fn names(&self, key: &str) -> Option<(ChannelName, Vec<String>)> {
+        let (name, roster) = self.roster(key)?;
+
+        let names = roster
+            .into_iter()
+            .map(|(nick, op)| match op {
+                true => format!("@{nick}"),
+                false => nick,
+            })
+            .collect();
+
+        Some((name, names))
+    }
+
+    /// The channel's name as created and the nick of every member,
+    /// along with whether it is a channel operator.
+    pub fn roster(&self, key: &str) -> Option<(ChannelName, Vec<(String, bool)>)> {
+        let channel = self.channels.get(key)?;
+        let roster = channel.members.values().map(|m| (m.nick.clone(), m.op)).collect();
+        Some((Arc::clone(&channel.name), roster))
+    }
+
+    /// The name as created, member count and topic text of every
+    /// channel, sorted by name, as listed in `RPL_LIST`.
+    pub fn list(&self) -> Vec<(ChannelName, usize, String)> {
+        let mut list: Vec<_> = self
+            .channels
+            .iter()
+            .map(|channel| {
+                let topic = channel.topic.as_ref().map(|topic| topic.text.clone()).unwrap_or_default();
+                (Arc::clone(&channel.name), channel.members.len(), topic)
+            })
+            .collect();
+
+        list.sort_by(|a, b| a.0.cmp(&b.0));
+        list
+    }
+
+    /// The channel's name as created and its topic.
+    pub fn topic(&self, key: &str) -> Option<(ChannelName, Option<Topic>)> {
+        let channel = self.channels.get(key)?;
+        Some((Arc::clone(&channel.name), channel.topic.clone()))
+    }
+
+    /// Replace the topic of the channel, or clear it. Returns false if
+    /// the channel is gone.
+    pub fn set_topic(&self, key: &str, topic: Option<Topic>) -> bool {
+        let Some(mut channel) = self.channels.get_mut(key) else {
+            return false;
+        };
+
+        channel.topic = topic;
+        true
+    }
+
+    /// The channel's name as created and its modes.
+    pub fn modes(&self, key: &str) -> Option<(ChannelName, Modes)> {
+        let channel = self.channels.get(key)?;
+
+        let modes = Modes {
+            invite_only: channel.invite_only,
+            key: channel.key.clone(),
+            limit: channel.limit,
+            created: channel.created,
+        };
+
+        Some((Arc::clone(&channel.name), modes))
+    }
+
+    /// Apply `change` to the channel. Returns false if it changed
+    /// nothing, or if the channel or the member it is about is gone.
+    pub fn change_mode(&self, key: &str, change: ModeChange) -> bool {
+        let Some(mut channel) = self.channels.get_mut(key) else {
+            return false;
+        };
+
+        match change {
+            ModeChange::InviteOnly(on) => {
+                let changed = channel.invite_only != on;
+                channel.invite_only = on;
+                if !on {
+                    channel.invites.clear();
+                }
+                changed
+            }
+            ModeChange::Key(new) => new != std::mem::replace(&mut channel.key, new.clone()),
+            ModeChange::Limit(new) => new != std::mem::replace(&mut channel.limit, new),
+            ModeChange::Op(id, op) => match channel.members.get_mut(&id) {
+                Some(member) => op != std::mem::replace(&mut member.op, op),
+                None => false,
+            },
+        }
+    }
+
+    /// Let the client join the channel once even while it is invite
+    /// only. Returns false if the channel is gone.
+    pub fn invite(&self, key: &str, id: ClientId) -> bool {
+        let Some(mut channel) = self.channels.get_mut(key) else {
+            return false;
+        };
+
+        channel.invites.insert(id);
+        true
+    }
+
+    /// Whether the client is a member of the channel.
+    pub fn is_member(&self, id: ClientId, key: &str) -> bool {
+        self.channels.get(key).is_some_and(|channel| channel.members.contains_key(&id))
+    }
+
+    /// Whether the client is an operator of the channel.
+    pub fn is_op(&self, id: ClientId, key: &str) -> bool {
+        self.channels
+            .get(key)
+            .and_then(|channel| channel.members.get(&id).map(|member| member.op))
+            .unwrap_or(false)
+    }
+
+    /// Whether the channel exists.
+    pub fn exists(&self, key: &str) -> bool {
+        self.channels.contains_key(key)
+    }
+
+    /// Identifiers of every member of the channel.
+    pub fn members(&self, key: &str) -> Vec<ClientId> {
+        self.channels
+            .get(key)
+            .map(|channel| channel.members.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Track a member's nick change.
+    pub fn rename_member(&self, id: ClientId, key: &str, nick: &str) {
+        if let Some(mut channel) = self.channels.get_mut(key)
+            && let Some(member) = channel.members.get_mut(&id)
+        {
+            member.nick = nick.to_owned();
+        }
+    }
+}
+
+impl Channel {
+    /// Create an empty channel. The registry only holds on to the weak
+    /// half of the broadcast stream, so the strong [Sender] is returned
+    /// to be handed to the first member.
+    fn new(name: &str, capacity: usize) -> (Self, Sender<Arc<Bytes>>) {
+        let (tx, _) = broadcast::channel(capacity);
+
+        let channel = Self {
+            name: name.into(),
+            tx: tx.downgrade(),
+            members: HashMap::new(),
+            topic: None,
+            created: Utc::now().timestamp(),
+            invite_only: false,
+            key: None,
+            limit: None,
+            invites: HashSet::new(),
+        };
+
+        (channel, tx)
+    }
+
+    fn check_join(&self, id: ClientId, key: Option<&str>) -> Result<(), JoinError> {
+        if self.invite_only && !self.invites.contains(&id) {
+            return Err(JoinError::InviteOnly);
+        }
+
+        if let Some(expected) = &self.key
+            && key != Some(expected.as_str())
+        {
+            return Err(JoinError::BadKey);
+        }
+
+        if self.limit.is_some_and(|limit| self.members.len() >= limit) {
+            return Err(JoinError::Full);
+        }
+
+        Ok(())
+    }
+}
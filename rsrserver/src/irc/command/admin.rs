@@ -2,8 +2,7 @@ use ircv3_parse::Message;
 
 use crate::{
     error::IrcResult,
-    ext::StrExt,
-    irc::{GenericStateExt, IrcContext, command::CommandHandler, state},
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
     storage::Storage,
 };
 
@@ -47,19 +46,14 @@ impl CommandHandler<state::Authenticated> for Admin {
 
 impl Admin {
     async fn handle_inner<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40).to_owned();
+        let config = ctx.network().config();
+        let admin = &config.admin;
 
-        ctx.send_client_unchecked(&format!(":* 256 {nick} :%INFOHEADER%\r\n"))
-            .await?;
-        ctx.send_client_unchecked(&format!(":* 257 {nick} :%ADMINLOC%\r\n"))
-            .await?;
-        ctx.send_client_unchecked(&format!(":* 258 {nick} :%ADMINHOST%\r\n"))
-            .await?;
-        ctx.send_client_unchecked(&format!(":* 257 {nick} :%ADMINEMAIL%\r\n"))
-            .await?;
+        ctx.reply(Reply::AdminMe).await?;
+        ctx.reply(Reply::AdminLoc1 { info: &admin.location }).await?;
+        ctx.reply(Reply::AdminLoc2 { info: &admin.institution }).await?;
+        ctx.reply(Reply::AdminEmail { info: &admin.email }).await?;
 
         Ok(())
     }
 }
-
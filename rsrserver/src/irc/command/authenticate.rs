@@ -1,30 +1,57 @@
+use chrono::Utc;
 use ircv3_parse::Message;
+use tokio::time::Instant;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    did::Grant,
+    error::{IrcResult, TokenError},
+    irc::{
+        Capabilities, GenericStateExt, IrcContext, Relay, Reply, SaslExchange,
+        command::CommandHandler,
+        sasl::{self, Mechanism, OAuthBearer, Plain, Step},
+        state::{self, MaybeTransition, New, Old},
+    },
+    storage::{Account, Storage},
+};
 
 pub struct Authenticate;
 
+/// What an OAUTHBEARER login may do in the current state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grants {
+    /// The client has not registered yet, so it can not take on a grant.
+    Refused,
+    /// The client may take on a grant.
+    Take,
+    /// The client holds a grant and may renew it for the same DID.
+    Refresh,
+}
+
 impl CommandHandler<state::Anonymous> for Authenticate {
     type Contract = state::Anonymous;
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Anonymous, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
+        Self::handle_inner(&mut ctx, &msg, Grants::Refused).await?;
         Ok(ctx)
     }
 }
 
 impl CommandHandler<state::Registered> for Authenticate {
-    type Contract = state::Registered;
+    type Contract = MaybeTransition<state::Registered, state::Authenticated>;
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
-        Ok(ctx)
+        let Some(grant) = Self::handle_inner(&mut ctx, &msg, Grants::Take).await? else {
+            return Ok(Self::Contract::from(Old(ctx)));
+        };
+
+        let expires = Self::expires(&ctx, &grant);
+        Ok(New(ctx.transition_with(|registered| registered.authenticate(expires))).into())
     }
 }
 
@@ -33,9 +60,190 @@ impl CommandHandler<state::Authenticated> for Authenticate {
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
+        if let Some(grant) = Self::handle_inner(&mut ctx, &msg, Grants::Refresh).await? {
+            ctx.expires = Self::expires(&ctx, &grant);
+            ctx.warned = false;
+        }
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Authenticate {
+    /// Run one step of an exchange. Returns the PDS grant once an
+    /// OAUTHBEARER login succeeds.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+        grants: Grants,
+    ) -> IrcResult<Option<Grant>> {
+        let Some(param) = msg.params().middles.first() else {
+            ctx.need_more_params("AUTHENTICATE").await?;
+            return Ok(None);
+        };
+
+        if !ctx.session().caps().contains(Capabilities::CapSasl) {
+            ctx.reply(Reply::SaslFail).await?;
+            return Ok(None);
+        }
+
+        let offered = sasl::offered(&ctx.network().config().caps, ctx.network().resolver().is_some());
+        let oauthbearer =
+            grants != Grants::Refused && ctx.session().caps().contains(Capabilities::CapRsrvcPlcOauthbearer);
+
+        // The first AUTHENTICATE of an exchange names the mechanism,
+        // every following one carries a chunk of the payload.
+        let Some(exchange) = ctx.session_mut().sasl_mut() else {
+            let Some(mechanism) = Mechanism::from_name(param)
+                .filter(|mechanism| offered.contains(mechanism))
+                .filter(|mechanism| *mechanism != Mechanism::OAuthBearer || oauthbearer)
+            else {
+                let mechanisms = offered.iter().map(|mechanism| mechanism.name()).collect::<Vec<_>>().join(",");
+                ctx.reply(Reply::SaslMechs { mechanisms: &mechanisms }).await?;
+                ctx.reply(Reply::SaslFail).await?;
+                return Ok(None);
+            };
+
+            // Only a grant can be renewed while logged in.
+            let refresh = grants == Grants::Refresh && mechanism == Mechanism::OAuthBearer;
+            if ctx.session().account().is_some() && !refresh {
+                ctx.reply(Reply::SaslAlready).await?;
+                return Ok(None);
+            }
+
+            *ctx.session_mut().sasl_mut() = Some(SaslExchange::new(mechanism));
+            ctx.reply(Reply::Authenticate { payload: "+" }).await?;
+            return Ok(None);
+        };
+
+        if param == "*" {
+            *ctx.session_mut().sasl_mut() = None;
+            ctx.reply(Reply::SaslAborted).await?;
+            return Ok(None);
+        }
+
+        let mechanism = exchange.mechanism();
+        let payload = match exchange.push(param) {
+            Step::More => return Ok(None),
+            Step::Done(payload) => payload,
+            Step::TooLong => {
+                *ctx.session_mut().sasl_mut() = None;
+                ctx.reply(Reply::SaslTooLong).await?;
+                return Ok(None);
+            }
+            Step::Invalid => {
+                *ctx.session_mut().sasl_mut() = None;
+                ctx.reply(Reply::SaslFail).await?;
+                return Ok(None);
+            }
+        };
+
+        *ctx.session_mut().sasl_mut() = None;
+
+        let (account, grant) = match mechanism {
+            Mechanism::Plain => (Self::plain(ctx, &payload).await, None),
+            Mechanism::External => (Self::external(ctx).await, None),
+            Mechanism::OAuthBearer => match Self::oauthbearer(ctx, &payload).await {
+                Some(grant) => (Some(Account { name: grant.did.clone() }), Some(grant)),
+                None => (None, None),
+            },
+        };
+
+        // A renewed grant must be for the DID that is already logged in.
+        let current = ctx.session().account();
+        let Some(account) = account.filter(|account| current.is_none_or(|current| current == account.name))
+        else {
+            ctx.reply(Reply::SaslFail).await?;
+            return Ok(None);
+        };
+
+        if let Some(ban) = ctx.network().bans().find_account(&account.name) {
+            return ctx.refuse_banned(&ban).await.map(|()| None);
+        }
+
+        let source = ctx.source();
+
+        if current.is_none() {
+            tracing::info!(account = account.name, source, ?mechanism, "SASL login");
+            ctx.session_mut().set_account(&account.name);
+            ctx.send_peers(Relay::Account { account: Some(&account.name) }.render(&source));
+        }
+
+        ctx.reply(Reply::LoggedIn { source: &source, account: &account.name }).await?;
+        ctx.reply(Reply::SaslSuccess).await?;
+
+        Ok(grant)
+    }
+
+    /// The grant's expiry on the monotonic clock the connection loop
+    /// runs on, held to the longest grant the server honours.
+    fn expires<'a, T, S>(ctx: &IrcContext<'a, T, S>, grant: &Grant) -> Instant {
+        let remaining = (grant.expires - Utc::now()).to_std().unwrap_or_default();
+        Instant::now() + remaining.min(ctx.network().config().limits.max_grant())
+    }
+
+    /// Check the credentials of a PLAIN exchange. Logging in as
+    /// another account than the one whose password was given is not
+    /// supported.
+    async fn plain<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        payload: &[u8],
+    ) -> Option<Account> {
+        let plain = Plain::parse(payload)?;
+
+        if !plain.authzid.is_empty() && plain.authzid != plain.authcid {
+            return None;
+        }
+
+        match ctx.storage().check_password(plain.authcid, plain.password).await {
+            Ok(account) => account,
+            Err(_) => {
+                tracing::warn!("storage backend failed to check a SASL PLAIN password");
+                None
+            }
+        }
+    }
+
+    /// Log in as the account the client certificate is registered to.
+    /// The payload carries an optional authzid, which is ignored.
+    async fn external<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+    ) -> Option<Account> {
+        let certfp = ctx.session().certfp()?;
+
+        match ctx.storage().account_by_certfp(certfp).await {
+            Ok(account) => account,
+            Err(_) => {
+                tracing::warn!("storage backend failed to look up a certificate fingerprint");
+                None
+            }
+        }
+    }
+
+    /// Verify a PDS token, logging in as the DID that signed it.
+    async fn oauthbearer<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        payload: &[u8],
+    ) -> Option<Grant> {
+        let bearer = OAuthBearer::parse(payload)?;
+        let audience = ctx.network().config().audience();
+
+        let verified = match ctx.network().resolver() {
+            Some(resolver) => Grant::verify(bearer.token, &audience, resolver).await,
+            None => Err(TokenError::NoResolver),
+        };
+
+        match verified {
+            Ok(grant) if bearer.authzid.is_none_or(|authzid| authzid == grant.did) => {
+                tracing::info!(source = ctx.source(), did = grant.did, handle = grant.handle.as_deref(), "OAUTHBEARER");
+                Some(grant)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                tracing::info!(source = ctx.source(), "OAUTHBEARER token rejected: {e}");
+                None
+            }
+        }
+    }
+}
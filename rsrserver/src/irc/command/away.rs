@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, network::AWAYLEN, state},
+    storage::Storage,
+};
 
 pub struct Away;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Away {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        ctx.away = Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,34 @@ impl CommandHandler<state::Authenticated> for Away {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        ctx.away = Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Away {
+    /// `AWAY [<text>]`. Without a message the client is no longer away.
+    /// Returns the new away message, to be kept in the typestate.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<Option<String>> {
+        let params = msg.params();
+        let text = params.trailing.raw().or(params.middles.first()).filter(|t| !t.is_empty());
+        let away = text.map(|text| text.slice_at_most(AWAYLEN).to_owned());
+
+        if ctx.away() != away.as_deref() {
+            ctx.session_mut().set_away(away.as_deref());
+        }
+
+        match away {
+            Some(_) => ctx.reply(Reply::NowAway).await?,
+            None => ctx.reply(Reply::UnAway).await?,
+        }
+
+        Ok(away)
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::Utc;
+use ircv3_parse::Message;
+
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, ServerMessage, ban::parse_duration, command::CommandHandler, state},
+    storage::{self, BanKind, Storage},
+};
+
+pub struct Ban;
+
+impl CommandHandler<state::Anonymous> for Ban {
+    type Contract = state::Anonymous;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        _msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        ctx.registration_required().await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Registered> for Ban {
+    type Contract = state::Registered;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        Self::handle_inner(&mut ctx, &msg).await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Authenticated> for Ban {
+    type Contract = state::Authenticated;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        Self::handle_inner(&mut ctx, &msg).await?;
+        Ok(ctx)
+    }
+}
+
+impl Ban {
+    /// `BAN <type> <mask> [<duration>] [:<reason>]`, where the type is
+    /// one of `HOST`, `IP`, `ACCOUNT`, `DID` or `CERTFP`. Without a
+    /// duration, the ban is permanent.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+        let mut args = params.middles.iter().chain(params.trailing.raw());
+
+        let (Some(kind), Some(mask)) = (args.next(), args.next()) else {
+            return ctx.need_more_params("BAN").await;
+        };
+
+        if !ctx.has_privilege(Privilege::Ban) {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        let Some(kind) = BanKind::from_name(kind) else {
+            let text = format!("Unknown ban type {kind}");
+            return ctx.fail("BAN", "INVALID_PARAMS", &text).await;
+        };
+
+        let mask = match kind.normalize(mask) {
+            Ok(mask) => mask,
+            Err(e) => return ctx.fail("BAN", "INVALID_PARAMS", &e).await,
+        };
+
+        // The duration may be left out, leaving just the reason.
+        let mut next = args.next();
+        let duration = match next.and_then(parse_duration) {
+            Some(duration) => {
+                next = args.next();
+                duration
+            }
+            None => None,
+        };
+        let reason = next.filter(|reason| !reason.is_empty()).unwrap_or("No reason given");
+
+        let now = Utc::now();
+        let ban = storage::Ban {
+            kind,
+            mask,
+            reason: reason.to_owned(),
+            set_by: ctx.source(),
+            set_at: now,
+            expires: duration.map(|duration| now + duration),
+        };
+
+        if ctx.storage().add_ban(&ban).await.is_err() {
+            tracing::warn!("storage backend failed to store a ban");
+            return ctx.fail("BAN", "TEMPORARILY_UNAVAILABLE", "The ban could not be saved").await;
+        }
+
+        tracing::info!(source = ban.set_by, kind = kind.name(), mask = ban.mask, reason, "BAN");
+        let text = match ban.expires {
+            Some(expires) => format!("Added {} ban on {} until {}: {reason}", kind.name(), ban.mask, expires.to_rfc2822()),
+            None => format!("Added permanent {} ban on {}: {reason}", kind.name(), ban.mask),
+        };
+
+        ctx.network().bans().add(ban);
+        ctx.reply(Reply::Notice { text: &text }).await?;
+        ctx.broadcast_server(ServerMessage::BanAdded);
+
+        Ok(())
+    }
+}
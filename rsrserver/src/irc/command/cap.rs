@@ -1,18 +1,51 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        Capabilities, GenericStateExt, IrcContext, Reply,
+        command::CommandHandler,
+        state::{self, MaybeTransition, Old},
+    },
+    storage::Storage,
+};
+
+/// Longest REQ list accepted, leaving room for the ACK around it.
+const MAX_REQ: usize = 400;
 
 pub struct Cap;
 
+/// The CAP subcommand that was processed, so that each state can
+/// decide what it means for registration.
+enum Subcommand {
+    Ls,
+    List,
+    Req,
+    End,
+    Invalid,
+}
+
 impl CommandHandler<state::Anonymous> for Cap {
-    type Contract = state::Anonymous;
+    type Contract = MaybeTransition<state::Anonymous, state::Registered>;
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Anonymous, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
-        Ok(ctx)
+        match Self::negotiate(&mut ctx, &msg).await? {
+            // LS and REQ hold registration open until the client
+            // is done negotiating and sends CAP END.
+            Subcommand::Ls | Subcommand::Req => {
+                ctx.cap_pending = true;
+                Ok(Old(ctx).into())
+            }
+            Subcommand::End => {
+                ctx.cap_pending = false;
+                ctx.try_register().await
+            }
+            Subcommand::List | Subcommand::Invalid => Ok(Old(ctx).into()),
+        }
     }
 }
 
@@ -20,10 +53,11 @@ impl CommandHandler<state::Registered> for Cap {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        // CAP END outside of registration is harmless and ignored.
+        Self::negotiate(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +66,216 @@ impl CommandHandler<state::Authenticated> for Cap {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::negotiate(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Cap {
+    async fn negotiate<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<Subcommand> {
+        let params = msg.params();
+
+        let Some(sub) = params.middles.first() else {
+            ctx.need_more_params("CAP").await?;
+            return Ok(Subcommand::Invalid);
+        };
+
+        // The argument may be sent as either a middle or a trailing parameter.
+        let arg = params.middles.second().or(params.trailing.raw());
+
+        match sub.to_ascii_uppercase().as_str() {
+            "LS" => {
+                Self::ls(ctx, arg).await?;
+                Ok(Subcommand::Ls)
+            }
+            "LIST" => {
+                Self::list(ctx).await?;
+                Ok(Subcommand::List)
+            }
+            "REQ" => {
+                Self::req(ctx, arg.unwrap_or("")).await?;
+                Ok(Subcommand::Req)
+            }
+            "END" => Ok(Subcommand::End),
+            _ => {
+                let subcommand = sub.slice_at_most(32);
+                ctx.reply(Reply::InvalidCapCmd { subcommand }).await?;
+                Ok(Subcommand::Invalid)
+            }
+        }
+    }
+
+    async fn ls<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        version: Option<&str>,
+    ) -> IrcResult<()> {
+        if let Some(version) = version {
+            // Unparseable versions are treated as the base version.
+            let version = version.parse::<u16>().unwrap_or(0);
+
+            // CAP LS 302 implicitly enables cap-notify.
+            if ctx.session_mut().set_caps_version(version) >= 302 {
+                ctx.session_mut().caps_mut().insert(Capabilities::CapCapNotify);
+            }
+        }
+
+        let advertised = ctx.network().caps().advertised();
+        let list = Self::render(ctx, advertised);
+        Self::send_chunked(ctx, "LS", &list).await
+    }
+
+    async fn list<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
+        let list = ctx.session().caps().cap_names().collect::<Vec<_>>().join(" ");
+        Self::send_chunked(ctx, "LIST", &list).await
+    }
+
+    /// Enable or disable all requested capabilities, or none of them
+    /// if any single capability in the request is not available.
+    async fn req<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        list: &str,
+    ) -> IrcResult<()> {
+        let list = list.trim();
+
+        // `sts` only informs the client, it can not be enabled.
+        let requestable = ctx.network().caps().advertised().difference(Capabilities::CapSts);
+        let mut enable = Capabilities::empty();
+        let mut disable = Capabilities::empty();
+        // The ACK echoes the list, so one too long to fit is refused whole.
+        let mut valid = !list.is_empty() && list.len() <= MAX_REQ;
+
+        for token in list.split_ascii_whitespace() {
+            let (negate, name) = match token.strip_prefix('-') {
+                Some(name) => (true, name),
+                None => (false, token),
+            };
+
+            match Capabilities::from_cap_name(name).filter(|cap| requestable.contains(*cap)) {
+                Some(cap) if negate => disable.insert(cap),
+                Some(cap) => enable.insert(cap),
+                None => valid = false,
+            }
+        }
+
+        if !valid {
+            return ctx
+                .reply(Reply::Cap { subcommand: "NAK", more: false, list })
+                .await;
+        }
+
+        let caps = ctx.session_mut().caps_mut();
+        caps.insert(enable);
+        caps.remove(disable);
+
+        ctx.reply(Reply::Cap { subcommand: "ACK", more: false, list }).await
+    }
+
+    /// Tell `cap-notify` clients about newly advertised capabilities.
+    pub async fn notify_new<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        caps: Capabilities,
+    ) -> IrcResult<()> {
+        if !ctx.session().caps().contains(Capabilities::CapCapNotify) {
+            return Ok(());
+        }
+
+        let list = Self::render(ctx, caps);
+        Self::send_chunked(ctx, "NEW", &list).await
+    }
+
+    /// Tell `cap-notify` clients about capabilities that are no longer
+    /// advertised. They are disabled on the session regardless.
+    pub async fn notify_del<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        caps: Capabilities,
+    ) -> IrcResult<()> {
+        ctx.session_mut().caps_mut().remove(caps);
+
+        if !ctx.session().caps().contains(Capabilities::CapCapNotify) {
+            return Ok(());
+        }
+
+        let list = caps.cap_names().collect::<Vec<_>>().join(" ");
+        Self::send_chunked(ctx, "DEL", &list).await
+    }
+
+    /// Render a space separated capability list, including the
+    /// capability values for clients that negotiated CAP 302.
+    fn render<'a, T, S>(ctx: &IrcContext<'a, T, S>, caps: Capabilities) -> String {
+        let with_values = ctx.session().caps_version() >= 302;
+        ctx.network().caps().render(caps, with_values, ctx.session().secure())
+    }
+
+    /// Send a capability list, split over as many lines as needed. CAP 302
+    /// clients are told more lines follow with a `*` continuation marker.
+    async fn send_chunked<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        sub: &str,
+        list: &str,
+    ) -> IrcResult<()> {
+        let multiline = ctx.session().caps_version() >= 302;
+
+        // Leave room for the prefix, the continuation marker and CRLF.
+        let overhead = Reply::Cap { subcommand: sub, more: true, list: "" }
+            .render(ctx.network().name(), ctx.nick())
+            .len();
+        let budget = 512usize.saturating_sub(overhead);
+
+        let mut chunks = chunk_by_whitespace(list, budget).peekable();
+
+        // Always send at least one line, even for an empty list.
+        if chunks.peek().is_none() {
+            return ctx.reply(Reply::Cap { subcommand: sub, more: false, list: "" }).await;
+        }
+
+        while let Some(list) = chunks.next() {
+            let more = multiline && chunks.peek().is_some();
+            ctx.reply(Reply::Cap { subcommand: sub, more, list }).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn chunk_by_whitespace(text: &str, max_bytes: usize) -> impl Iterator<Item = &str> + '_ {
+    let mut pos = 0;
+
+    std::iter::from_fn(move || {
+        // Skip whitespace
+        pos += text[pos..].chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| c.len_utf8())
+            .sum::<usize>();
+
+        if pos >= text.len() {
+            return None;
+        }
+
+        let rest = &text[pos..];
+
+        // If it all fits, we're done
+        if rest.len() <= max_bytes {
+            pos = text.len();
+            return Some(rest);
+        }
+
+        // Find last whitespace within limit, or first whitespace after
+        let split_at = rest.char_indices()
+            .take_while(|(i, c)| i + c.len_utf8() <= max_bytes)
+            .filter(|(_, c)| c.is_whitespace())
+            .last()
+            .map(|(i, _)| i)
+            .or_else(|| rest.char_indices().find(|(_, c)| c.is_whitespace()).map(|(i, _)| i))
+            .unwrap_or(rest.len());
+
+        let chunk = &rest[..split_at];
+        pos += split_at;
+        Some(chunk)
+    })
+}
@@ -1,6 +1,10 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Connect;
 
@@ -20,10 +24,10 @@ impl CommandHandler<state::Registered> for Connect {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +36,29 @@ impl CommandHandler<state::Authenticated> for Connect {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Connect {
+    /// `CONNECT <server>`. This server does not link to others, so
+    /// there is no server an oper could connect it to.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let Some(server) = msg.params().middles.first() else {
+            return ctx.need_more_params("CONNECT").await;
+        };
+
+        if ctx.session().oper().is_none() {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        ctx.reply(Reply::NoSuchServer { server }).await
+    }
+}
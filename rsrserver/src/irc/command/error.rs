@@ -8,7 +8,7 @@ impl CommandHandler<state::Anonymous> for Error {
     type Contract = state::Anonymous;
 
     async fn handle<'a, S: Storage>(
-        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        ctx: IrcContext<'a, state::Anonymous, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
         // Clients should never send this message.
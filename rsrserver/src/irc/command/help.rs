@@ -1,6 +1,6 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, ext::StrExt, irc::{GenericStateExt, IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Help;
 
@@ -23,9 +23,7 @@ impl CommandHandler<state::Registered> for Help {
         mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 524 {nick} * :Not yet implemented\r\n")).await?;
+        ctx.reply(Reply::HelpNotFound { subject: "*" }).await?;
         Ok(ctx)
     }
 }
@@ -37,9 +35,7 @@ impl CommandHandler<state::Authenticated> for Help {
         mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 524 {nick} * :Not yet implemented\r\n")).await?;
+        ctx.reply(Reply::HelpNotFound { subject: "*" }).await?;
         Ok(ctx)
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, ext::StrExt, irc::{GenericStateExt, IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Info;
 
@@ -23,9 +23,7 @@ impl CommandHandler<state::Registered> for Info {
         mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 374 {nick}  :End of INFO list\r\n")).await?;
+        ctx.reply(Reply::EndOfInfo).await?;
         Ok(ctx)
     }
 }
@@ -37,9 +35,7 @@ impl CommandHandler<state::Authenticated> for Info {
         mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 374 {nick}  :End of INFO list\r\n")).await?;
+        ctx.reply(Reply::EndOfInfo).await?;
         Ok(ctx)
     }
 }
\ No newline at end of file
@@ -1,6 +1,14 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Relay, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Invite;
 
@@ -20,10 +28,10 @@ impl CommandHandler<state::Registered> for Invite {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +40,55 @@ impl CommandHandler<state::Authenticated> for Invite {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Invite {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let (Some(nick), Some(target)) = (params.middles.first(), params.middles.second().or(params.trailing.raw()))
+        else {
+            return ctx.need_more_params("INVITE").await;
+        };
+
+        let Some(id) = ctx.network().lookup(nick) else {
+            return ctx.reply(Reply::NoSuchNick { target: nick }).await;
+        };
+
+        let key = target.irc_casefold();
+        let Some((channel, modes)) = ctx.network().channels().modes(&key) else {
+            return ctx.reply(Reply::NoSuchChannel { channel: target }).await;
+        };
+
+        if !ctx.is_subscribed(&key) {
+            return ctx.reply(Reply::NotOnChannel { channel: &channel }).await;
+        }
+
+        // Anyone may invite to an open channel, but only operators can
+        // let others past +i.
+        if modes.invite_only && !ctx.network().channels().is_op(ctx.session().id(), &key) {
+            return ctx.reply(Reply::ChanOpPrivsNeeded { channel: &channel }).await;
+        }
+
+        if ctx.network().channels().is_member(id, &key) {
+            return ctx.reply(Reply::UserOnChannel { nick, channel: &channel }).await;
+        }
+
+        ctx.network().channels().invite(&key, id);
+        ctx.reply(Reply::Inviting { nick, channel: &channel }).await?;
+
+        let line = Relay::Invite { target: nick, channel: &channel }.render(&ctx.source());
+        ctx.network().send_direct(id, Arc::new(Bytes::from(line)));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,18 @@
+use std::sync::Arc;
+
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        GenericStateExt, IrcContext, JoinError, Relay, Reply,
+        channel::{CHANLIMIT, CHANNELLEN},
+        command::{CommandHandler, Names, Part, Topic},
+        state,
+    },
+    storage::Storage,
+};
 
 pub struct Join;
 
@@ -20,10 +32,10 @@ impl CommandHandler<state::Registered> for Join {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +44,94 @@ impl CommandHandler<state::Authenticated> for Join {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Join {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(targets) = params.middles.first().filter(|t| !t.is_empty()) else {
+            ctx.need_more_params("JOIN").await?;
+            return Ok(());
+        };
+
+        // JOIN 0 leaves every channel.
+        if targets == "0" {
+            return Part::part_all(ctx, None).await;
+        }
+
+        let mut keys = params.middles.second().unwrap_or("").split(',');
+        for name in targets.split(',') {
+            let key = keys.next().filter(|k| !k.is_empty());
+            Self::join_one(ctx, name, key).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn join_one<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        name: &str,
+        key: Option<&str>,
+    ) -> IrcResult<()> {
+        let nick = ctx.nick().to_owned();
+        let name = name.slice_at_most(CHANNELLEN + 1);
+
+        if !Self::valid_name(name) {
+            return ctx.reply(Reply::NoSuchChannel { channel: name }).await;
+        }
+
+        if ctx.is_subscribed(&name.irc_casefold()) {
+            return Ok(());
+        }
+
+        if ctx.subscriptions().len() >= CHANLIMIT {
+            return ctx.reply(Reply::TooManyChannels { channel: name }).await;
+        }
+
+        let id = ctx.session().id();
+        let joined = match ctx.network().channels().join(id, &nick, name, key) {
+            Ok(joined) => joined,
+            Err(e) => {
+                let reply = match e {
+                    JoinError::Full => Reply::ChannelIsFull { channel: name },
+                    JoinError::InviteOnly => Reply::InviteOnlyChan { channel: name },
+                    JoinError::BadKey => Reply::BadChannelKey { channel: name },
+                };
+
+                return ctx.reply(reply).await;
+            }
+        };
+
+        let line = Relay::Join { channel: &joined.name }.render(&ctx.source());
+
+        // Announce the join to the existing members before subscribing,
+        // so that our own copy of the JOIN can be sent directly ahead
+        // of the topic and names burst instead of arriving after it.
+        let _ = joined.tx.send(Arc::new(line.clone().into()));
+        ctx.subscribe(Arc::clone(&joined.key), joined.tx);
+        ctx.send_client_unchecked(&line).await?;
+
+        if let Some(topic) = joined.topic {
+            Topic::send_topic(ctx, &joined.name, &topic).await?;
+        }
+
+        Names::send_names(ctx, &joined.key).await
+    }
+
+    fn valid_name(name: &str) -> bool {
+        name.len() > 1
+            && name.len() <= CHANNELLEN
+            && name.starts_with('#')
+            && !name.contains([' ', ',', '\x07'])
+    }
+}
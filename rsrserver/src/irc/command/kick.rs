@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Relay, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Kick;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Kick {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,53 @@ impl CommandHandler<state::Authenticated> for Kick {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Kick {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let (Some(target), Some(nicks)) = (params.middles.first(), params.middles.second()) else {
+            return ctx.need_more_params("KICK").await;
+        };
+
+        let key = target.irc_casefold();
+        let Some((channel, _)) = ctx.network().channels().modes(&key) else {
+            return ctx.reply(Reply::NoSuchChannel { channel: target }).await;
+        };
+
+        if !ctx.is_subscribed(&key) {
+            return ctx.reply(Reply::NotOnChannel { channel: &channel }).await;
+        }
+
+        if !ctx.network().channels().is_op(ctx.session().id(), &key) {
+            return ctx.reply(Reply::ChanOpPrivsNeeded { channel: &channel }).await;
+        }
+
+        let kicker = ctx.nick().to_owned();
+        let reason = params.trailing.raw().unwrap_or(&kicker);
+
+        for nick in nicks.split(',') {
+            let kicked = ctx.network().lookup(nick).is_some_and(|id| ctx.network().channels().kick(&key, id));
+            if !kicked {
+                ctx.reply(Reply::UserNotInChannel { nick, channel: &channel }).await?;
+                continue;
+            }
+
+            // The kicked member leaves the channel once it sees this.
+            let line = Relay::Kick { channel: &channel, target: nick, reason }.render(&ctx.source());
+            ctx.send_channel(&key, line);
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    irc::{Control, GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Kill;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Kill {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,46 @@ impl CommandHandler<state::Authenticated> for Kill {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Kill {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let (Some(nick), Some(reason)) = (
+            params.middles.first(),
+            params.trailing.raw().or(params.middles.second()),
+        ) else {
+            return ctx.need_more_params("KILL").await;
+        };
+
+        if !ctx.has_privilege(Privilege::Kill) {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        let Some(target) = ctx.network().lookup(nick) else {
+            return ctx.reply(Reply::NoSuchNick { target: nick }).await;
+        };
+
+        tracing::info!(source = ctx.source(), target = nick, reason, "KILL");
+        let order = Control::Kill {
+            source: ctx.source().into(),
+            reason: reason.into(),
+        };
+
+        match ctx.network().control(target, order) {
+            true => Ok(()),
+            // Gone between the lookup and now.
+            false => ctx.reply(Reply::NoSuchNick { target: nick }).await,
+        }
+    }
+}
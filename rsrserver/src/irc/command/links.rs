@@ -1,6 +1,6 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, ext::StrExt, irc::{GenericStateExt, IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Links;
 
@@ -23,9 +23,7 @@ impl CommandHandler<state::Registered> for Links {
         mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 365 {nick}  :End of /LINKS list\r\n")).await?;
+        ctx.reply(Reply::EndOfLinks { mask: "*" }).await?;
         Ok(ctx)
     }
 }
@@ -37,9 +35,7 @@ impl CommandHandler<state::Authenticated> for Links {
         mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-        ctx.send_client_unchecked(&format!(":* 365 {nick}  :End of INFO list\r\n")).await?;
+        ctx.reply(Reply::EndOfLinks { mask: "*" }).await?;
         Ok(ctx)
     }
 }
\ No newline at end of file
@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct List;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for List {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,35 @@ impl CommandHandler<state::Authenticated> for List {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl List {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        // Without a comma-separated list of channels, every channel is
+        // listed.
+        let wanted: Option<Vec<String>> =
+            msg.params().middles.first().map(|names| names.split(',').map(|name| name.irc_casefold()).collect());
+
+        let channels = ctx.network().channels().list();
+
+        ctx.reply(Reply::ListStart).await?;
+        for (channel, count, topic) in channels {
+            if wanted.as_ref().is_some_and(|wanted| !wanted.contains(&channel.as_ref().irc_casefold())) {
+                continue;
+            }
+
+            ctx.reply(Reply::List { channel: &channel, count, topic: &topic }).await?;
+        }
+
+        ctx.reply(Reply::ListEnd).await
+    }
+}
@@ -1,6 +1,6 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Lusers;
 
@@ -20,10 +20,10 @@ impl CommandHandler<state::Registered> for Lusers {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::send_lusers(&mut ctx).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +32,39 @@ impl CommandHandler<state::Authenticated> for Lusers {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::send_lusers(&mut ctx).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Lusers {
+    /// Send the 251-266 statistics block. Also used as
+    /// part of the welcome burst after registration.
+    pub async fn send_lusers<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
+        let users = ctx.network().users();
+        let invisible = ctx.network().invisible();
+        let unknown = ctx.network().unknown();
+        let max = ctx.network().max_users();
+        let channels = ctx.network().channels().len();
+
+        // Invisible users are counted apart from the others.
+        ctx.reply(Reply::LuserClient { users: users.saturating_sub(invisible), invisible }).await?;
+
+        if unknown > 0 {
+            ctx.reply(Reply::LuserUnknown { count: unknown }).await?;
+        }
+
+        if channels > 0 {
+            ctx.reply(Reply::LuserChannels { count: channels }).await?;
+        }
+
+        ctx.reply(Reply::LuserMe { clients: users }).await?;
+        ctx.reply(Reply::LocalUsers { current: users, max }).await?;
+        ctx.reply(Reply::GlobalUsers { current: users, max }).await?;
+
+        Ok(())
+    }
+}
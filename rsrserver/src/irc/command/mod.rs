@@ -111,11 +111,14 @@ commands![
     Whois,
     Whowas,
     Kill,
+    Ban,
+    Unban,
     Rehash,
     Restart,
     Squit,
     Away,
     Links,
     Userhost,
-    Wallops
+    Wallops,
+    Webirc
 ];
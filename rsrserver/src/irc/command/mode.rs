@@ -1,6 +1,16 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        GenericStateExt, IrcContext, Relay, Reply,
+        channel::{KEYLEN, ModeChange},
+        command::CommandHandler,
+        state,
+    },
+    storage::Storage,
+};
 
 pub struct Mode;
 
@@ -20,10 +30,10 @@ impl CommandHandler<state::Registered> for Mode {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +42,190 @@ impl CommandHandler<state::Authenticated> for Mode {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Mode {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(target) = params.middles.first() else {
+            return ctx.need_more_params("MODE").await;
+        };
+
+        if target.starts_with('#') {
+            let mut args = params.middles.iter().chain(params.trailing.raw()).skip(1);
+            let modes = args.next();
+            return Self::channel_modes(ctx, target, modes, args).await;
+        }
+
+        if target.irc_casefold() != ctx.nick().irc_casefold() {
+            return match ctx.network().lookup(target) {
+                Some(_) => ctx.reply(Reply::UsersDontMatch).await,
+                None => ctx.reply(Reply::NoSuchNick { target }).await,
+            };
+        }
+
+        let Some(modes) = params.middles.second().or(params.trailing.raw()) else {
+            let mut modes = String::from("+");
+            if ctx.session().invisible() {
+                modes.push('i');
+            }
+            if ctx.session().wallops() {
+                modes.push('w');
+            }
+            return ctx.reply(Reply::UModeIs { modes: &modes }).await;
+        };
+
+        Self::change_user_modes(ctx, modes).await
+    }
+
+    /// Apply a mode string such as `+iw` or `-w` to the client itself,
+    /// and tell it which modes actually changed.
+    async fn change_user_modes<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        modes: &str,
+    ) -> IrcResult<()> {
+        let mut adding = true;
+        let mut unknown = false;
+        let mut changed = String::new();
+
+        for flag in modes.chars() {
+            match flag {
+                '+' | '-' => adding = flag == '+',
+                'w' if ctx.session().wallops() != adding => {
+                    ctx.session_mut().set_wallops(adding);
+                    changed.push(if adding { '+' } else { '-' });
+                    changed.push('w');
+                }
+                'w' => {}
+                'i' if ctx.session().invisible() != adding => {
+                    ctx.session_mut().set_invisible(adding);
+                    changed.push(if adding { '+' } else { '-' });
+                    changed.push('i');
+                }
+                'i' => {}
+                _ => unknown = true,
+            }
+        }
+
+        if unknown {
+            ctx.reply(Reply::UModeUnknownFlag).await?;
+        }
+
+        if !changed.is_empty() {
+            let nick = ctx.nick().to_owned();
+            let line = Relay::Mode { target: &nick, modes: &changed }.render(&ctx.source());
+            ctx.send_client_unchecked(line).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Show the modes of a channel, or apply a mode string such as
+    /// `+kl key 10` to it as one of its operators and tell every member
+    /// which modes actually changed.
+    async fn channel_modes<'a, 'm, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        target: &str,
+        modes: Option<&str>,
+        mut args: impl Iterator<Item = &'m str>,
+    ) -> IrcResult<()> {
+        let key = target.irc_casefold();
+        let Some((channel, current)) = ctx.network().channels().modes(&key) else {
+            return ctx.reply(Reply::NoSuchChannel { channel: target }).await;
+        };
+
+        let Some(modes) = modes else {
+            let (modes, args) = current.render(ctx.is_subscribed(&key));
+            ctx.reply(Reply::ChannelModeIs { channel: &channel, modes: &modes, args: &args }).await?;
+            return ctx.reply(Reply::CreationTime { channel: &channel, created: current.created }).await;
+        };
+
+        if !ctx.is_subscribed(&key) {
+            return ctx.reply(Reply::NotOnChannel { channel: &channel }).await;
+        }
+
+        if !ctx.network().channels().is_op(ctx.session().id(), &key) {
+            return ctx.reply(Reply::ChanOpPrivsNeeded { channel: &channel }).await;
+        }
+
+        let mut adding = true;
+        let mut sign = None;
+        let mut changed = String::new();
+        let mut changed_args = Vec::new();
+
+        for flag in modes.chars() {
+            let (change, arg) = match flag {
+                '+' | '-' => {
+                    adding = flag == '+';
+                    continue;
+                }
+                'i' => (ModeChange::InviteOnly(adding), None),
+                'k' if adding => match args.next().filter(|k| Self::valid_key(k)) {
+                    Some(k) => (ModeChange::Key(Some(k.to_owned())), Some(k.to_owned())),
+                    None => continue,
+                },
+                // The key is not needed to remove it, but is consumed
+                // if given.
+                'k' => {
+                    args.next();
+                    (ModeChange::Key(None), Some("*".to_owned()))
+                }
+                'l' if adding => match args.next().and_then(|l| l.parse::<usize>().ok()).filter(|&l| l > 0) {
+                    Some(l) => (ModeChange::Limit(Some(l)), Some(l.to_string())),
+                    None => continue,
+                },
+                'l' => (ModeChange::Limit(None), None),
+                'o' => {
+                    let Some(nick) = args.next() else {
+                        continue;
+                    };
+
+                    let member = ctx.network().lookup(nick).filter(|&id| ctx.network().channels().is_member(id, &key));
+                    match member {
+                        Some(id) => (ModeChange::Op(id, adding), Some(nick.to_owned())),
+                        None => {
+                            ctx.reply(Reply::UserNotInChannel { nick, channel: &channel }).await?;
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    ctx.reply(Reply::UnknownMode { mode: flag }).await?;
+                    continue;
+                }
+            };
+
+            if ctx.network().channels().change_mode(&key, change) {
+                let prefix = if adding { '+' } else { '-' };
+                if sign != Some(prefix) {
+                    changed.push(prefix);
+                    sign = Some(prefix);
+                }
+                changed.push(flag);
+                changed_args.extend(arg);
+            }
+        }
+
+        if !changed.is_empty() {
+            let relay = Relay::ChannelMode { channel: &channel, modes: &changed, args: &changed_args };
+            let line = relay.render(&ctx.source());
+            ctx.send_channel(&key, line);
+        }
+
+        Ok(())
+    }
+
+    fn valid_key(key: &str) -> bool {
+        !key.is_empty() && key.len() <= KEYLEN && !key.contains([' ', ',', ':'])
+    }
+}
@@ -1,7 +1,7 @@
 use ircv3_parse::Message;
 
 use crate::{
-    error::IrcResult, ext::StrExt, irc::{GenericStateExt, IrcContext, command::CommandHandler, state}, storage::Storage
+    error::IrcResult, irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state}, storage::Storage
 };
 
 pub struct Motd;
@@ -25,16 +25,7 @@ impl CommandHandler<state::Registered> for Motd {
         mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-
-        const VERSION: &str = env!("CARGO_PKG_VERSION");
-        let modes = ""; // Supported user and channel modes.
-        ctx.send_client_unchecked(&format!(":* 422 {nick} rsr-{VERSION} * :{modes}\r\n"))
-            .await?;
-
-        // TODO: RPL_ISUPPORT?
-
+        Self::send_motd(&mut ctx).await?;
         Ok(ctx)
     }
 }
@@ -46,16 +37,24 @@ impl CommandHandler<state::Authenticated> for Motd {
         mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-
-        const VERSION: &str = env!("CARGO_PKG_VERSION");
-        let modes = ""; // Supported user and channel modes.
-        ctx.send_client_unchecked(&format!(":* 422 {nick} rsr-{VERSION} * :{modes}\r\n"))
-            .await?;
-
-        // TODO: RPL_ISUPPORT?
-
+        Self::send_motd(&mut ctx).await?;
         Ok(ctx)
     }
 }
+
+impl Motd {
+    /// Send the message of the day, or 422 if none is configured.
+    /// Also used as the tail of the welcome burst after registration.
+    pub async fn send_motd<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
+        let config = ctx.network().config();
+        let Some(lines) = &config.motd else {
+            return ctx.reply(Reply::NoMotd).await;
+        };
+
+        ctx.reply(Reply::MotdStart).await?;
+        for line in lines {
+            ctx.reply(Reply::Motd { line }).await?;
+        }
+        ctx.reply(Reply::EndOfMotd).await
+    }
+}
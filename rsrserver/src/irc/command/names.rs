@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Names;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Names {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,68 @@ impl CommandHandler<state::Authenticated> for Names {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Names {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let Some(targets) = msg.params().middles.first().filter(|t| !t.is_empty()) else {
+            // Listing every visible user on the server is not supported.
+            return Self::end_of_names(ctx, "*").await;
+        };
+
+        for name in targets.split(',') {
+            Self::send_names(ctx, &name.irc_casefold()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send `RPL_NAMREPLY` for the channel with the given casefolded
+    /// name, followed by `RPL_ENDOFNAMES`.
+    pub async fn send_names<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        key: &str,
+    ) -> IrcResult<()> {
+        let Some((channel, names)) = ctx.network().channels().names(key) else {
+            return Self::end_of_names(ctx, key).await;
+        };
+
+        // Pack as many names into each line as will fit.
+        let overhead = Reply::NamReply { channel: &channel, names: "" }
+            .render(ctx.network().name(), ctx.nick())
+            .len();
+        let budget = 512usize.saturating_sub(overhead);
+
+        let mut line = String::new();
+        for name in names {
+            if !line.is_empty() && line.len() + name.len() + 1 > budget {
+                ctx.reply(Reply::NamReply { channel: &channel, names: &line }).await?;
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&name);
+        }
+        ctx.reply(Reply::NamReply { channel: &channel, names: &line }).await?;
+
+        Self::end_of_names(ctx, &channel).await
+    }
+
+    async fn end_of_names<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        channel: &str,
+    ) -> IrcResult<()> {
+        ctx.reply(Reply::EndOfNames { channel }).await
+    }
+}
@@ -1,18 +1,30 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        GenericStateExt, IrcContext, Relay, Reply,
+        command::CommandHandler,
+        state::{self, MaybeTransition},
+    },
+    storage::Storage,
+};
 
 pub struct Nick;
 
 impl CommandHandler<state::Anonymous> for Nick {
-    type Contract = state::Anonymous;
+    type Contract = MaybeTransition<state::Anonymous, state::Registered>;
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Anonymous, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
-        Ok(ctx)
+        if let Some(new_nick) = Self::claim(&mut ctx, &msg).await? {
+            ctx.nick = Some(new_nick);
+        }
+
+        ctx.try_register().await
     }
 }
 
@@ -20,10 +32,14 @@ impl CommandHandler<state::Registered> for Nick {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        if let Some(new_nick) = Self::claim(&mut ctx, &msg).await? {
+            Self::announce(&mut ctx, &new_nick).await?;
+            ctx.nick = new_nick;
+        }
+
         Ok(ctx)
     }
 }
@@ -32,37 +48,67 @@ impl CommandHandler<state::Authenticated> for Nick {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        if let Some(new_nick) = Self::claim(&mut ctx, &msg).await? {
+            Self::announce(&mut ctx, &new_nick).await?;
+            ctx.nick = new_nick;
+        }
+
         Ok(ctx)
     }
 }
 
-/*
-       let client = ctx.client_nick();
-
-        let Some(new_nick) = msg.params().middles.first() else {
-            ctx.send_client_unchecked(&format!(":* 431 {client} :No nickname given")).await?;
-            return Ok(Old(ctx).into());
+impl Nick {
+    /// Validate the requested nick and claim it server-wide. Returns the
+    /// new nick on success, or None once the appropriate error numeric
+    /// has been sent to the client.
+    async fn claim<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<Option<String>> {
+        let Some(new_nick) = msg.params().middles.first().or(msg.params().trailing.raw()) else {
+            ctx.reply(Reply::NoNicknameGiven).await?;
+            return Ok(None);
         };
 
-        // TODO: Actual length limits.
-        let new_nick = new_nick.slice_at_most(128);
+        let new_nick = new_nick.slice_at_most(64);
+
+        // An identical nick is a no-op. Case-only changes fall through
+        // and succeed since we already own the casefolded nick.
+        if new_nick == ctx.nick() {
+            return Ok(None);
+        }
 
         if let Err(reason) = ctx.validate_nick(new_nick) {
-            ctx.send_client_unchecked(&format!(":* 432 {client} {new_nick} :{reason}")).await?;
-            return Ok(Old(ctx).into());
+            ctx.reply(Reply::ErroneusNickname { nick: new_nick, reason }).await?;
+            return Ok(None);
         }
 
-        let Ok(_) = ctx.storage().whois(new_nick).await else {
-            ctx.send_client_unchecked(&format!(":* 432 {client} {new_nick} :Nickname is already in use")).await?;
-            return Ok(Old(ctx).into());
-        };
+        if !ctx.network().claim_nick(ctx.session().id(), new_nick) {
+            ctx.reply(Reply::NicknameInUse { nick: new_nick }).await?;
+            return Ok(None);
+        }
 
-        ctx.nick = Some(new_nick.to_owned());
+        Ok(Some(new_nick.to_owned()))
+    }
 
-        // TODO: Trigger try state change.
-        Ok(Old(ctx).into())
- */
\ No newline at end of file
+    /// Tell a registered client and everyone sharing a channel with it
+    /// that its nick changed, and update the member lists of those channels.
+    async fn announce<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        new_nick: &str,
+    ) -> IrcResult<()> {
+        let id = ctx.session().id();
+        for key in ctx.subscriptions() {
+            ctx.network().channels().rename_member(id, &key, new_nick);
+        }
+
+        let line = Relay::Nick { nick: new_nick }.render(&ctx.source());
+        ctx.session_mut().rename(new_nick);
+
+        ctx.send_peers(line.clone());
+        ctx.send_client_unchecked(&line).await
+    }
+}
@@ -1,6 +1,14 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{
+        IrcContext,
+        command::{CommandHandler, Privmsg},
+        state,
+    },
+    storage::Storage,
+};
 
 pub struct Notice;
 
@@ -20,10 +28,10 @@ impl CommandHandler<state::Registered> for Notice {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Privmsg::relay(&mut ctx, &msg, "NOTICE", false).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +40,10 @@ impl CommandHandler<state::Authenticated> for Notice {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Privmsg::relay(&mut ctx, &msg, "NOTICE", false).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
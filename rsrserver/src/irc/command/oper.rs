@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Oper;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Oper {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,44 @@ impl CommandHandler<state::Authenticated> for Oper {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Oper {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let (Some(name), Some(password)) = (
+            params.middles.first(),
+            params.middles.second().or(params.trailing.raw()),
+        ) else {
+            return ctx.need_more_params("OPER").await;
+        };
+
+        let config = ctx.network().config();
+        let Some(oper) = config.opers.iter().find(|oper| oper.name == name) else {
+            return ctx.reply(Reply::PasswdMismatch).await;
+        };
+
+        let mask = format!("{}@{}", ctx.user(), ctx.session().host());
+        if !oper.hosts.is_empty() && !oper.hosts.iter().any(|host| mask.as_str().matches_mask(host)) {
+            return ctx.reply(Reply::NoOperHost).await;
+        }
+
+        if !oper.check_password(password) || !oper.check_certfp(ctx.session().certfp()) {
+            return ctx.reply(Reply::PasswdMismatch).await;
+        }
+
+        tracing::info!(oper = name, source = ctx.source(), "OPER");
+        ctx.session_mut().set_oper(name);
+        ctx.reply(Reply::YoureOper).await
+    }
+}
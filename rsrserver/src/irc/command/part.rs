@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Relay, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Part;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Part {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,79 @@ impl CommandHandler<state::Authenticated> for Part {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Part {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(targets) = params.middles.first().filter(|t| !t.is_empty()) else {
+            ctx.need_more_params("PART").await?;
+            return Ok(());
+        };
+
+        let reason = params.trailing.raw().or(params.middles.second());
+
+        for name in targets.split(',') {
+            Self::part_one(ctx, name, reason).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Leave every channel, as done by `JOIN 0`.
+    pub async fn part_all<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        reason: Option<&str>,
+    ) -> IrcResult<()> {
+        for key in ctx.subscriptions() {
+            Self::part_one(ctx, &key, reason).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn part_one<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        name: &str,
+        reason: Option<&str>,
+    ) -> IrcResult<()> {
+        let name = name.slice_at_most(64);
+        let key = name.irc_casefold();
+
+        if !ctx.is_subscribed(&key) {
+            let reply = match ctx.network().channels().exists(&key) {
+                true => Reply::NotOnChannel { channel: name },
+                false => Reply::NoSuchChannel { channel: name },
+            };
+
+            return ctx.reply(reply).await;
+        }
+
+        let channel = ctx
+            .network()
+            .channels()
+            .names(&key)
+            .map(|(channel, _)| channel.to_string())
+            .unwrap_or_else(|| name.to_owned());
+
+        let line = Relay::Part { channel: &channel, reason }.render(&ctx.source());
+
+        // Other members see the PART through the channel. Our own copy
+        // goes out directly since we stop listening to the channel here.
+        ctx.send_channel(&key, line.clone());
+        ctx.unsubscribe(&key);
+        ctx.network().channels().part(ctx.session().id(), &key);
+
+        ctx.send_client_unchecked(&line).await
+    }
+}
@@ -8,7 +8,7 @@ impl CommandHandler<state::Anonymous> for Pass {
     type Contract = state::Anonymous;
 
     async fn handle<'a, S: Storage>(
-        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        ctx: IrcContext<'a, state::Anonymous, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
         // Silently allow any PASS commands as RSR servers do not 
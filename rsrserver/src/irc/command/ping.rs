@@ -1,6 +1,6 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Ping;
 
@@ -41,10 +41,10 @@ impl CommandHandler<state::Authenticated> for Ping {
 }
 
 impl Ping {
-    async fn pong<'a, T, S: Storage>(ctx: &mut IrcContext<'a, T, S>, msg: &Message<'a>) -> IrcResult<()> {
-        let token = msg.params().middles.first().unwrap_or("");
-        ctx.send_client_unchecked(&format!("PONG {token}\r\n")).await?;
+    async fn pong<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>, msg: &Message<'a>) -> IrcResult<()> {
+        let params = msg.params();
+        let token = params.middles.first().or(params.trailing.raw()).unwrap_or("");
 
-        Ok(())
+        ctx.reply(Reply::Pong { token }).await
     }
 }
\ No newline at end of file
@@ -54,8 +54,11 @@ impl Pong {
             return Ok(());
         }
 
-        // Did the client return the correct token?
-        let Ok(client_token) = msg.params().middles.first().unwrap_or("").parse::<u64>() else {
+        // Did the client return the correct token? Some clients send
+        // it back as the trailing parameter.
+        let params = msg.params();
+        let token = params.middles.first().or(params.trailing.raw()).unwrap_or("");
+        let Ok(client_token) = token.parse::<u64>() else {
             // Drop malformed PONG replies.
             return Ok(());
         };
@@ -1,6 +1,15 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    ext::StrExt,
+    irc::{Capabilities, GenericStateExt, IrcContext, Relay, Reply, ServerMessage, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Privmsg;
 
@@ -20,10 +29,10 @@ impl CommandHandler<state::Registered> for Privmsg {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::relay(&mut ctx, &msg, "PRIVMSG", true).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +41,146 @@ impl CommandHandler<state::Authenticated> for Privmsg {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::relay(&mut ctx, &msg, "PRIVMSG", true).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Privmsg {
+    /// Deliver a PRIVMSG or NOTICE to each of its comma separated
+    /// targets. NOTICE must never trigger an automatic reply, so
+    /// error numerics are only sent when `replies` is set.
+    pub async fn relay<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+        command: &str,
+        replies: bool,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(targets) = params.middles.first().filter(|t| !t.is_empty()) else {
+            if replies {
+                ctx.reply(Reply::NoRecipient { command }).await?;
+            }
+            return Ok(());
+        };
+
+        let Some(text) = params.trailing.raw().or(params.middles.second()).filter(|t| !t.is_empty())
+        else {
+            if replies {
+                ctx.reply(Reply::NoTextToSend).await?;
+            }
+            return Ok(());
+        };
+
+        let targets = targets.split(',').filter(|t| !t.is_empty()).collect::<Vec<_>>();
+        if targets.len() > ctx.network().targmax() {
+            if replies {
+                let target = targets[ctx.network().targmax()];
+                ctx.reply(Reply::TooManyTargets { target }).await?;
+            }
+            return Ok(());
+        }
+
+        for target in targets {
+            Self::relay_one(ctx, command, target, text, replies).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn relay_one<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        command: &str,
+        target: &str,
+        text: &str,
+        replies: bool,
+    ) -> IrcResult<()> {
+        let echo = ctx.session().caps().contains(Capabilities::CapEchoMessage);
+
+        let target = target.slice_at_most(64);
+
+        if target.starts_with('$') {
+            return Self::relay_global(ctx, command, target, text, replies).await;
+        }
+
+        let account = ctx.session().account();
+        let line = Relay::Message { command, target, text, account }.render(&ctx.source());
+
+        if target.starts_with('#') {
+            let key = target.irc_casefold();
+
+            if !ctx.is_subscribed(&key) {
+                if replies {
+                    let reply = match ctx.network().channels().exists(&key) {
+                        true => Reply::CannotSendToChan { channel: target },
+                        false => Reply::NoSuchNick { target },
+                    };
+                    ctx.reply(reply).await?;
+                }
+                return Ok(());
+            }
+
+            // Our own subscription hands the line straight back to us,
+            // which is exactly what echo-message clients asked for.
+            match echo {
+                true => ctx.send_channel(&key, line),
+                false => ctx.send_channel_others(&key, line),
+            }
+            return Ok(());
+        }
+
+        let Some(id) = ctx.network().lookup(target) else {
+            if replies {
+                ctx.reply(Reply::NoSuchNick { target }).await?;
+            }
+            return Ok(());
+        };
+
+        let to_self = id == ctx.session().id();
+        if !to_self || !echo {
+            ctx.network().send_direct(id, Arc::new(Bytes::from(line.clone())));
+        }
+
+        if echo {
+            ctx.deliver(&line).await?;
+        }
+
+        if replies && let Some(profile) = ctx.network().profile(target) && let Some(text) = &profile.away {
+            ctx.reply(Reply::Away { nick: &profile.nick, text }).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Deliver a NOTICE to every user on the server. Opers address
+    /// these to a `$mask` matching the server name, usually `$*`.
+    async fn relay_global<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        command: &str,
+        mask: &str,
+        text: &str,
+        replies: bool,
+    ) -> IrcResult<()> {
+        let server = ctx.network().name();
+        let allowed = command == "NOTICE" && ctx.has_privilege(Privilege::GlobalNotice);
+
+        if !allowed || !server.matches_mask(&mask[1..]) {
+            if replies {
+                ctx.reply(Reply::NoSuchNick { target: mask }).await?;
+            }
+            return Ok(());
+        }
+
+        ctx.broadcast_server(ServerMessage::GlobalNotice {
+            source: ctx.source().into(),
+            mask: mask.into(),
+            text: text.into(),
+        });
+
+        Ok(())
+    }
+}
@@ -1,23 +1,28 @@
 use ircv3_parse::Message;
 
-use crate::{error::{IrcResult, IrcSessionError}, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::{IrcResult, IrcSessionError},
+    ext::StrExt,
+    irc::{IrcContext, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Quit;
 
+/// Longest QUIT reason passed on to other clients.
+const QUITLEN: usize = 256;
+
 impl CommandHandler<state::Anonymous> for Quit {
     type Contract = state::Anonymous;
 
     async fn handle<'a, S: Storage>(
-        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        _ctx: IrcContext<'a, state::Anonymous, S>,
         msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
         // QUIT on an Anonymous connection is easy: Clients
         // must be registered to join channels or chat, so
-        // they can simply be disconnected without any extra
-        // handling needed at this point since there's
-        // nobody to notify.
-
-        Err::<state::Anonymous, IrcSessionError>(crate::error::IrcSessionError::ClientQUIT(msg.params().trailing.as_str().to_owned()))
+        // there's nobody to notify.
+        Err::<state::Anonymous, _>(Self::quit(&msg))
     }
 }
 
@@ -25,11 +30,11 @@ impl CommandHandler<state::Registered> for Quit {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        _ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
-        Ok(ctx)
+        // Peers are told when the connection is torn down.
+        Err::<state::Registered, _>(Self::quit(&msg))
     }
 }
 
@@ -37,10 +42,18 @@ impl CommandHandler<state::Authenticated> for Quit {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        _ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
-        Ok(ctx)
+        Err::<state::Authenticated, _>(Self::quit(&msg))
+    }
+}
+
+impl Quit {
+    fn quit(msg: &Message<'_>) -> IrcSessionError {
+        let params = msg.params();
+        let reason = params.trailing.raw().or(params.middles.first()).unwrap_or("");
+
+        IrcSessionError::ClientQUIT(reason.slice_at_most(QUITLEN).to_owned())
     }
 }
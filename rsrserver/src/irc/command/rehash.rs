@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    config::Privilege,
+    error::{ConfigError, IrcResult},
+    irc::{GenericStateExt, IrcContext, Reply, ServerMessage, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Rehash;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Rehash {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,46 @@ impl CommandHandler<state::Authenticated> for Rehash {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Rehash {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
+        if !ctx.has_privilege(Privilege::Rehash) {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        let file = ctx.network().config().path.display().to_string();
+        ctx.reply(Reply::Rehashing { file: &file }).await?;
+
+        match ctx.network().rehash().await {
+            Ok(changes) => {
+                tracing::info!(source = ctx.source(), "rehashed");
+                ctx.notify_cap_changes(changes);
+                ctx.broadcast_server(ServerMessage::Rehashed { source: ctx.source().into() });
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!(source = ctx.source(), "rehash failed: {e}");
+
+                // Report each problem on its own line.
+                let errors = match e {
+                    ConfigError::Invalid(errors) => errors,
+                    e => vec![e.to_string()],
+                };
+
+                for error in errors {
+                    let text = format!("Rehash failed: {error}");
+                    ctx.reply(Reply::Notice { text: &text }).await?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Restart;
 
@@ -20,10 +24,10 @@ impl CommandHandler<state::Registered> for Restart {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +36,25 @@ impl CommandHandler<state::Authenticated> for Restart {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Restart {
+    /// `RESTART`. Restarting is left to whatever supervises the
+    /// process, so opers are told it is not supported.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        _msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        if ctx.session().oper().is_none() {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        ctx.fail("RESTART", "NOT_SUPPORTED", "This server can not be restarted over IRC").await
+    }
+}
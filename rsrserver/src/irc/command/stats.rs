@@ -1,6 +1,12 @@
+use chrono::Utc;
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Stats;
 
@@ -20,10 +26,10 @@ impl CommandHandler<state::Registered> for Stats {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +38,55 @@ impl CommandHandler<state::Authenticated> for Stats {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Stats {
+    /// `STATS <query>`. `u` is the server uptime, `z` counts the
+    /// channel messages clients lost by falling behind and `k` lists
+    /// server bans to opers allowed to set them. Anything else only
+    /// gets the end of the report.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(query) = params.middles.first().or(params.trailing.raw()).filter(|q| !q.is_empty()) else {
+            return ctx.need_more_params("STATS").await;
+        };
+
+        match query {
+            "u" => {
+                let seconds = (Utc::now() - *ctx.network().created()).num_seconds();
+                ctx.reply(Reply::StatsUptime { seconds }).await?;
+            }
+            "k" if !ctx.has_privilege(Privilege::Ban) => return ctx.reply(Reply::NoPrivileges).await,
+            "k" => {
+                for ban in ctx.network().bans().active() {
+                    ctx.reply(Reply::StatsBan {
+                        kind: ban.kind.name(),
+                        mask: &ban.mask,
+                        expires: ban.expires.map_or(0, |expires| expires.timestamp()),
+                        set_by: &ban.set_by,
+                        reason: &ban.reason,
+                    })
+                    .await?;
+                }
+            }
+            "z" => {
+                let (events, lost) = ctx.network().channel_lag();
+                let text = format!("Channel lag: {events} times, {lost} messages lost");
+                ctx.reply(Reply::StatsDebug { query, text: &text }).await?;
+            }
+            _ => {}
+        }
+
+        ctx.reply(Reply::EndOfStats { query }).await
+    }
+}
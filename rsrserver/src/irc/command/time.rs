@@ -1,7 +1,7 @@
 use chrono::Utc;
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, ext::StrExt, irc::{GenericStateExt, IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{error::IrcResult, irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state}, storage::Storage};
 
 pub struct Time;
 
@@ -48,12 +48,7 @@ impl Time {
     }
 
     async fn handle_inner<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>, _msg: &Message<'a>) -> IrcResult<()> {
-        let (unix_time, time_str) = Self::current();
-        let nick = ctx.nick();
-        let nick = nick.slice_at_most(40);
-
-        ctx.send_client_unchecked(&format!(":* {nick} * {unix_time} 0 :{time_str}\r\n"))
-            .await?;
-        Ok(())
+        let (timestamp, text) = Self::current();
+        ctx.reply(Reply::Time { timestamp, text: &text }).await
     }
 }
@@ -1,6 +1,17 @@
+use chrono::Utc;
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        GenericStateExt, IrcContext, Relay, Reply,
+        channel::{self, TOPICLEN},
+        command::CommandHandler,
+        state,
+    },
+    storage::Storage,
+};
 
 pub struct Topic;
 
@@ -20,10 +31,10 @@ impl CommandHandler<state::Registered> for Topic {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +43,70 @@ impl CommandHandler<state::Authenticated> for Topic {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Topic {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(target) = params.middles.first() else {
+            return ctx.need_more_params("TOPIC").await;
+        };
+
+        let key = target.irc_casefold();
+        let Some((channel, topic)) = ctx.network().channels().topic(&key) else {
+            return ctx.reply(Reply::NoSuchChannel { channel: target }).await;
+        };
+
+        let Some(text) = params.middles.second().or(params.trailing.raw()) else {
+            return match topic {
+                Some(topic) => Self::send_topic(ctx, &channel, &topic).await,
+                None => ctx.reply(Reply::NoTopic { channel: &channel }).await,
+            };
+        };
+
+        if !ctx.is_subscribed(&key) {
+            return ctx.reply(Reply::NotOnChannel { channel: &channel }).await;
+        }
+
+        // An empty topic clears it.
+        let text = text.slice_at_most(TOPICLEN);
+        let topic = (!text.is_empty()).then(|| channel::Topic {
+            text: text.to_owned(),
+            set_by: ctx.nick().to_owned(),
+            set_at: Utc::now().timestamp(),
+        });
+
+        if ctx.network().channels().set_topic(&key, topic) {
+            let line = Relay::Topic { channel: &channel, text }.render(&ctx.source());
+            ctx.send_channel(&key, line);
+        }
+
+        Ok(())
+    }
+
+    /// Send `RPL_TOPIC` and `RPL_TOPICWHOTIME` for a channel that has
+    /// a topic.
+    pub async fn send_topic<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        channel: &str,
+        topic: &channel::Topic,
+    ) -> IrcResult<()> {
+        ctx.reply(Reply::Topic { channel, text: &topic.text }).await?;
+        ctx.reply(Reply::TopicWhoTime {
+            channel,
+            set_by: &topic.set_by,
+            set_at: topic.set_at,
+        })
+        .await
+    }
+}
@@ -0,0 +1,88 @@
+use ircv3_parse::Message;
+
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::{BanKind, Storage},
+};
+
+pub struct Unban;
+
+impl CommandHandler<state::Anonymous> for Unban {
+    type Contract = state::Anonymous;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        _msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        ctx.registration_required().await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Registered> for Unban {
+    type Contract = state::Registered;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        Self::handle_inner(&mut ctx, &msg).await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Authenticated> for Unban {
+    type Contract = state::Authenticated;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        Self::handle_inner(&mut ctx, &msg).await?;
+        Ok(ctx)
+    }
+}
+
+impl Unban {
+    /// `UNBAN <type> <mask>`, lifting a ban set with `BAN`.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+        let mut args = params.middles.iter().chain(params.trailing.raw());
+
+        let (Some(kind), Some(mask)) = (args.next(), args.next()) else {
+            return ctx.need_more_params("UNBAN").await;
+        };
+
+        if !ctx.has_privilege(Privilege::Ban) {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        let Some(kind) = BanKind::from_name(kind) else {
+            let text = format!("Unknown ban type {kind}");
+            return ctx.fail("UNBAN", "INVALID_PARAMS", &text).await;
+        };
+
+        // Masks are compared in the form they were stored in.
+        let mask = kind.normalize(mask).unwrap_or_else(|_| mask.to_owned());
+        if !ctx.network().bans().active().iter().any(|ban| ban.kind == kind && ban.mask == mask) {
+            let text = format!("There is no {} ban on {mask}", kind.name());
+            return ctx.fail("UNBAN", "NO_SUCH_BAN", &text).await;
+        }
+
+        if ctx.storage().remove_ban(kind, &mask).await.is_err() {
+            tracing::warn!("storage backend failed to remove a ban");
+            return ctx.fail("UNBAN", "TEMPORARILY_UNAVAILABLE", "The ban could not be removed").await;
+        }
+
+        ctx.network().bans().remove(kind, &mask);
+        tracing::info!(source = ctx.source(), kind = kind.name(), mask, "UNBAN");
+
+        let text = format!("Removed {} ban on {mask}", kind.name());
+        ctx.reply(Reply::Notice { text: &text }).await
+    }
+}
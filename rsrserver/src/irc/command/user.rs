@@ -1,18 +1,46 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{
+        IrcContext,
+        command::CommandHandler,
+        network::USERLEN,
+        state::{self, MaybeTransition, Old},
+    },
+    storage::Storage,
+};
 
 pub struct User;
 
 impl CommandHandler<state::Anonymous> for User {
-    type Contract = state::Anonymous;
+    type Contract = MaybeTransition<state::Anonymous, state::Registered>;
 
     async fn handle<'a, S: Storage>(
         mut ctx: IrcContext<'a, state::Anonymous, S>,
-        _msg: Message<'a>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        ctx.registration_required().await?;
-        Ok(ctx)
+        // USER <username> <mode> <unused> :<realname>
+        let params = msg.params();
+        let mut middles = params.middles.iter();
+        let user = middles.next().filter(|u| !u.is_empty());
+        let real = params.trailing.raw().or(middles.nth(2));
+
+        let (Some(user), Some(real)) = (user, real) else {
+            ctx.need_more_params("USER").await?;
+            return Ok(Old(ctx).into());
+        };
+
+        if ctx.user.is_some() {
+            ctx.already_registered().await?;
+            return Ok(Old(ctx).into());
+        }
+
+        ctx.user = Some(user.slice_at_most(USERLEN).to_owned());
+        ctx.real = Some(real.slice_at_most(256).to_owned());
+
+        ctx.try_register().await
     }
 }
 
@@ -20,10 +48,10 @@ impl CommandHandler<state::Registered> for User {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        ctx.already_registered().await?;
         Ok(ctx)
     }
 }
@@ -32,45 +60,10 @@ impl CommandHandler<state::Authenticated> for User {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
-        Ok(ctx)
-    }
-}
-/*
-impl CommandHandler<User> for state::Anonymous {
-    type Contract = MaybeTransition<state::Anonymous, State>;
-    async fn handle<'a, S: Storage>(
-        mut ctx: crate::irc::IrcContext<'a, Self, S>,
-        msg: ircv3_parse::Message<'a>,
-    ) -> crate::error::IrcResult<crate::irc::IrcContext<'a, Self, S>> {
-        let client = ctx.client_nick();
-
-        let Some(new_nick) = msg.params().middles.first() else {
-            ctx.send_client_unchecked(&format!(":* 431 {client} :No nickname given")).await?;
-            return Ok(ctx);
-        };
-
-        // TODO: Actual length limits.
-        let new_nick = new_nick.slice_at_most(128);
-
-        if let Err(reason) = ctx.validate_nick(new_nick) {
-            ctx.send_client_unchecked(&format!(":* 432 {client} {new_nick} :{reason}")).await?;
-            return Ok(ctx);
-        }
-
-        let Ok(_) = ctx.session().whois(new_nick).await else {
-            ctx.send_client_unchecked(&format!(":* 432 {client} {new_nick} :Nickname is already in use")).await?;
-            return Ok(ctx);
-        };
-
-        ctx.nick = Some(new_nick.to_owned());
-
-        // TODO: Trigger state change.
-
+        ctx.already_registered().await?;
         Ok(ctx)
     }
 }
-*/
\ No newline at end of file
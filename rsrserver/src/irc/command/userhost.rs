@@ -1,6 +1,13 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
+
+/// Nicks looked up by a single USERHOST, as in RFC 2812.
+const MAX_NICKS: usize = 5;
 
 pub struct Userhost;
 
@@ -20,10 +27,10 @@ impl CommandHandler<state::Registered> for Userhost {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +39,49 @@ impl CommandHandler<state::Authenticated> for Userhost {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Userhost {
+    /// `USERHOST <nick>{ <nick>}`. Each known nick is listed as
+    /// `nick[*]=<+|->user@host`, where `*` marks opers and `-` away users.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+        let nicks = params
+            .middles
+            .iter()
+            .chain(params.trailing.raw())
+            .flat_map(str::split_ascii_whitespace)
+            .take(MAX_NICKS)
+            .collect::<Vec<_>>();
+
+        if nicks.is_empty() {
+            return ctx.need_more_params("USERHOST").await;
+        }
+
+        let replies = nicks
+            .into_iter()
+            .filter_map(|nick| ctx.network().profile(nick))
+            .map(|profile| {
+                format!(
+                    "{}{}={}{}@{}",
+                    profile.nick,
+                    if profile.oper { "*" } else { "" },
+                    if profile.away.is_some() { '-' } else { '+' },
+                    profile.user,
+                    profile.host,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        ctx.reply(Reply::UserHost { replies: &replies.join(" ") }).await
+    }
+}
@@ -1,6 +1,10 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, registration::VERSION, state},
+    storage::Storage,
+};
 
 pub struct Version;
 
@@ -20,10 +24,10 @@ impl CommandHandler<state::Registered> for Version {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +36,19 @@ impl CommandHandler<state::Authenticated> for Version {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
         _msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Version {
+    /// `VERSION [<server>]`. Only this server can be asked, so the
+    /// target is ignored. `RPL_ISUPPORT` is sent again afterwards.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(ctx: &mut IrcContext<'a, T, S>) -> IrcResult<()> {
+        ctx.reply(Reply::Version { version: VERSION, comments: env!("CARGO_PKG_HOMEPAGE") }).await?;
+        ctx.isupport().await
+    }
+}
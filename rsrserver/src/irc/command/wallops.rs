@@ -1,6 +1,11 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    config::Privilege,
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, ServerMessage, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Wallops;
 
@@ -20,10 +25,10 @@ impl CommandHandler<state::Registered> for Wallops {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +37,34 @@ impl CommandHandler<state::Authenticated> for Wallops {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Wallops {
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(text) = params.trailing.raw().or(params.middles.first()).filter(|t| !t.is_empty()) else {
+            return ctx.need_more_params("WALLOPS").await;
+        };
+
+        if !ctx.has_privilege(Privilege::Wallops) {
+            return ctx.reply(Reply::NoPrivileges).await;
+        }
+
+        ctx.broadcast_server(ServerMessage::Wallops {
+            source: ctx.source().into(),
+            text: text.into(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+use std::net::IpAddr;
+
+use ircv3_parse::Message;
+
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
+
+/// Longest hostname a gateway may pass on.
+const HOSTLEN: usize = 63;
+
+pub struct Webirc;
+
+impl CommandHandler<state::Anonymous> for Webirc {
+    type Contract = state::Anonymous;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Anonymous, S>,
+        msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        Self::handle_inner(&mut ctx, &msg).await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Registered> for Webirc {
+    type Contract = state::Registered;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        _msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        ctx.reply(Reply::AlreadyRegistered).await?;
+        Ok(ctx)
+    }
+}
+
+impl CommandHandler<state::Authenticated> for Webirc {
+    type Contract = state::Authenticated;
+
+    async fn handle<'a, S: Storage>(
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        _msg: Message<'a>,
+    ) -> IrcResult<impl Into<Self::Contract>> {
+        ctx.reply(Reply::AlreadyRegistered).await?;
+        Ok(ctx)
+    }
+}
+
+impl Webirc {
+    /// `WEBIRC <password> <gateway> <hostname> <ip> [:<options>]`
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+        let mut args = params.middles.iter().chain(params.trailing.raw());
+
+        let (Some(password), Some(name), Some(hostname), Some(ip)) = (args.next(), args.next(), args.next(), args.next())
+        else {
+            return ctx.need_more_params("WEBIRC").await;
+        };
+        let options = args.next().unwrap_or_default();
+
+        if ctx.session().gateway().is_some() {
+            return ctx.fail("WEBIRC", "INVALID_REQUEST", "WEBIRC may only be sent once").await;
+        }
+
+        // The gateway is recognised by where it connects from and its
+        // password. The name it gives is only logged.
+        let config = ctx.network().config();
+        let peer = ctx.session().client_addr().ip().to_string();
+        let Some(gateway) = config
+            .gateways
+            .iter()
+            .find(|g| g.hosts.iter().any(|host| peer.as_str().matches_mask(host)) && g.check_password(password))
+        else {
+            tracing::warn!(peer, name, "WEBIRC refused");
+            return ctx.fail("WEBIRC", "INVALID_CREDENTIALS", "WEBIRC is not allowed from this host").await;
+        };
+
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            return ctx.fail("WEBIRC", "INVALID_PARAMS", "Invalid IP address").await;
+        };
+
+        let secure = options.split_ascii_whitespace().any(|option| option == "secure");
+        let hostname = Self::valid_hostname(hostname).then_some(hostname);
+
+        tracing::info!(gateway = gateway.name, peer, %ip, hostname, "WEBIRC");
+        ctx.session_mut().set_gateway_client(&gateway.name, ip, hostname, secure);
+
+        Ok(())
+    }
+
+    /// Whether a hostname can be shown in `nick!user@host`. Gateways
+    /// that could not resolve one send the IP address instead.
+    fn valid_hostname(hostname: &str) -> bool {
+        hostname.len() <= HOSTLEN
+            && hostname.parse::<IpAddr>().is_err()
+            && !hostname.starts_with(['-', '.'])
+            && hostname.contains('.')
+            && hostname.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-')
+    }
+}
@@ -1,6 +1,13 @@
+use std::collections::HashSet;
+
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    ext::StrExt,
+    irc::{GenericStateExt, IrcContext, Profile, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Who;
 
@@ -20,10 +27,10 @@ impl CommandHandler<state::Registered> for Who {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +39,93 @@ impl CommandHandler<state::Authenticated> for Who {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Who {
+    /// `WHO <mask>`. A channel lists its members, anything else is
+    /// matched against the nick of every user. `0` and `*` match all.
+    /// Users with mode +i are only shown to those sharing a channel.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+        let mask = params.middles.first().or(params.trailing.raw()).filter(|m| !m.is_empty()).unwrap_or("*");
+        let mask = mask.slice_at_most(64);
+
+        if mask.starts_with('#') {
+            let key = mask.irc_casefold();
+            let member = ctx.is_subscribed(&key);
+
+            if let Some((channel, roster)) = ctx.network().channels().roster(&key) {
+                for (nick, op) in roster {
+                    if let Some(profile) = ctx.network().profile(&nick).filter(|p| member || !p.invisible) {
+                        Self::send_entry(ctx, &channel, &profile, op).await?;
+                    }
+                }
+            }
+        } else {
+            let pattern = if mask == "0" { "*" } else { mask };
+            let peers = Self::peers(ctx);
+            let mut profiles = ctx.network().profiles();
+            profiles.retain(|profile| {
+                profile.nick.as_str().matches_mask(pattern)
+                    && (!profile.invisible || peers.contains(&profile.nick.as_str().irc_casefold()))
+            });
+            profiles.sort_by(|a, b| a.nick.cmp(&b.nick));
+
+            for profile in profiles {
+                Self::send_entry(ctx, "*", &profile, false).await?;
+            }
+        }
+
+        ctx.reply(Reply::EndOfWho { mask }).await
+    }
+
+    /// Casefolded nicks of everyone sharing a channel with the client,
+    /// itself included.
+    fn peers<'a, T: GenericStateExt, S: Storage>(ctx: &IrcContext<'a, T, S>) -> HashSet<String> {
+        let mut peers = HashSet::from([ctx.nick().irc_casefold()]);
+        for key in ctx.subscriptions() {
+            if let Some((_, roster)) = ctx.network().channels().roster(&key) {
+                peers.extend(roster.into_iter().map(|(nick, _)| nick.as_str().irc_casefold()));
+            }
+        }
+
+        peers
+    }
+
+    /// Send `RPL_WHOREPLY` for one user. The flags tell whether the
+    /// user is here (`H`) or gone (`G`), an oper (`*`) and a channel
+    /// operator (`@`).
+    async fn send_entry<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        channel: &str,
+        profile: &Profile,
+        op: bool,
+    ) -> IrcResult<()> {
+        let mut flags = String::from(if profile.away.is_some() { "G" } else { "H" });
+        if profile.oper {
+            flags.push('*');
+        }
+        if op {
+            flags.push('@');
+        }
+
+        ctx.reply(Reply::WhoReply {
+            channel,
+            user: &profile.user,
+            host: &profile.host,
+            nick: &profile.nick,
+            flags: &flags,
+            real: &profile.real,
+        })
+        .await
+    }
+}
@@ -1,6 +1,10 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Whois;
 
@@ -20,10 +24,10 @@ impl CommandHandler<state::Registered> for Whois {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +36,78 @@ impl CommandHandler<state::Authenticated> for Whois {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
+}
+
+impl Whois {
+    /// `WHOIS [<server>] <nick>`. The certificate fingerprint of a
+    /// client is only shown to itself and to opers.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(nick) = params.middles.iter().chain(params.trailing.raw()).last().filter(|n| !n.is_empty()) else {
+            return ctx.reply(Reply::NoNicknameGiven).await;
+        };
+
+        let Some(profile) = ctx.network().profile(nick) else {
+            ctx.reply(Reply::NoSuchNick { target: nick }).await?;
+            return ctx.reply(Reply::EndOfWhois { nick }).await;
+        };
+
+        let nick = profile.nick.as_str();
+        ctx.reply(Reply::WhoisUser {
+            nick,
+            user: &profile.user,
+            host: &profile.host,
+            real: &profile.real,
+        })
+        .await?;
+
+        let (server, info) = (ctx.network().name().to_owned(), ctx.network().network().to_owned());
+        ctx.reply(Reply::WhoisServer { nick, server: &server, info: &info }).await?;
+
+        if let Some(text) = &profile.away {
+            ctx.reply(Reply::Away { nick, text }).await?;
+        }
+
+        let registered = match ctx.storage().whois(nick).await {
+            Ok(whois) => whois.is_some(),
+            Err(_) => {
+                tracing::warn!("storage backend failed to look up a registered nick");
+                false
+            }
+        };
+
+        if registered {
+            ctx.reply(Reply::WhoisRegNick { nick }).await?;
+        }
+
+        if profile.oper {
+            ctx.reply(Reply::WhoisOperator { nick }).await?;
+        }
+
+        if let Some(account) = &profile.account {
+            ctx.reply(Reply::WhoisAccount { nick, account }).await?;
+        }
+
+        if profile.secure {
+            ctx.reply(Reply::WhoisSecure { nick }).await?;
+        }
+
+        let own = ctx.session().identity().is_some_and(|me| me.nick == profile.nick);
+        let privileged = own || ctx.session().oper().is_some();
+        if let Some(fingerprint) = profile.certfp.as_deref().filter(|_| privileged) {
+            ctx.reply(Reply::WhoisCertfp { nick, fingerprint }).await?;
+        }
+
+        ctx.reply(Reply::EndOfWhois { nick }).await
+    }
 }
\ No newline at end of file
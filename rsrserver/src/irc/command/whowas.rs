@@ -1,9 +1,16 @@
 use ircv3_parse::Message;
 
-use crate::{error::IrcResult, irc::{IrcContext, command::CommandHandler, state}, storage::Storage};
+use crate::{
+    error::IrcResult,
+    irc::{GenericStateExt, IrcContext, Reply, command::CommandHandler, state},
+    storage::Storage,
+};
 
 pub struct Whowas;
 
+/// Entries returned when the client does not ask for a count.
+const DEFAULT_COUNT: usize = 8;
+
 impl CommandHandler<state::Anonymous> for Whowas {
     type Contract = state::Anonymous;
 
@@ -20,10 +27,10 @@ impl CommandHandler<state::Registered> for Whowas {
     type Contract = state::Registered;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Registered, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Registered, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
 }
@@ -32,10 +39,53 @@ impl CommandHandler<state::Authenticated> for Whowas {
     type Contract = state::Authenticated;
 
     async fn handle<'a, S: Storage>(
-        ctx: IrcContext<'a, state::Authenticated, S>,
-        _msg: Message<'a>,
+        mut ctx: IrcContext<'a, state::Authenticated, S>,
+        msg: Message<'a>,
     ) -> IrcResult<impl Into<Self::Contract>> {
-        todo!();
+        Self::handle_inner(&mut ctx, &msg).await?;
         Ok(ctx)
     }
-}
\ No newline at end of file
+}
+
+impl Whowas {
+    /// `WHOWAS <nick> [<count>]`. A count of zero or less, or one that
+    /// does not parse, returns the default number of entries.
+    async fn handle_inner<'a, T: GenericStateExt, S: Storage>(
+        ctx: &mut IrcContext<'a, T, S>,
+        msg: &Message<'a>,
+    ) -> IrcResult<()> {
+        let params = msg.params();
+
+        let Some(nick) = params.middles.first().or(params.trailing.raw()).filter(|n| !n.is_empty()) else {
+            return ctx.reply(Reply::NoNicknameGiven).await;
+        };
+
+        let count = params
+            .middles
+            .second()
+            .and_then(|count| count.parse::<usize>().ok())
+            .filter(|count| *count > 0)
+            .unwrap_or(DEFAULT_COUNT);
+
+        let entries = ctx.network().whowas(nick, count);
+        if entries.is_empty() {
+            ctx.reply(Reply::WasNoSuchNick { nick }).await?;
+        }
+
+        let server = ctx.network().name().to_owned();
+        for entry in entries {
+            ctx.reply(Reply::WhowasUser {
+                nick: &entry.nick,
+                user: &entry.user,
+                host: &entry.host,
+                real: &entry.real,
+            })
+            .await?;
+
+            let left = entry.left.to_rfc2822();
+            ctx.reply(Reply::WhoisServer { nick: &entry.nick, server: &server, info: &left }).await?;
+        }
+
+        ctx.reply(Reply::EndOfWhowas { nick }).await
+    }
+}
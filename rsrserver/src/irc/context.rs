@@ -1,16 +1,20 @@
 use std::{
+    collections::HashMap,
     ops::{Deref, DerefMut},
-    time::Duration,
+    sync::Arc,
 };
 
-use ircv3_parse::Message;
-use tokio::{io::AsyncWriteExt, time::Instant};
-use tokio_stream::StreamMap;
+use bytes::Bytes;
+use tokio::time::Instant;
+use tokio_stream::{StreamMap, wrappers::BroadcastStream};
 
 use crate::{
+    config::Privilege,
     error::{IrcResult, IrcSessionError},
-    ext::StrExt,
-    irc::{ChannelName, ChannelSink, ClientSink, IrcSession, ServerSink, state},
+    irc::{
+        Capabilities, ChannelName, ChannelSink, ChannelSource, ClientSink, IrcSession, Network, Reply,
+        ServerMessage, ServerSink, network::NICKLEN, reply::adapt, state,
+    },
     storage::Storage,
 };
 
@@ -26,32 +30,40 @@ use crate::{
 /// [IrcContext::]
 pub struct IrcContext<'a, T, S> {
     storage: &'a S,
+    network: &'a Network,
     session: &'a mut IrcSession,
 
     /// Stream back to Client
     r_tx: &'a mut ClientSink,
     /// Stream back to Server
     s_tx: &'a mut ServerSink,
+    /// Streams from Channels
+    c_rx: &'a mut StreamMap<ChannelName, ChannelSource>,
     /// Streams back to Channels
-    c_tx: &'a mut StreamMap<ChannelName, ChannelSink>,
+    c_tx: &'a mut HashMap<ChannelName, ChannelSink>,
 
     typestate: T,
 }
 
 impl<'a, T, S> IrcContext<'a, T, S> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         storage: &'a S,
+        network: &'a Network,
         session: &'a mut IrcSession,
         r_tx: &'a mut ClientSink,
         s_tx: &'a mut ServerSink,
-        c_tx: &'a mut StreamMap<ChannelName, ChannelSink>,
+        c_rx: &'a mut StreamMap<ChannelName, ChannelSource>,
+        c_tx: &'a mut HashMap<ChannelName, ChannelSink>,
         state: T,
     ) -> Self {
         Self {
             storage,
+            network,
             session,
             r_tx,
             s_tx,
+            c_rx,
             c_tx,
             typestate: state,
         }
@@ -69,13 +81,30 @@ impl<'a, T, S> IrcContext<'a, T, S> {
     pub fn transition<U>(self, new: U) -> IrcContext<'a, U, S> {
         IrcContext {
             storage: self.storage,
+            network: self.network,
             session: self.session,
             r_tx: self.r_tx,
             s_tx: self.s_tx,
+            c_rx: self.c_rx,
             c_tx: self.c_tx,
             typestate: new,
         }
     }
+
+    /// Like [IrcContext::transition], but building the new state out
+    /// of the current one.
+    pub fn transition_with<U>(self, f: impl FnOnce(T) -> U) -> IrcContext<'a, U, S> {
+        IrcContext {
+            storage: self.storage,
+            network: self.network,
+            session: self.session,
+            r_tx: self.r_tx,
+            s_tx: self.s_tx,
+            c_rx: self.c_rx,
+            c_tx: self.c_tx,
+            typestate: f(self.typestate),
+        }
+    }
 }
 
 impl<T, S> IrcContext<'_, T, S>
@@ -85,25 +114,117 @@ impl<T, S> IrcContext<'_, T, S>
     }
 
     pub fn session(&self) -> &IrcSession {
-        &self.session
+        self.session
     }
 
     pub fn session_mut(&mut self) -> &mut IrcSession {
-        &mut self.session
+        self.session
     }
 
     pub fn storage(&self) -> &S {
-        &self.storage
+        self.storage
     }
 
-    pub async fn send_client_unchecked<'a>(&'a mut self, msg: impl AsRef<[u8]>) -> IrcResult<()> {
-        self.r_tx.write_all(msg.as_ref()).await?;
-        self.r_tx.flush().await?;
-        Ok(())
+    pub fn network(&self) -> &Network {
+        self.network
+    }
+
+    /// Whether this client is an oper whose class grants `privilege`.
+    pub fn has_privilege(&self, privilege: Privilege) -> bool {
+        self.session
+            .oper()
+            .is_some_and(|oper| self.network.config().has_privilege(oper, privilege))
+    }
+
+    /// Send a message to every connection on the server, including
+    /// this one.
+    pub fn broadcast_server(&self, msg: ServerMessage) {
+        if let Some(s_tx) = self.s_tx.upgrade() {
+            let _ = s_tx.send(msg);
+        }
+    }
+
+    /// Send `CAP NEW`/`CAP DEL` to every connection after the set of
+    /// capabilities advertised by the server has changed.
+    pub fn notify_cap_changes(&self, changes: (Capabilities, Capabilities)) {
+        for msg in ServerMessage::cap_changes(changes) {
+            self.broadcast_server(msg);
+        }
+    }
+
+    /// Start receiving messages broadcast to a channel. `key` is the
+    /// casefolded channel name.
+    pub fn subscribe(&mut self, key: ChannelName, tx: ChannelSink) {
+        self.c_rx.insert(Arc::clone(&key), BroadcastStream::new(tx.subscribe()));
+        self.c_tx.insert(key, tx);
+    }
+
+    /// Stop receiving messages broadcast to a channel, dropping our
+    /// handle on it. Returns false if we were not subscribed.
+    pub fn unsubscribe(&mut self, key: &str) -> bool {
+        self.c_rx.remove(key);
+        self.c_tx.remove(key).is_some()
+    }
+
+    /// Stop listening to a channel once we are no longer a member of
+    /// it, which happens after being kicked.
+    pub fn leave_if_kicked(&mut self, key: &str) {
+        if self.network.channels().is_member(self.session.id(), key) {
+            return;
+        }
+
+        self.unsubscribe(key);
+        self.network.channels().collect_garbage(key);
+    }
+
+    pub fn is_subscribed(&self, key: &str) -> bool {
+        self.c_tx.contains_key(key)
+    }
+
+    /// Casefolded names of every channel this connection is in.
+    pub fn subscriptions(&self) -> Vec<ChannelName> {
+        self.c_tx.keys().cloned().collect()
+    }
+
+    /// Broadcast a raw line to every member of a channel we are in,
+    /// including ourselves.
+    pub fn send_channel(&self, key: &str, line: impl Into<Bytes>) {
+        if let Some(tx) = self.c_tx.get(key) {
+            // An error only means nobody is listening.
+            let _ = tx.send(Arc::new(line.into()));
+        }
+    }
+
+    /// Broadcast a raw line to every other member of a channel we are in.
+    pub fn send_channel_others(&mut self, key: &str, line: impl Into<Bytes>) {
+        if let Some(tx) = self.c_tx.get(key) {
+            let line = Arc::new(line.into());
+            self.session.expect_echo(&line);
+            let _ = tx.send(line);
+        }
+    }
+
+    /// Send a raw line to every client sharing at least one channel with
+    /// us, exactly once each. Used for messages such as NICK and QUIT that
+    /// are about the client rather than any one channel.
+    pub fn send_peers(&self, line: impl Into<Bytes>) {
+        let channels = self.c_tx.keys().map(|key| &**key);
+        self.network.send_peers(self.session.id(), channels, Arc::new(line.into()));
     }
 
-    pub async fn send_client<'a>(&'a mut self, msg: &'a Message<'a>) -> IrcResult<()> {
-        self.send_client_unchecked(msg.input_raw()).await
+    /// Queue raw bytes for the client. Fails once the client is too
+    /// far behind on reading what it was sent.
+    pub async fn send_client_unchecked(&mut self, msg: impl AsRef<[u8]>) -> IrcResult<()> {
+        self.r_tx.push(Bytes::copy_from_slice(msg.as_ref()))
+    }
+
+    /// Send a relayed line to the client, adapted to the capabilities
+    /// it has enabled.
+    pub async fn deliver(&mut self, line: &str) -> IrcResult<()> {
+        match adapt(line, *self.session.caps()) {
+            Some(line) => self.send_client_unchecked(line).await,
+            None => Ok(()),
+        }
     }
 
     pub async fn ping_keepalive(&mut self) -> IrcResult<()> {
@@ -119,11 +240,11 @@ impl<T, S> IrcContext<'_, T, S>
             }
             None => {
                 // No awaiting ping, so send one out.
-                let deadline = Instant::now() + Duration::from_secs(8);
+                let deadline = Instant::now() + self.network.config().limits.ping_timeout();
                 let nonce: u64 = rand::random();
-                self.send_client_unchecked("PING ").await?;
-                self.send_client_unchecked(nonce.to_string()).await?;
-                self.send_client_unchecked("\r\n").await?;
+                // PING carries no nick, so this works before registration too.
+                let line = Reply::Ping { token: &nonce.to_string() }.render(self.network.name(), "*");
+                self.send_client_unchecked(line).await?;
 
                 *self.session.ping_deadline() = Some((deadline, nonce));
 
@@ -132,8 +253,25 @@ impl<T, S> IrcContext<'_, T, S>
         }
     }
 
-    pub fn validate_nick(&self, _nick: &str) -> Result<(), &str> {
-        // TODO: Nick validaton rules.
+    pub fn validate_nick(&self, nick: &str) -> Result<(), &'static str> {
+        const SPECIAL: &[char] = &['[', ']', '\\', '`', '_', '^', '{', '|', '}', '-'];
+
+        let Some(first) = nick.chars().next() else {
+            return Err("Erroneous nickname");
+        };
+
+        if nick.len() > NICKLEN {
+            return Err("Nickname too long");
+        }
+
+        if first.is_ascii_digit() || first == '-' {
+            return Err("Erroneous nickname");
+        }
+
+        if !nick.chars().all(|c| c.is_ascii_alphanumeric() || SPECIAL.contains(&c)) {
+            return Err("Erroneous nickname");
+        }
+
         Ok(())
     }
 }
@@ -146,6 +284,9 @@ pub trait GenericStateExt {
     fn user(&self) -> &str;
     fn real(&self) -> &str;
     fn away(&self) -> Option<&str>;
+
+    /// Whether the client has completed registration.
+    fn registered(&self) -> bool;
 }
 
 impl GenericStateExt for state::Anonymous {
@@ -164,6 +305,10 @@ impl GenericStateExt for state::Anonymous {
     fn away(&self) -> Option<&str> {
         None
     }
+
+    fn registered(&self) -> bool {
+        false
+    }
 }
 
 impl GenericStateExt for state::Registered {
@@ -182,6 +327,10 @@ impl GenericStateExt for state::Registered {
     fn away(&self) -> Option<&str> {
         self.away.as_deref()
     }
+
+    fn registered(&self) -> bool {
+        true
+    }
 }
 
 impl GenericStateExt for state::Authenticated {
@@ -200,6 +349,10 @@ impl GenericStateExt for state::Authenticated {
     fn away(&self) -> Option<&str> {
         self.away.as_deref()
     }
+
+    fn registered(&self) -> bool {
+        true
+    }
 }
 
 impl<'a, T, S> IrcContext<'a, T, S>
@@ -207,25 +360,59 @@ where
     S: Storage,
     T: GenericStateExt
 {
-    pub async fn unknown_command(&mut self, cmd: &str) -> IrcResult<()> {
-        let nick = self.typestate.nick();
-        let nick = nick.slice_at_most(40);
-        let cmd = cmd.slice_at_most(512 - 70);
+    /// The `nick!user@host` prefix of messages sent on behalf of this client.
+    pub fn source(&self) -> String {
+        format!("{}!{}@{}", self.nick(), self.user(), self.session.host())
+    }
 
-        let msg = format!(":* 421 {nick} {cmd} :Unknown command\r\n");
-        self.r_tx.write_all(msg.as_ref()).await?;
-        self.r_tx.flush().await?;
-        Ok(())
+    /// Send a numeric or message from this server to the client.
+    pub async fn reply(&mut self, reply: Reply<'_>) -> IrcResult<()> {
+        let line = reply.render(self.network.name(), self.typestate.nick());
+        self.send_client_unchecked(line).await
+    }
+
+    /// Send a `FAIL` standard reply, or a NOTICE with the same
+    /// description to clients without `standard-replies`.
+    pub async fn fail(&mut self, command: &str, code: &str, description: &str) -> IrcResult<()> {
+        match self.session.caps().contains(Capabilities::CapStandardReplies) {
+            true => self.reply(Reply::Fail { command, code, description }).await,
+            false => self.reply(Reply::Notice { text: description }).await,
+        }
+    }
+
+    /// Send a `NOTE` standard reply, or a NOTICE with the same
+    /// description to clients without `standard-replies`.
+    pub async fn note(&mut self, command: &str, code: &str, description: &str) -> IrcResult<()> {
+        match self.session.caps().contains(Capabilities::CapStandardReplies) {
+            true => self.reply(Reply::Note { command, code, description }).await,
+            false => self.reply(Reply::Notice { text: description }).await,
+        }
+    }
+
+    /// Tell the client it fell too far behind on `channel` and missed
+    /// `missed` of its messages.
+    pub async fn channel_lagged(&mut self, channel: &str, missed: u64) -> IrcResult<()> {
+        tracing::warn!(id = ?self.session.id(), channel, missed, "channel lagged");
+        self.network.record_channel_lag(missed);
+
+        let text = format!("{missed} messages in {channel} were lost because you fell behind");
+        self.note("*", "MESSAGES_LOST", &text).await
+    }
+
+    pub async fn unknown_command(&mut self, cmd: &str) -> IrcResult<()> {
+        self.reply(Reply::UnknownCommand { command: cmd }).await
     }
 
     pub async fn registration_required(&mut self) -> IrcResult<()> {
-        let nick = self.typestate.nick();
-        let nick = nick.slice_at_most(40);
-        
-        let msg = format!(":* 451 {nick} :Registration is required\r\n");
-        self.r_tx.write_all(msg.as_ref()).await?;
-        self.r_tx.flush().await?;
-        Ok(())
+        self.reply(Reply::NotRegistered).await
+    }
+
+    pub async fn need_more_params(&mut self, cmd: &str) -> IrcResult<()> {
+        self.reply(Reply::NeedMoreParams { command: cmd }).await
+    }
+
+    pub async fn already_registered(&mut self) -> IrcResult<()> {
+        self.reply(Reply::AlreadyRegistered).await
     }
 }
 
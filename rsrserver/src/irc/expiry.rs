@@ -0,0 +1,90 @@
+use tokio::time::Instant;
+
+use crate::{
+    error::IrcResult,
+    irc::{
+        IrcContext, Relay, Reply,
+        state::{self, MaybeTransition, New, Old, StateInto},
+    },
+    storage::Storage,
+};
+
+/// How a typestate reacts to the passage of time on its PDS grant.
+///
+/// The connection loop waits for [GrantExpiry::grant_timer] alongside
+/// every other signal, and hands the context to
+/// [GrantExpiry::on_grant_timer] once it fires. Only
+/// [state::Authenticated] holds a grant, so for the other states the
+/// timer never fires.
+pub trait GrantExpiry<U>: StateInto<U> + Sized {
+    /// When [GrantExpiry::on_grant_timer] must next be called, if ever.
+    fn grant_timer(&self, warning: std::time::Duration) -> Option<Instant>;
+
+    fn on_grant_timer<'a, S: Storage>(
+        ctx: IrcContext<'a, Self, S>,
+    ) -> impl Future<Output = IrcResult<MaybeTransition<Self, U>>>;
+}
+
+impl GrantExpiry<state::Registered> for state::Anonymous {
+    fn grant_timer(&self, _warning: std::time::Duration) -> Option<Instant> {
+        None
+    }
+
+    async fn on_grant_timer<'a, S: Storage>(
+        ctx: IrcContext<'a, Self, S>,
+    ) -> IrcResult<MaybeTransition<Self, state::Registered>> {
+        Ok(Old(ctx).into())
+    }
+}
+
+impl GrantExpiry<state::Authenticated> for state::Registered {
+    fn grant_timer(&self, _warning: std::time::Duration) -> Option<Instant> {
+        None
+    }
+
+    async fn on_grant_timer<'a, S: Storage>(
+        ctx: IrcContext<'a, Self, S>,
+    ) -> IrcResult<MaybeTransition<Self, state::Authenticated>> {
+        Ok(Old(ctx).into())
+    }
+}
+
+impl GrantExpiry<state::Registered> for state::Authenticated {
+    fn grant_timer(&self, warning: std::time::Duration) -> Option<Instant> {
+        match self.warned {
+            true => Some(self.expires),
+            false => Some(self.expires.checked_sub(warning).unwrap_or(self.expires)),
+        }
+    }
+
+    /// Warn the client once the grant is about to run out, and log it
+    /// out when it does, unless it was refreshed through AUTHENTICATE
+    /// in the meantime.
+    async fn on_grant_timer<'a, S: Storage>(
+        mut ctx: IrcContext<'a, Self, S>,
+    ) -> IrcResult<MaybeTransition<Self, state::Registered>> {
+        let now = Instant::now();
+
+        if now < ctx.expires {
+            let left = (ctx.expires - now).as_secs();
+            let description = format!(
+                "Your PDS grant expires in {left} seconds, renew it with AUTHENTICATE OAUTHBEARER"
+            );
+
+            ctx.note("AUTHENTICATE", "GRANT_EXPIRING", &description).await?;
+            ctx.warned = true;
+            return Ok(Old(ctx).into());
+        }
+
+        let source = ctx.source();
+        tracing::info!(account = ctx.session().account(), source, "PDS grant expired");
+
+        ctx.session_mut().clear_account();
+        ctx.send_peers(Relay::Account { account: None }.render(&source));
+
+        ctx.fail("AUTHENTICATE", "GRANT_EXPIRED", "Your PDS grant has expired").await?;
+        ctx.reply(Reply::LoggedOut { source: &source }).await?;
+
+        Ok(New(ctx.transition_with(state::Authenticated::expire)).into())
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::FloodConfig;
+
+/// A token bucket kept as the moment by which every line read so far
+/// is paid for, at one line per `1 / rate` seconds. A client that has
+/// been quiet for long enough, or has only just connected, has `burst`
+/// lines in credit.
+#[derive(Debug, Default)]
+pub struct FakeLag {
+    paid_until: Option<Instant>,
+}
+
+impl FakeLag {
+
+    /// Charge a line of `command`, returning when it may be processed.
+    /// None if that is more than `max_lag` away, in which case the
+    /// client is flooding.
+    pub fn charge(&mut self, config: &FloodConfig, command: &str) -> Option<Instant> {
+        let now = Instant::now();
+        let line = Duration::from_secs_f64(1.0 / config.rate);
+
+        let credit = now.checked_sub(line * config.burst).unwrap_or(now);
+        let paid_until = self.paid_until.map_or(credit, |paid_until| paid_until.max(credit)) + line * config.cost(command);
+        self.paid_until = Some(paid_until);
+
+        (paid_until <= now + config.max_lag()).then(|| paid_until.max(now))
+    }
+}
+
+/// The command of a raw line, without parsing the rest of it.
+pub fn command_of(line: &[u8]) -> &str {
+    let mut words = line.split(|b| *b == b' ').filter(|word| !word.is_empty());
+    let command = words
+        .find(|word| !word.starts_with(b"@") && !word.starts_with(b":"))
+        .unwrap_or_default();
+
+    str::from_utf8(command).unwrap_or_default().trim_end_matches(['\r', '\n'])
+}
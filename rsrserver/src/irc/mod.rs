@@ -11,20 +11,58 @@ pub use context::*;
 mod capability;
 pub use capability::*;
 
+mod network;
+pub use network::{ClientId, Control, Network, Profile, Whowas};
+
+mod channel;
+pub use channel::{Channels, JoinError};
+
+mod registration;
+
+mod ban;
+pub use ban::Bans;
+
+mod expiry;
+pub use expiry::GrantExpiry;
+
+mod flood;
+
+mod reply;
+pub use reply::{Relay, Reply};
+
+mod bus;
+pub use bus::ServerMessage;
+
+mod transport;
+pub use transport::Transport;
+
+mod sasl;
+pub use sasl::SaslExchange;
+
+mod sendq;
+pub use sendq::SendQueue;
+
 pub mod command;
 
+#[cfg(test)]
+mod tests;
+
 use std::sync::Arc;
 use bytes::Bytes;
-use tokio::io::BufReader;
 
-type ClientSource = BufReader<tokio::io::ReadHalf<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>>;
-type ClientSink = tokio::io::WriteHalf<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>;
+type ClientSource<IO> = tokio::io::BufReader<tokio::io::ReadHalf<IO>>;
+type ClientSink = SendQueue;
 
 type ServerSource = tokio::sync::broadcast::Receiver<ServerMessage>;
 type ServerSink = tokio::sync::broadcast::WeakSender<ServerMessage>;
 
+type DirectSource = tokio::sync::mpsc::Receiver<Arc<Bytes>>;
+type DirectSink = tokio::sync::mpsc::Sender<Arc<Bytes>>;
+
+type ControlSource = tokio::sync::mpsc::UnboundedReceiver<Control>;
+type ControlSink = tokio::sync::mpsc::UnboundedSender<Control>;
+
 type ChannelSource = tokio_stream::wrappers::BroadcastStream<Arc<Bytes>>;
-type ChannelSink = tokio::sync::broadcast::WeakSender<Arc<Bytes>>;
+type ChannelSink = tokio::sync::broadcast::Sender<Arc<Bytes>>;
 
 pub type ChannelName = Arc<str>;
-pub type ServerMessage = ();
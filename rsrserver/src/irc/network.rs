@@ -0,0 +1,478 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, mapref::entry::Entry};
+use tokio::sync::mpsc::error::TrySendError;
+
+use crate::{
+    config::Config,
+    did::DidResolver,
+    error::ConfigError,
+    ext::StrExt,
+    irc::{
+        Bans, CapRegistry, Capabilities, Channels, ControlSink, DirectSink,
+        channel::{CHANLIMIT, CHANNELLEN, KEYLEN, TOPICLEN},
+    },
+};
+
+/// Maximum nickname length advertised through `NICKLEN`.
+pub const NICKLEN: usize = 30;
+
+/// Maximum username length advertised through `USERLEN`.
+pub const USERLEN: usize = 16;
+
+/// Maximum away message length advertised through `AWAYLEN`.
+pub const AWAYLEN: usize = 200;
+
+/// User modes advertised in `RPL_MYINFO`.
+pub const USER_MODES: &str = "iw";
+
+/// Number of direct messages buffered for a client. Its connection
+/// moves them onto its [SendQueue](crate::irc::SendQueue) as they
+/// arrive, so only a connection that has stopped making progress ever
+/// fills it, and is disconnected rather than silently losing messages.
+pub const DIRECT_CAPACITY: usize = 256;
+
+/// Number of departed clients remembered for WHOWAS.
+pub const WHOWAS_LENGTH: usize = 1024;
+
+/// Unique, never reused identifier of a single client connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(u64);
+
+/// Server-wide state shared between every [crate::irc::IrcConnection].
+///
+/// Connections only ever hold this behind an `Arc`, so all
+/// bookkeeping here is either atomic, sharded through [DashMap], or
+/// behind a lock that is never held across an await.
+pub struct Network {
+    name: Box<str>,
+    network: Box<str>,
+    created: DateTime<Utc>,
+
+    // Swapped out as a whole on rehash.
+    config: RwLock<Arc<Config>>,
+
+    caps: CapRegistry,
+    channels: Channels,
+    // Shared with the listeners, which check IP bans on accept.
+    bans: Arc<Bans>,
+
+    // Looks up the DID documents of users logging in through OAUTHBEARER.
+    resolver: Option<Box<dyn DidResolver>>,
+
+    next_id: AtomicU64,
+    connections: AtomicUsize,
+    users: AtomicUsize,
+    max_users: AtomicUsize,
+
+    // Channel broadcasts dropped because a member fell behind.
+    lag_events: AtomicU64,
+    lost_messages: AtomicU64,
+
+    // Casefolded nick -> owning connection.
+    nicks: DashMap<Box<str>, ClientId>,
+    clients: DashMap<ClientId, ClientEntry>,
+
+    // Most recently departed clients last.
+    whowas: Mutex<VecDeque<Whowas>>,
+}
+
+/// A client that has left, as shown by WHOWAS.
+#[derive(Debug, Clone)]
+pub struct Whowas {
+    pub nick: String,
+    pub user: String,
+    pub host: String,
+    pub real: String,
+    pub left: DateTime<Utc>,
+}
+
+/// A registered client, as shown by WHOIS.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub nick: String,
+    pub user: String,
+    pub host: String,
+    pub real: String,
+    pub account: Option<String>,
+    pub away: Option<String>,
+    pub oper: bool,
+    pub invisible: bool,
+    pub secure: bool,
+    pub certfp: Option<String>,
+}
+
+/// An order for a single connection. Unlike lines sent through
+/// [Network::send_direct], these are never dropped.
+#[derive(Debug)]
+pub enum Control {
+    /// Disconnect on behalf of an oper.
+    Kill {
+        /// `nick!user@host` of the oper.
+        source: Arc<str>,
+        reason: Arc<str>,
+    },
+    /// Disconnect because the queue of direct messages is full.
+    SendQExceeded,
+}
+
+struct ClientEntry {
+    nick: Option<Box<str>>,
+    registered: bool,
+    tx: DirectSink,
+    ctl: ControlSink,
+    // Published by the connection whenever it changes.
+    profile: Option<Arc<Profile>>,
+}
+
+impl Network {
+    pub fn new(config: Config) -> Result<Self, ConfigError> {
+        Ok(Self {
+            name: config.server.name.as_str().into(),
+            network: config.server.network.as_str().into(),
+            created: Utc::now(),
+            caps: CapRegistry::new(&config.caps)?,
+            channels: Channels::new(config.limits.channel_capacity),
+            config: RwLock::new(Arc::new(config)),
+            bans: Arc::new(Bans::default()),
+            resolver: None,
+            next_id: AtomicU64::new(0),
+            connections: AtomicUsize::new(0),
+            users: AtomicUsize::new(0),
+            max_users: AtomicUsize::new(0),
+            lag_events: AtomicU64::new(0),
+            lost_messages: AtomicU64::new(0),
+            nicks: DashMap::new(),
+            clients: DashMap::new(),
+            whowas: Mutex::new(VecDeque::with_capacity(WHOWAS_LENGTH)),
+        })
+    }
+
+    /// Resolve DIDs through `resolver`, enabling OAUTHBEARER logins
+    /// and `rsr.chat/plc-oauthbearer`.
+    pub fn with_resolver(mut self, resolver: impl DidResolver + 'static) -> Self {
+        self.resolver = Some(Box::new(resolver));
+        self.caps.support(Capabilities::CapRsrvcPlcOauthbearer);
+        self
+    }
+
+    /// The name of this server, used as the prefix of
+    /// every server-originated message.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the IRC network this server belongs to.
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    /// The configuration currently in effect. Hold on to the returned
+    /// value rather than calling this repeatedly to see a consistent
+    /// view across a rehash.
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    /// Maximum number of targets of a single PRIVMSG or NOTICE.
+    pub fn targmax(&self) -> usize {
+        self.config().limits.targmax
+    }
+
+    /// Reload the configuration file this server was started with.
+    /// Returns the capabilities that were newly advertised and those
+    /// that were withdrawn, which connected clients must be told about.
+    pub async fn rehash(&self) -> Result<(Capabilities, Capabilities), ConfigError> {
+        let path = self.config().path.clone();
+
+        // Reading the file blocks, so keep it off the threads that
+        // serve connections.
+        let config = tokio::task::spawn_blocking(move || Config::load(&path))
+            .await
+            .expect("loading the configuration panicked")?;
+
+        self.reload(config)
+    }
+
+    /// Put a new configuration into effect. Nothing is changed if
+    /// any part of it can not be applied.
+    pub fn reload(&self, config: Config) -> Result<(Capabilities, Capabilities), ConfigError> {
+        if config.server.name != *self.name {
+            return Err(ConfigError::Immutable("server.name"));
+        }
+
+        if config.server.network != *self.network {
+            return Err(ConfigError::Immutable("server.network"));
+        }
+
+        let mut current = self.config.write().unwrap();
+
+        let changes = self.caps.apply(&config.caps)?;
+        self.channels.set_capacity(config.limits.channel_capacity);
+        *current = Arc::new(config);
+
+        Ok(changes)
+    }
+
+    pub fn resolver(&self) -> Option<&dyn DidResolver> {
+        self.resolver.as_deref()
+    }
+
+    pub fn created(&self) -> &DateTime<Utc> {
+        &self.created
+    }
+
+    /// Capabilities offered to clients, and their values.
+    pub fn caps(&self) -> &CapRegistry {
+        &self.caps
+    }
+
+    /// Every channel on the server.
+    pub fn channels(&self) -> &Channels {
+        &self.channels
+    }
+
+    /// Every server ban in effect.
+    pub fn bans(&self) -> &Arc<Bans> {
+        &self.bans
+    }
+
+    /// Number of open connections, registered or not.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections that have not yet completed registration.
+    pub fn unknown(&self) -> usize {
+        self.connections
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.users())
+    }
+
+    /// Number of registered connections.
+    pub fn users(&self) -> usize {
+        self.users.load(Ordering::Relaxed)
+    }
+
+    /// Number of registered connections with user mode +i.
+    pub fn invisible(&self) -> usize {
+        self.clients
+            .iter()
+            .filter(|entry| entry.registered && entry.profile.as_ref().is_some_and(|profile| profile.invisible))
+            .count()
+    }
+
+    /// Highest number of registered connections seen since startup.
+    pub fn max_users(&self) -> usize {
+        self.max_users.load(Ordering::Relaxed)
+    }
+
+    /// Count a connection falling behind on a channel and
+    /// missing `missed` of its messages.
+    pub fn record_channel_lag(&self, missed: u64) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.lost_messages.fetch_add(missed, Ordering::Relaxed);
+    }
+
+    /// How often connections fell behind on a channel since startup,
+    /// and how many messages they missed in total.
+    pub fn channel_lag(&self) -> (u64, u64) {
+        (self.lag_events.load(Ordering::Relaxed), self.lost_messages.load(Ordering::Relaxed))
+    }
+
+    /// Tokens sent in `RPL_ISUPPORT` as part of the welcome burst.
+    pub fn isupport(&self) -> Vec<String> {
+        vec![
+            format!("AWAYLEN={AWAYLEN}"),
+            "CASEMAPPING=rfc1459".to_owned(),
+            format!("CHANLIMIT=#:{CHANLIMIT}"),
+            "CHANMODES=,k,l,i".to_owned(),
+            format!("CHANNELLEN={CHANNELLEN}"),
+            "CHANTYPES=#".to_owned(),
+            format!("KEYLEN={KEYLEN}"),
+            format!("NETWORK={}", self.network),
+            format!("NICKLEN={NICKLEN}"),
+            "PREFIX=(o)@".to_owned(),
+            format!("TARGMAX=PRIVMSG:{0},NOTICE:{0}", self.targmax()),
+            format!("TOPICLEN={TOPICLEN}"),
+            format!("USERLEN={USERLEN}"),
+        ]
+    }
+
+    /// Allocate an identifier for a freshly accepted connection, which
+    /// receives messages addressed to it directly through `tx` and
+    /// orders through `ctl`. Every call must be paired with a call to
+    /// [Network::disconnect].
+    pub fn connect(&self, tx: DirectSink, ctl: ControlSink) -> ClientId {
+        let id = ClientId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.clients.insert(id, ClientEntry {
+            nick: None,
+            registered: false,
+            tx,
+            ctl,
+            profile: None,
+        });
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        id
+    }
+
+    /// Forget everything about the given connection, releasing its nick.
+    pub fn disconnect(&self, id: ClientId) {
+        let Some((_, entry)) = self.clients.remove(&id) else {
+            return;
+        };
+
+        if let Some(nick) = entry.nick {
+            self.nicks.remove_if(&nick, |_, owner| *owner == id);
+        }
+
+        if entry.registered {
+            self.users.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Find the registered connection currently using `nick`.
+    pub fn lookup(&self, nick: &str) -> Option<ClientId> {
+        let id = *self.nicks.get(nick.irc_casefold().as_str())?;
+        self.clients
+            .get(&id)
+            .filter(|entry| entry.registered)
+            .map(|_| id)
+    }
+
+    /// Replace what WHOIS shows about the given connection.
+    pub fn publish(&self, id: ClientId, profile: Profile) {
+        if let Some(mut entry) = self.clients.get_mut(&id) {
+            entry.profile = Some(Arc::new(profile));
+        }
+    }
+
+    /// What WHOIS shows about the registered connection using `nick`.
+    pub fn profile(&self, nick: &str) -> Option<Arc<Profile>> {
+        let id = self.lookup(nick)?;
+        self.clients.get(&id)?.profile.clone()
+    }
+
+    /// What WHOIS shows about every registered connection.
+    pub fn profiles(&self) -> Vec<Arc<Profile>> {
+        self.clients
+            .iter()
+            .filter(|entry| entry.registered)
+            .filter_map(|entry| entry.profile.clone())
+            .collect()
+    }
+
+    /// Queue a raw line for delivery to a single connection. Returns
+    /// false if the connection is gone. A connection that lets its
+    /// queue of direct messages fill up is disconnected, see
+    /// [DIRECT_CAPACITY].
+    pub fn send_direct(&self, id: ClientId, line: Arc<Bytes>) -> bool {
+        let Some(entry) = self.clients.get(&id) else {
+            return false;
+        };
+
+        match entry.tx.try_send(line) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!(?id, "direct message queue full, disconnecting");
+                let _ = entry.ctl.send(Control::SendQExceeded);
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Give an order to a single connection. Returns false if the
+    /// connection is gone.
+    pub fn control(&self, id: ClientId, order: Control) -> bool {
+        self.clients.get(&id).is_some_and(|entry| entry.ctl.send(order).is_ok())
+    }
+
+    /// Queue a raw line for every client sharing at least one of
+    /// `channels` with `id`, exactly once each.
+    pub fn send_peers<'c>(&self, id: ClientId, channels: impl Iterator<Item = &'c str>, line: Arc<Bytes>) {
+        let mut peers = channels
+            .flat_map(|key| self.channels.members(key))
+            .filter(|peer| *peer != id)
+            .collect::<Vec<_>>();
+        peers.sort_unstable();
+        peers.dedup();
+
+        for peer in peers {
+            self.send_direct(peer, Arc::clone(&line));
+        }
+    }
+
+    /// Remember a client that has left for WHOWAS, forgetting the
+    /// oldest one if the history is full.
+    pub fn record_whowas(&self, entry: Whowas) {
+        let mut whowas = self.whowas.lock().unwrap();
+        if whowas.len() >= WHOWAS_LENGTH {
+            whowas.pop_front();
+        }
+        whowas.push_back(entry);
+    }
+
+    /// Up to `count` departed clients that used `nick`, most recent first.
+    pub fn whowas(&self, nick: &str, count: usize) -> Vec<Whowas> {
+        let nick = nick.irc_casefold();
+
+        self.whowas
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|entry| entry.nick.as_str().irc_casefold() == nick)
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    /// Mark the connection as having completed registration.
+    pub fn register(&self, id: ClientId) {
+        let Some(mut entry) = self.clients.get_mut(&id) else {
+            return;
+        };
+
+        if !entry.registered {
+            entry.registered = true;
+            let users = self.users.fetch_add(1, Ordering::Relaxed) + 1;
+            self.max_users.fetch_max(users, Ordering::Relaxed);
+        }
+    }
+
+    /// Atomically claim `nick` for the given connection, releasing
+    /// any nick it previously held. Returns false if the nick is
+    /// already held by another connection.
+    pub fn claim_nick(&self, id: ClientId, nick: &str) -> bool {
+        let key: Box<str> = nick.irc_casefold().into();
+
+        match self.nicks.entry(key.clone()) {
+            Entry::Occupied(owner) if *owner.get() != id => return false,
+            Entry::Occupied(_) => {}
+            Entry::Vacant(v) => {
+                v.insert(id);
+            }
+        }
+
+        let old = self
+            .clients
+            .get_mut(&id)
+            .and_then(|mut entry| entry.nick.replace(key.clone()));
+
+        if let Some(old) = old.filter(|old| *old != key) {
+            self.nicks.remove_if(&old, |_, owner| *owner == id);
+        }
+
+        true
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    error::IrcResult,
+    irc::{
+        GenericStateExt, Identity, IrcContext, Reply,
+        command::{Lusers, Motd},
+        channel::CHANNEL_MODES,
+        network::USER_MODES,
+        state::{self, MaybeTransition, New, Old},
+    },
+    storage::Storage,
+};
+
+/// Version shown in `RPL_YOURHOST`, `RPL_MYINFO` and `RPL_VERSION`.
+pub const VERSION: &str = concat!("rsr-", env!("CARGO_PKG_VERSION"));
+
+impl<'a, S: Storage> IrcContext<'a, state::Anonymous, S> {
+    /// Complete registration if the client has supplied both NICK and
+    /// USER and is not in the middle of capability negotiation. On
+    /// success the welcome burst is sent and the connection transitions
+    /// into [state::Registered].
+    pub async fn try_register(mut self) -> IrcResult<MaybeTransition<state::Anonymous, state::Registered>> {
+        if self.cap_pending {
+            return Ok(Old(self).into());
+        }
+
+        let (Some(nick), Some(user)) = (self.nick.clone(), self.user.clone()) else {
+            return Ok(Old(self).into());
+        };
+        let real = self.real().to_owned();
+
+        // Everything a ban can match on is known by now.
+        self.check_bans().await?;
+
+        self.network().register(self.session().id());
+        self.session_mut().set_identity(Identity {
+            nick: nick.clone(),
+            user: user.clone(),
+            real: real.clone(),
+        });
+
+        let mut ctx = self.transition(state::Registered {
+            nick,
+            user,
+            real,
+            away: None,
+        });
+
+        ctx.welcome().await?;
+
+        Ok(New(ctx).into())
+    }
+}
+
+impl<'a, T, S> IrcContext<'a, T, S>
+where
+    T: GenericStateExt,
+    S: Storage,
+{
+    /// Send 001-005 followed by the LUSERS and MOTD replies, in the order
+    /// clients expect to see them once registration completes.
+    async fn welcome(&mut self) -> IrcResult<()> {
+        let network = self.network().network().to_owned();
+        let created = self.network().created().to_rfc2822();
+        let source = self.source();
+
+        self.reply(Reply::Welcome { network: &network, source: &source }).await?;
+        self.reply(Reply::YourHost { version: VERSION }).await?;
+        self.reply(Reply::Created { date: &created }).await?;
+        self.reply(Reply::MyInfo {
+            version: VERSION,
+            user_modes: USER_MODES,
+            channel_modes: CHANNEL_MODES,
+            param_modes: "klo",
+        })
+        .await?;
+        self.isupport().await?;
+
+        Lusers::send_lusers(self).await?;
+        Motd::send_motd(self).await?;
+
+        Ok(())
+    }
+
+    /// Send the `RPL_ISUPPORT` tokens of the server.
+    pub async fn isupport(&mut self) -> IrcResult<()> {
+        // Clients accept at most 13 tokens per RPL_ISUPPORT line.
+        for tokens in self.network().isupport().chunks(13) {
+            self.reply(Reply::ISupport { tokens }).await?;
+        }
+
+        Ok(())
+    }
+}
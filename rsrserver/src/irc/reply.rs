@@ -0,0 +1,593 @@
+use crate::{ext::StrExt, irc::Capabilities};
+
+/// Longest line sent to a client, not counting the trailing CRLF.
+const MAX_LINE: usize = 510;
+
+/// A line sent by this server to a single client, with one variant per
+/// numeric or message.
+///
+/// Replies are rendered through [Reply::render], which prefixes them
+/// with the server name and the client's nick, strips anything that
+/// would break the line apart and truncates it to fit in 512 bytes.
+// Variants are named after their numerics, `RPL_NAMREPLY` included.
+#[allow(clippy::enum_variant_names)]
+pub enum Reply<'r> {
+    /// `001 RPL_WELCOME`
+    Welcome { network: &'r str, source: &'r str },
+    /// `002 RPL_YOURHOST`
+    YourHost { version: &'r str },
+    /// `003 RPL_CREATED`
+    Created { date: &'r str },
+    /// `004 RPL_MYINFO`
+    MyInfo {
+        version: &'r str,
+        user_modes: &'r str,
+        channel_modes: &'r str,
+        param_modes: &'r str,
+    },
+    /// `005 RPL_ISUPPORT`
+    ISupport { tokens: &'r [String] },
+    /// `216 RPL_STATSKLINE`, listing bans of every kind.
+    StatsBan {
+        kind: &'r str,
+        mask: &'r str,
+        expires: i64,
+        set_by: &'r str,
+        reason: &'r str,
+    },
+    /// `219 RPL_ENDOFSTATS`
+    EndOfStats { query: &'r str },
+    /// `221 RPL_UMODEIS`
+    UModeIs { modes: &'r str },
+    /// `242 RPL_STATSUPTIME`
+    StatsUptime { seconds: i64 },
+    /// `249 RPL_STATSDEBUG`
+    StatsDebug { query: &'r str, text: &'r str },
+    /// `251 RPL_LUSERCLIENT`
+    LuserClient { users: usize, invisible: usize },
+    /// `253 RPL_LUSERUNKNOWN`
+    LuserUnknown { count: usize },
+    /// `254 RPL_LUSERCHANNELS`
+    LuserChannels { count: usize },
+    /// `255 RPL_LUSERME`
+    LuserMe { clients: usize },
+    /// `256 RPL_ADMINME`
+    AdminMe,
+    /// `257 RPL_ADMINLOC1`
+    AdminLoc1 { info: &'r str },
+    /// `258 RPL_ADMINLOC2`
+    AdminLoc2 { info: &'r str },
+    /// `259 RPL_ADMINEMAIL`
+    AdminEmail { info: &'r str },
+    /// `265 RPL_LOCALUSERS`
+    LocalUsers { current: usize, max: usize },
+    /// `266 RPL_GLOBALUSERS`
+    GlobalUsers { current: usize, max: usize },
+    /// `276 RPL_WHOISCERTFP`
+    WhoisCertfp { nick: &'r str, fingerprint: &'r str },
+    /// `301 RPL_AWAY`
+    Away { nick: &'r str, text: &'r str },
+    /// `302 RPL_USERHOST`
+    UserHost { replies: &'r str },
+    /// `305 RPL_UNAWAY`
+    UnAway,
+    /// `306 RPL_NOWAWAY`
+    NowAway,
+    /// `307 RPL_WHOISREGNICK`
+    WhoisRegNick { nick: &'r str },
+    /// `311 RPL_WHOISUSER`
+    WhoisUser { nick: &'r str, user: &'r str, host: &'r str, real: &'r str },
+    /// `312 RPL_WHOISSERVER`
+    WhoisServer { nick: &'r str, server: &'r str, info: &'r str },
+    /// `313 RPL_WHOISOPERATOR`
+    WhoisOperator { nick: &'r str },
+    /// `314 RPL_WHOWASUSER`
+    WhowasUser { nick: &'r str, user: &'r str, host: &'r str, real: &'r str },
+    /// `315 RPL_ENDOFWHO`
+    EndOfWho { mask: &'r str },
+    /// `318 RPL_ENDOFWHOIS`
+    EndOfWhois { nick: &'r str },
+    /// `321 RPL_LISTSTART`
+    ListStart,
+    /// `322 RPL_LIST`
+    List { channel: &'r str, count: usize, topic: &'r str },
+    /// `323 RPL_LISTEND`
+    ListEnd,
+    /// `324 RPL_CHANNELMODEIS`
+    ChannelModeIs { channel: &'r str, modes: &'r str, args: &'r [String] },
+    /// `329 RPL_CREATIONTIME`
+    CreationTime { channel: &'r str, created: i64 },
+    /// `330 RPL_WHOISACCOUNT`
+    WhoisAccount { nick: &'r str, account: &'r str },
+    /// `331 RPL_NOTOPIC`
+    NoTopic { channel: &'r str },
+    /// `332 RPL_TOPIC`
+    Topic { channel: &'r str, text: &'r str },
+    /// `333 RPL_TOPICWHOTIME`
+    TopicWhoTime { channel: &'r str, set_by: &'r str, set_at: i64 },
+    /// `341 RPL_INVITING`
+    Inviting { nick: &'r str, channel: &'r str },
+    /// `351 RPL_VERSION`
+    Version { version: &'r str, comments: &'r str },
+    /// `352 RPL_WHOREPLY`
+    WhoReply {
+        channel: &'r str,
+        user: &'r str,
+        host: &'r str,
+        nick: &'r str,
+        flags: &'r str,
+        real: &'r str,
+    },
+    /// `353 RPL_NAMREPLY`
+    NamReply { channel: &'r str, names: &'r str },
+    /// `365 RPL_ENDOFLINKS`
+    EndOfLinks { mask: &'r str },
+    /// `366 RPL_ENDOFNAMES`
+    EndOfNames { channel: &'r str },
+    /// `369 RPL_ENDOFWHOWAS`
+    EndOfWhowas { nick: &'r str },
+    /// `372 RPL_MOTD`
+    Motd { line: &'r str },
+    /// `374 RPL_ENDOFINFO`
+    EndOfInfo,
+    /// `375 RPL_MOTDSTART`
+    MotdStart,
+    /// `376 RPL_ENDOFMOTD`
+    EndOfMotd,
+    /// `381 RPL_YOUREOPER`
+    YoureOper,
+    /// `382 RPL_REHASHING`
+    Rehashing { file: &'r str },
+    /// `391 RPL_TIME`
+    Time { timestamp: i64, text: &'r str },
+    /// `401 ERR_NOSUCHNICK`
+    NoSuchNick { target: &'r str },
+    /// `402 ERR_NOSUCHSERVER`
+    NoSuchServer { server: &'r str },
+    /// `403 ERR_NOSUCHCHANNEL`
+    NoSuchChannel { channel: &'r str },
+    /// `404 ERR_CANNOTSENDTOCHAN`
+    CannotSendToChan { channel: &'r str },
+    /// `405 ERR_TOOMANYCHANNELS`
+    TooManyChannels { channel: &'r str },
+    /// `406 ERR_WASNOSUCHNICK`
+    WasNoSuchNick { nick: &'r str },
+    /// `407 ERR_TOOMANYTARGETS`
+    TooManyTargets { target: &'r str },
+    /// `410 ERR_INVALIDCAPCMD`
+    InvalidCapCmd { subcommand: &'r str },
+    /// `411 ERR_NORECIPIENT`
+    NoRecipient { command: &'r str },
+    /// `412 ERR_NOTEXTTOSEND`
+    NoTextToSend,
+    /// `421 ERR_UNKNOWNCOMMAND`
+    UnknownCommand { command: &'r str },
+    /// `422 ERR_NOMOTD`
+    NoMotd,
+    /// `431 ERR_NONICKNAMEGIVEN`
+    NoNicknameGiven,
+    /// `432 ERR_ERRONEUSNICKNAME`
+    ErroneusNickname { nick: &'r str, reason: &'r str },
+    /// `433 ERR_NICKNAMEINUSE`
+    NicknameInUse { nick: &'r str },
+    /// `441 ERR_USERNOTINCHANNEL`
+    UserNotInChannel { nick: &'r str, channel: &'r str },
+    /// `442 ERR_NOTONCHANNEL`
+    NotOnChannel { channel: &'r str },
+    /// `443 ERR_USERONCHANNEL`
+    UserOnChannel { nick: &'r str, channel: &'r str },
+    /// `451 ERR_NOTREGISTERED`
+    NotRegistered,
+    /// `461 ERR_NEEDMOREPARAMS`
+    NeedMoreParams { command: &'r str },
+    /// `462 ERR_ALREADYREGISTERED`
+    AlreadyRegistered,
+    /// `464 ERR_PASSWDMISMATCH`
+    PasswdMismatch,
+    /// `465 ERR_YOUREBANNEDCREEP`
+    YoureBannedCreep { reason: &'r str },
+    /// `471 ERR_CHANNELISFULL`
+    ChannelIsFull { channel: &'r str },
+    /// `472 ERR_UNKNOWNMODE`
+    UnknownMode { mode: char },
+    /// `473 ERR_INVITEONLYCHAN`
+    InviteOnlyChan { channel: &'r str },
+    /// `475 ERR_BADCHANNELKEY`
+    BadChannelKey { channel: &'r str },
+    /// `481 ERR_NOPRIVILEGES`
+    NoPrivileges,
+    /// `482 ERR_CHANOPRIVSNEEDED`
+    ChanOpPrivsNeeded { channel: &'r str },
+    /// `491 ERR_NOOPERHOST`
+    NoOperHost,
+    /// `501 ERR_UMODEUNKNOWNFLAG`
+    UModeUnknownFlag,
+    /// `502 ERR_USERSDONTMATCH`
+    UsersDontMatch,
+    /// `524 ERR_HELPNOTFOUND`
+    HelpNotFound { subject: &'r str },
+    /// `671 RPL_WHOISSECURE`
+    WhoisSecure { nick: &'r str },
+    /// `900 RPL_LOGGEDIN`
+    LoggedIn { source: &'r str, account: &'r str },
+    /// `901 RPL_LOGGEDOUT`
+    LoggedOut { source: &'r str },
+    /// `903 RPL_SASLSUCCESS`
+    SaslSuccess,
+    /// `904 ERR_SASLFAIL`
+    SaslFail,
+    /// `905 ERR_SASLTOOLONG`
+    SaslTooLong,
+    /// `906 ERR_SASLABORTED`
+    SaslAborted,
+    /// `907 ERR_SASLALREADY`
+    SaslAlready,
+    /// `908 RPL_SASLMECHS`
+    SaslMechs { mechanisms: &'r str },
+    /// `AUTHENTICATE <payload>`, without the nick.
+    Authenticate { payload: &'r str },
+    /// `FAIL <command> <code> :<description>`, without the nick.
+    Fail { command: &'r str, code: &'r str, description: &'r str },
+    /// `NOTE <command> <code> :<description>`, without the nick.
+    Note { command: &'r str, code: &'r str, description: &'r str },
+    /// `CAP <nick> <subcommand> [*] :<list>`, where `more` marks that
+    /// further lines of the same list follow.
+    Cap { subcommand: &'r str, more: bool, list: &'r str },
+    /// `ERROR :<text>`, sent right before the connection is closed.
+    Error { text: &'r str },
+    /// `NOTICE <nick> :<text>`
+    Notice { text: &'r str },
+    /// `PING <token>`, checking that an idle client is still there.
+    Ping { token: &'r str },
+    /// `PONG <server> :<token>`
+    Pong { token: &'r str },
+}
+
+impl Reply<'_> {
+    /// Render the reply as a complete line, including the CRLF.
+    pub fn render(&self, server: &str, nick: &str) -> String {
+        let n = Numeric { server, nick };
+
+        match *self {
+            Reply::Welcome { network, source } => n.line(
+                "001",
+                &[],
+                Some(&format!("Welcome to the {network} Network, {source}")),
+            ),
+            Reply::YourHost { version } => n.line(
+                "002",
+                &[],
+                Some(&format!("Your host is {server}, running version {version}")),
+            ),
+            Reply::Created { date } => n.line("003", &[], Some(&format!("This server was created {date}"))),
+            Reply::MyInfo {
+                version,
+                user_modes,
+                channel_modes,
+                param_modes,
+            } => n.line("004", &[server, version, user_modes, channel_modes, param_modes], None),
+            Reply::ISupport { tokens } => {
+                let tokens = tokens.iter().map(String::as_str).collect::<Vec<_>>();
+                n.line("005", &tokens, Some("are supported by this server"))
+            }
+            Reply::StatsBan { kind, mask, expires, set_by, reason } => {
+                n.line("216", &[kind, mask, &expires.to_string(), set_by], Some(reason))
+            }
+            Reply::EndOfStats { query } => n.line("219", &[query], Some("End of /STATS report")),
+            Reply::UModeIs { modes } => n.line("221", &[modes], None),
+            Reply::StatsUptime { seconds } => n.line(
+                "242",
+                &[],
+                Some(&format!(
+                    "Server Up {} days {}:{:02}:{:02}",
+                    seconds / 86400,
+                    seconds / 3600 % 24,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )),
+            ),
+            Reply::StatsDebug { query, text } => n.line("249", &[query], Some(text)),
+            Reply::LuserClient { users, invisible } => n.line(
+                "251",
+                &[],
+                Some(&format!("There are {users} users and {invisible} invisible on 1 servers")),
+            ),
+            Reply::LuserUnknown { count } => n.line("253", &[&count.to_string()], Some("unknown connection(s)")),
+            Reply::LuserChannels { count } => n.line("254", &[&count.to_string()], Some("channels formed")),
+            Reply::LuserMe { clients } => {
+                n.line("255", &[], Some(&format!("I have {clients} clients and 0 servers")))
+            }
+            Reply::AdminMe => n.line("256", &[server], Some("Administrative info")),
+            Reply::AdminLoc1 { info } => n.line("257", &[], Some(info)),
+            Reply::AdminLoc2 { info } => n.line("258", &[], Some(info)),
+            Reply::AdminEmail { info } => n.line("259", &[], Some(info)),
+            Reply::LocalUsers { current, max } => n.line(
+                "265",
+                &[&current.to_string(), &max.to_string()],
+                Some(&format!("Current local users {current}, max {max}")),
+            ),
+            Reply::GlobalUsers { current, max } => n.line(
+                "266",
+                &[&current.to_string(), &max.to_string()],
+                Some(&format!("Current global users {current}, max {max}")),
+            ),
+            Reply::WhoisCertfp { nick, fingerprint } => n.line(
+                "276",
+                &[nick],
+                Some(&format!("has client certificate fingerprint {fingerprint}")),
+            ),
+            Reply::Away { nick, text } => n.line("301", &[nick], Some(text)),
+            Reply::UserHost { replies } => n.line("302", &[], Some(replies)),
+            Reply::UnAway => n.line("305", &[], Some("You are no longer marked as being away")),
+            Reply::NowAway => n.line("306", &[], Some("You have been marked as being away")),
+            Reply::WhoisRegNick { nick } => n.line("307", &[nick], Some("is a registered nick")),
+            Reply::WhoisUser { nick, user, host, real } => n.line("311", &[nick, user, host, "*"], Some(real)),
+            Reply::WhoisServer { nick, server, info } => n.line("312", &[nick, server], Some(info)),
+            Reply::WhoisOperator { nick } => n.line("313", &[nick], Some("is an IRC operator")),
+            Reply::WhowasUser { nick, user, host, real } => n.line("314", &[nick, user, host, "*"], Some(real)),
+            Reply::EndOfWho { mask } => n.line("315", &[mask], Some("End of WHO list")),
+            Reply::EndOfWhois { nick } => n.line("318", &[nick], Some("End of /WHOIS list")),
+            Reply::ListStart => n.line("321", &["Channel"], Some("Users  Name")),
+            Reply::List { channel, count, topic } => n.line("322", &[channel, &count.to_string()], Some(topic)),
+            Reply::ListEnd => n.line("323", &[], Some("End of /LIST")),
+            Reply::ChannelModeIs { channel, modes, args } => {
+                let middles = [channel, modes].into_iter().chain(args.iter().map(String::as_str)).collect::<Vec<_>>();
+                n.line("324", &middles, None)
+            }
+            Reply::CreationTime { channel, created } => n.line("329", &[channel, &created.to_string()], None),
+            Reply::WhoisAccount { nick, account } => n.line("330", &[nick, account], Some("is logged in as")),
+            Reply::NoTopic { channel } => n.line("331", &[channel], Some("No topic is set")),
+            Reply::Topic { channel, text } => n.line("332", &[channel], Some(text)),
+            Reply::TopicWhoTime { channel, set_by, set_at } => {
+                n.line("333", &[channel, set_by, &set_at.to_string()], None)
+            }
+            Reply::Inviting { nick, channel } => n.line("341", &[nick, channel], None),
+            Reply::Version { version, comments } => n.line("351", &[version, server], Some(comments)),
+            Reply::WhoReply { channel, user, host, nick, flags, real } => {
+                n.line("352", &[channel, user, host, server, nick, flags], Some(&format!("0 {real}")))
+            }
+            Reply::NamReply { channel, names } => n.line("353", &["=", channel], Some(names)),
+            Reply::EndOfLinks { mask } => n.line("365", &[mask], Some("End of /LINKS list")),
+            Reply::EndOfNames { channel } => n.line("366", &[channel], Some("End of /NAMES list")),
+            Reply::EndOfWhowas { nick } => n.line("369", &[nick], Some("End of WHOWAS")),
+            Reply::Motd { line } => n.line("372", &[], Some(line)),
+            Reply::EndOfInfo => n.line("374", &[], Some("End of INFO list")),
+            Reply::MotdStart => n.line("375", &[], Some(&format!("- {server} Message of the day - "))),
+            Reply::EndOfMotd => n.line("376", &[], Some("End of /MOTD command.")),
+            Reply::YoureOper => n.line("381", &[], Some("You are now an IRC operator")),
+            Reply::Rehashing { file } => n.line("382", &[file], Some("Rehashing")),
+            Reply::Time { timestamp, text } => n.line("391", &[server, &timestamp.to_string(), "0"], Some(text)),
+            Reply::NoSuchNick { target } => n.line("401", &[target], Some("No such nick/channel")),
+            Reply::NoSuchServer { server } => n.line("402", &[server], Some("No such server")),
+            Reply::NoSuchChannel { channel } => n.line("403", &[channel], Some("No such channel")),
+            Reply::CannotSendToChan { channel } => n.line("404", &[channel], Some("Cannot send to channel")),
+            Reply::TooManyChannels { channel } => {
+                n.line("405", &[channel], Some("You have joined too many channels"))
+            }
+            Reply::WasNoSuchNick { nick } => n.line("406", &[nick], Some("There was no such nickname")),
+            Reply::TooManyTargets { target } => {
+                n.line("407", &[target], Some("Too many targets. No message delivered"))
+            }
+            Reply::InvalidCapCmd { subcommand } => n.line("410", &[subcommand], Some("Invalid CAP command")),
+            Reply::NoRecipient { command } => {
+                n.line("411", &[], Some(&format!("No recipient given ({command})")))
+            }
+            Reply::NoTextToSend => n.line("412", &[], Some("No text to send")),
+            Reply::UnknownCommand { command } => n.line("421", &[command], Some("Unknown command")),
+            Reply::NoMotd => n.line("422", &[], Some("MOTD File is missing")),
+            Reply::NoNicknameGiven => n.line("431", &[], Some("No nickname given")),
+            Reply::ErroneusNickname { nick, reason } => n.line("432", &[nick], Some(reason)),
+            Reply::NicknameInUse { nick } => n.line("433", &[nick], Some("Nickname is already in use")),
+            Reply::UserNotInChannel { nick, channel } => {
+                n.line("441", &[nick, channel], Some("They aren't on that channel"))
+            }
+            Reply::NotOnChannel { channel } => n.line("442", &[channel], Some("You're not on that channel")),
+            Reply::UserOnChannel { nick, channel } => {
+                n.line("443", &[nick, channel], Some("is already on channel"))
+            }
+            Reply::NotRegistered => n.line("451", &[], Some("You have not registered")),
+            Reply::NeedMoreParams { command } => n.line("461", &[command], Some("Not enough parameters")),
+            Reply::AlreadyRegistered => n.line("462", &[], Some("You may not reregister")),
+            Reply::PasswdMismatch => n.line("464", &[], Some("Password incorrect")),
+            Reply::YoureBannedCreep { reason } => {
+                n.line("465", &[], Some(&format!("You are banned from this server ({reason})")))
+            }
+            Reply::ChannelIsFull { channel } => n.line("471", &[channel], Some("Cannot join channel (+l)")),
+            Reply::UnknownMode { mode } => {
+                n.line("472", &[&mode.to_string()], Some("is unknown mode char to me"))
+            }
+            Reply::InviteOnlyChan { channel } => n.line("473", &[channel], Some("Cannot join channel (+i)")),
+            Reply::BadChannelKey { channel } => n.line("475", &[channel], Some("Cannot join channel (+k)")),
+            Reply::NoPrivileges => n.line("481", &[], Some("Permission Denied- You're not an IRC operator")),
+            Reply::ChanOpPrivsNeeded { channel } => {
+                n.line("482", &[channel], Some("You're not channel operator"))
+            }
+            Reply::NoOperHost => n.line("491", &[], Some("No O-lines for your host")),
+            Reply::UModeUnknownFlag => n.line("501", &[], Some("Unknown MODE flag")),
+            Reply::UsersDontMatch => n.line("502", &[], Some("Cant change mode for other users")),
+            Reply::HelpNotFound { subject } => n.line("524", &[subject], Some("No help available on this topic")),
+            Reply::WhoisSecure { nick } => n.line("671", &[nick], Some("is using a secure connection")),
+            Reply::LoggedIn { source, account } => {
+                n.line("900", &[source, account], Some(&format!("You are now logged in as {account}")))
+            }
+            Reply::LoggedOut { source } => n.line("901", &[source], Some("You are now logged out")),
+            Reply::SaslSuccess => n.line("903", &[], Some("SASL authentication successful")),
+            Reply::SaslFail => n.line("904", &[], Some("SASL authentication failed")),
+            Reply::SaslTooLong => n.line("905", &[], Some("SASL message too long")),
+            Reply::SaslAborted => n.line("906", &[], Some("SASL authentication aborted")),
+            Reply::SaslAlready => n.line("907", &[], Some("You have already authenticated using SASL")),
+            Reply::SaslMechs { mechanisms } => {
+                n.line("908", &[mechanisms], Some("are available SASL mechanisms"))
+            }
+            Reply::Authenticate { payload } => line(server, "AUTHENTICATE", &[payload], None),
+            Reply::Fail { command, code, description } => line(server, "FAIL", &[command, code], Some(description)),
+            Reply::Note { command, code, description } => line(server, "NOTE", &[command, code], Some(description)),
+            Reply::Cap { subcommand, more: true, list } => n.line("CAP", &[subcommand, "*"], Some(list)),
+            Reply::Cap { subcommand, more: false, list } => n.line("CAP", &[subcommand], Some(list)),
+            Reply::Error { text } => line(server, "ERROR", &[], Some(text)),
+            Reply::Notice { text } => n.line("NOTICE", &[], Some(text)),
+            Reply::Ping { token } => line(server, "PING", &[token], None),
+            Reply::Pong { token } => line(server, "PONG", &[server], Some(token)),
+        }
+    }
+}
+
+/// A message relayed on behalf of a client, prefixed with
+/// its `nick!user@host`.
+pub enum Relay<'r> {
+    Join { channel: &'r str },
+    Part { channel: &'r str, reason: Option<&'r str> },
+    Nick { nick: &'r str },
+    Quit { reason: &'r str },
+    Kill { target: &'r str, reason: &'r str },
+    Wallops { text: &'r str },
+    Mode { target: &'r str, modes: &'r str },
+    Invite { target: &'r str, channel: &'r str },
+    Kick { channel: &'r str, target: &'r str, reason: &'r str },
+    Topic { channel: &'r str, text: &'r str },
+    /// `MODE <channel> <modes> <args...>`, with every argument a
+    /// separate parameter.
+    ChannelMode { channel: &'r str, modes: &'r str, args: &'r [String] },
+    /// `ACCOUNT <account>`, or `ACCOUNT *` after logging out.
+    Account { account: Option<&'r str> },
+    /// A PRIVMSG or NOTICE, tagged with the sender's account if it is
+    /// logged in.
+    Message {
+        command: &'r str,
+        target: &'r str,
+        text: &'r str,
+        account: Option<&'r str>,
+    },
+}
+
+impl Relay<'_> {
+    /// Render the message as a complete line, including the CRLF.
+    pub fn render(&self, source: &str) -> String {
+        match *self {
+            Relay::Join { channel } => line(source, "JOIN", &[channel], None),
+            Relay::Part { channel, reason } => line(source, "PART", &[channel], reason),
+            Relay::Nick { nick } => line(source, "NICK", &[], Some(nick)),
+            Relay::Quit { reason } => line(source, "QUIT", &[], Some(reason)),
+            Relay::Kill { target, reason } => line(source, "KILL", &[target], Some(reason)),
+            Relay::Wallops { text } => line(source, "WALLOPS", &[], Some(text)),
+            Relay::Mode { target, modes } => line(source, "MODE", &[target], Some(modes)),
+            Relay::Invite { target, channel } => line(source, "INVITE", &[target, channel], None),
+            Relay::Kick { channel, target, reason } => line(source, "KICK", &[channel, target], Some(reason)),
+            Relay::Topic { channel, text } => line(source, "TOPIC", &[channel], Some(text)),
+            Relay::ChannelMode { channel, modes, args } => {
+                let middles = [channel, modes].into_iter().chain(args.iter().map(String::as_str)).collect::<Vec<_>>();
+                line(source, "MODE", &middles, None)
+            }
+            Relay::Account { account } => line(source, "ACCOUNT", &[account.unwrap_or("*")], None),
+            Relay::Message { command, target, text, account: None } => {
+                line(source, command, &[target], Some(text))
+            }
+            // Tags have a length limit of their own, so they are added
+            // after the rest of the line has been cut down to size.
+            Relay::Message { command, target, text, account: Some(account) } => format!(
+                "@account={} {}",
+                escape_tag(account),
+                line(source, command, &[target], Some(text))
+            ),
+        }
+    }
+}
+
+/// Tailor a relayed line to the capabilities of the client receiving
+/// it. Returns `None` if the client should not see the line at all.
+///
+/// Relayed lines are rendered once and shared by every recipient, so
+/// they carry everything any recipient might want. The only tag added
+/// so far is `account`, which is stripped for clients without
+/// `account-tag`, and `ACCOUNT` lines are only for `account-notify`.
+pub fn adapt(line: &str, caps: Capabilities) -> Option<&str> {
+    let untagged = match line.strip_prefix('@') {
+        Some(tagged) => tagged.split_once(' ').map_or("", |(_, rest)| rest),
+        None => line,
+    };
+
+    let command = untagged
+        .strip_prefix(':')
+        .and_then(|prefixed| prefixed.split_once(' '))
+        .map_or(untagged, |(_, rest)| rest)
+        .split(' ')
+        .next()
+        .unwrap_or("");
+
+    if command.eq_ignore_ascii_case("ACCOUNT") && !caps.contains(Capabilities::CapAccountNotify) {
+        return None;
+    }
+
+    match caps.contains(Capabilities::CapAccountTag) {
+        true => Some(line),
+        false => Some(untagged),
+    }
+}
+
+/// Escape a message tag value.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            '\0' => {}
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+struct Numeric<'n> {
+    server: &'n str,
+    nick: &'n str,
+}
+
+impl Numeric<'_> {
+    fn line(&self, command: &str, middles: &[&str], trailing: Option<&str>) -> String {
+        let middles = std::iter::once(self.nick).chain(middles.iter().copied()).collect::<Vec<_>>();
+        line(self.server, command, &middles, trailing)
+    }
+}
+
+/// Assemble `:<prefix> <command> <middles...> :<trailing>`.
+///
+/// Middle parameters lose any spaces, line breaks and leading colons,
+/// and become `*` if nothing is left. The trailing parameter only loses
+/// line breaks. The line is then cut down to [MAX_LINE] bytes, which
+/// only ever shortens the trailing parameter unless the rest of the
+/// line is already too long.
+fn line(prefix: &str, command: &str, middles: &[&str], trailing: Option<&str>) -> String {
+    let mut line = String::with_capacity(MAX_LINE + 2);
+    line.push(':');
+    line.push_str(prefix);
+    line.push(' ');
+    line.push_str(command);
+
+    for middle in middles {
+        let middle = middle.trim_start_matches(':');
+        let start = line.len() + 1;
+
+        line.push(' ');
+        line.extend(middle.chars().filter(|c| !matches!(c, ' ' | '\r' | '\n' | '\0')));
+
+        if line.len() == start {
+            line.push('*');
+        }
+    }
+
+    if let Some(trailing) = trailing {
+        line.push_str(" :");
+        line.extend(trailing.chars().filter(|c| !matches!(c, '\r' | '\n' | '\0')));
+    }
+
+    let end = line.as_str().slice_at_most(MAX_LINE).len();
+    line.truncate(end);
+    line.push_str("\r\n");
+    line
+}
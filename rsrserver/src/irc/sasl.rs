@@ -0,0 +1,175 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::irc::CapConfig;
+
+/// AUTHENTICATE payloads are split into chunks of this many bytes,
+/// with a shorter (or `+`) chunk marking the end.
+const CHUNK_LEN: usize = 400;
+
+/// Largest encoded payload accepted over all chunks.
+const MAX_PAYLOAD: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    Plain,
+    External,
+    /// PDS tokens, as extended by `rsr.chat/plc-oauthbearer`.
+    OAuthBearer,
+}
+
+impl Mechanism {
+    /// Every mechanism the server implements.
+    pub const ALL: [Mechanism; 3] = [Self::Plain, Self::External, Self::OAuthBearer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+            Self::External => "EXTERNAL",
+            Self::OAuthBearer => "OAUTHBEARER",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "PLAIN" => Some(Self::Plain),
+            "EXTERNAL" => Some(Self::External),
+            "OAUTHBEARER" => Some(Self::OAuthBearer),
+            _ => None,
+        }
+    }
+}
+
+/// Mechanisms clients may log in with: the ones listed in the value of
+/// the `sasl` capability, or all of them if it has none. OAUTHBEARER
+/// also needs a DID resolver to verify tokens with.
+pub fn offered(config: &CapConfig, resolver: bool) -> Vec<Mechanism> {
+    let listed = |mechanism: &Mechanism| match config.values.get("sasl") {
+        Some(value) => value.split(',').any(|name| Mechanism::from_name(name) == Some(*mechanism)),
+        None => true,
+    };
+
+    Mechanism::ALL
+        .into_iter()
+        .filter(listed)
+        .filter(|mechanism| *mechanism != Mechanism::OAuthBearer || resolver)
+        .collect()
+}
+
+/// An AUTHENTICATE exchange in progress, reassembling the client's
+/// chunked payload.
+#[derive(Debug)]
+pub struct SaslExchange {
+    mechanism: Mechanism,
+    payload: String,
+}
+
+/// The result of feeding one AUTHENTICATE line into a [SaslExchange].
+pub enum Step {
+    /// More chunks are expected.
+    More,
+    /// The payload is complete and decoded.
+    Done(Vec<u8>),
+    /// A chunk or the whole payload was too long.
+    TooLong,
+    /// The payload was not valid base64.
+    Invalid,
+}
+
+impl SaslExchange {
+    pub fn new(mechanism: Mechanism) -> Self {
+        Self {
+            mechanism,
+            payload: String::new(),
+        }
+    }
+
+    pub fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    pub fn push(&mut self, chunk: &str) -> Step {
+        if chunk.len() > CHUNK_LEN || self.payload.len() + chunk.len() > MAX_PAYLOAD {
+            return Step::TooLong;
+        }
+
+        // `+` stands for an empty chunk.
+        if chunk != "+" {
+            self.payload.push_str(chunk);
+        }
+
+        if chunk.len() == CHUNK_LEN {
+            return Step::More;
+        }
+
+        match STANDARD.decode(&self.payload) {
+            Ok(payload) => Step::Done(payload),
+            Err(_) => Step::Invalid,
+        }
+    }
+}
+
+/// Credentials sent with the PLAIN mechanism.
+pub struct Plain<'p> {
+    /// Identity to act as. Empty when the same as `authcid`.
+    pub authzid: &'p str,
+    /// Identity whose password was given.
+    pub authcid: &'p str,
+    pub password: &'p str,
+}
+
+impl<'p> Plain<'p> {
+    /// Parse `authzid NUL authcid NUL password`.
+    pub fn parse(payload: &'p [u8]) -> Option<Self> {
+        let payload = str::from_utf8(payload).ok()?;
+
+        let mut parts = payload.splitn(3, '\0');
+        let (authzid, authcid, password) = (parts.next()?, parts.next()?, parts.next()?);
+
+        if authcid.is_empty() || password.contains('\0') {
+            return None;
+        }
+
+        Some(Self {
+            authzid,
+            authcid,
+            password,
+        })
+    }
+}
+
+/// The initial client response of OAUTHBEARER (RFC 7628).
+pub struct OAuthBearer<'o> {
+    /// Identity to act as, from the GS2 header.
+    pub authzid: Option<&'o str>,
+    pub token: &'o str,
+}
+
+impl<'o> OAuthBearer<'o> {
+    /// Parse `n,[a=authzid],` followed by `^A`-separated key/value
+    /// pairs, one of which must be `auth=Bearer <token>`.
+    pub fn parse(payload: &'o [u8]) -> Option<Self> {
+        let payload = str::from_utf8(payload).ok()?;
+
+        let mut pairs = payload.split('\x01');
+        let mut header = pairs.next()?.split(',');
+
+        // Channel binding is not supported.
+        if header.next()? != "n" {
+            return None;
+        }
+
+        let authzid = match header.next()? {
+            "" => None,
+            authzid => Some(authzid.strip_prefix("a=")?),
+        };
+
+        let token = pairs.find_map(|pair| pair.strip_prefix("auth="))?;
+        let (scheme, token) = token.split_once(' ')?;
+
+        if !scheme.eq_ignore_ascii_case("Bearer") || token.is_empty() {
+            return None;
+        }
+
+        Some(Self { authzid, token })
+    }
+}
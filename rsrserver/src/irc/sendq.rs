@@ -0,0 +1,113 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+    sync::mpsc,
+    task::JoinHandle,
+    time::timeout,
+};
+
+use crate::error::{IrcResult, IrcSessionError};
+
+/// Lines taken off the queue to be written in one go.
+const BATCH: usize = 64;
+
+/// Size of the buffer lines are gathered in before they are written.
+const WRITE_BUFFER: usize = 16 * 1024;
+
+/// Time a closing connection is given to write out what is queued.
+const LINGER: Duration = Duration::from_secs(5);
+
+/// Lines on their way to a client, written out by a task of their own
+/// so that a slow reader never holds up its connection. Once more than
+/// `limit` bytes are waiting, the client is too far behind to keep.
+pub struct SendQueue {
+    tx: Option<mpsc::UnboundedSender<Bytes>>,
+    queued: Arc<AtomicUsize>,
+    limit: usize,
+    writer: JoinHandle<()>,
+}
+
+impl SendQueue {
+    pub fn new<W>(writer: W, limit: usize) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let queued = Arc::new(AtomicUsize::new(0));
+
+        Self {
+            tx: Some(tx),
+            writer: tokio::spawn(write_queued(writer, rx, Arc::clone(&queued))),
+            queued,
+            limit,
+        }
+    }
+
+    /// Queue a line, failing with [IrcSessionError::SendQExceeded] if
+    /// the client has fallen too far behind on reading.
+    pub fn push(&mut self, line: Bytes) -> IrcResult<()> {
+        let queued = self.queued.fetch_add(line.len(), Ordering::Relaxed) + line.len();
+        if queued > self.limit {
+            return Err(IrcSessionError::SendQExceeded);
+        }
+
+        self.send(line)
+    }
+
+    /// Queue a last line regardless of the limit, and wait a little for
+    /// everything queued to be written before the connection is shut.
+    pub async fn close(mut self, last: Bytes) {
+        self.queued.fetch_add(last.len(), Ordering::Relaxed);
+        let _ = self.send(last);
+        drop(self.tx.take());
+
+        if timeout(LINGER, &mut self.writer).await.is_err() {
+            self.writer.abort();
+        }
+    }
+
+    fn send(&mut self, line: Bytes) -> IrcResult<()> {
+        let sent = self.tx.as_ref().is_some_and(|tx| tx.send(line).is_ok());
+        if !sent {
+            // The writer only stops early when writing failed.
+            return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Write lines as they are queued, as many at once as are waiting,
+/// until the queue is closed or the client stops accepting them.
+async fn write_queued<W>(writer: W, mut rx: mpsc::UnboundedReceiver<Bytes>, queued: Arc<AtomicUsize>)
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER, writer);
+    let mut batch = Vec::with_capacity(BATCH);
+
+    while rx.recv_many(&mut batch, BATCH).await > 0 {
+        let mut written = 0;
+        for line in batch.drain(..) {
+            if writer.write_all(&line).await.is_err() {
+                return;
+            }
+            written += line.len();
+        }
+
+        if writer.flush().await.is_err() {
+            return;
+        }
+        queued.fetch_sub(written, Ordering::Relaxed);
+    }
+
+    let _ = writer.shutdown().await;
+}
@@ -1,91 +1,213 @@
-use std::{net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 
 use bytes::Bytes;
+use chrono::Utc;
 use ircv3_parse::Message;
 use tokio::{
-    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, split},
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader, split},
     select,
-    sync::broadcast,
-    time::{Instant, Sleep, sleep},
+    signal::unix::{SignalKind, signal},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::{Instant, Sleep, sleep, sleep_until},
 };
-use tokio_stream::{StreamExt, StreamMap};
+use tokio_stream::{StreamExt, StreamMap, wrappers::errors::BroadcastStreamRecvError};
 
 use crate::{
     error::{IrcResult, IrcSessionError},
     irc::{
-        ChannelName, ChannelSink, ChannelSource, ClientSink, ClientSource, IrcContext, IrcSession,
-        ServerMessage, ServerSink, ServerSource, command,
+        ChannelName, ChannelSink, ChannelSource, ClientSource, Control, ControlSource, DirectSource, GrantExpiry, IrcContext, IrcSession,
+        Network, Relay, Reply, SendQueue, ServerMessage, ServerSink, ServerSource, Transport, Whowas, command,
+        flood::{FakeLag, command_of},
+        network::DIRECT_CAPACITY,
         state::{self, MaybeTransition, Old},
     },
-    storage::Storage,
-    tls::TlsHandler,
+    storage::{Storage, StorageResult},
+    tls::{self, TlsHandler},
 };
 
 pub struct IrcServer<S> {
     storage: Arc<S>,
-    s_rx: broadcast::Receiver<()>,
-    s_tx: broadcast::Sender<()>,
+    network: Arc<Network>,
+    s_rx: broadcast::Receiver<ServerMessage>,
+    s_tx: broadcast::Sender<ServerMessage>,
 }
 
 impl<S> IrcServer<S> {
-    pub fn new(storage: S) -> Self {
-        let (s_tx, s_rx) = broadcast::channel(1024);
+    pub fn new(storage: S, network: Network) -> Self {
+        let (s_tx, s_rx) = broadcast::channel(network.config().limits.server_bus_capacity);
         let storage = Arc::new(storage);
+        let network = Arc::new(network);
 
         Self {
             storage,
+            network,
             s_rx,
             s_tx,
         }
     }
+
+    /// Server-wide shared state.
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// A new receiver of everything sent over the server bus.
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerMessage> {
+        self.s_tx.subscribe()
+    }
+
+    /// Rehash whenever the process receives SIGHUP. Errors are logged,
+    /// since there is nobody to report them to.
+    pub fn rehash_on_sighup(&self) -> std::io::Result<()> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        let network = Arc::clone(&self.network);
+        let s_tx = self.s_tx.clone();
+
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match network.rehash().await {
+                    Ok(changes) => {
+                        tracing::info!("rehashed on SIGHUP");
+                        for msg in ServerMessage::cap_changes(changes) {
+                            let _ = s_tx.send(msg);
+                        }
+                        let _ = s_tx.send(ServerMessage::Rehashed {
+                            source: network.name().into(),
+                        });
+                    }
+                    Err(e) => tracing::error!("rehash on SIGHUP failed: {e}"),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Close every connection, waiting up to `grace` for them to say
+    /// goodbye to their clients.
+    pub async fn shutdown(&self, reason: &str, grace: Duration) {
+        let _ = self.s_tx.send(ServerMessage::Shutdown { reason: reason.into() });
+
+        let deadline = Instant::now() + grace;
+        while self.network.connections() > 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
 }
 
-impl<S> TlsHandler for IrcServer<S>
+impl<S> Clone for IrcServer<S> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: Arc::clone(&self.storage),
+            network: Arc::clone(&self.network),
+            s_rx: self.s_rx.resubscribe(),
+            s_tx: self.s_tx.clone(),
+        }
+    }
+}
+
+impl<S> IrcServer<S>
 where
     S: Storage + 'static,
 {
-    type Future = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+    /// Put the bans kept in storage into effect. Called once at
+    /// startup, before any client connects. Returns how many there are.
+    pub async fn load_bans(&self) -> StorageResult<usize, S::Error> {
+        let bans = self.storage.bans().await?;
+        self.network.bans().load(bans);
+        Ok(self.network.bans().active().len())
+    }
 
-    fn handle(
-        &mut self,
-        stream: tokio_rustls::server::TlsStream<tokio::net::TcpStream>,
+    /// Run the IRC protocol over an accepted byte stream until the
+    /// connection ends. The stream may be anything from a TLS session
+    /// to an in-memory pipe; what the engine needs to know about it is
+    /// passed in through `transport`.
+    pub fn connect<IO>(
+        &self,
+        stream: IO,
         client_addr: SocketAddr,
-    ) -> Self::Future {
+        transport: Transport,
+    ) -> impl Future<Output = ()> + Send + use<S, IO>
+    where
+        IO: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let (r_rx, r_tx) = split(stream);
         let (s_rx, s_tx) = (self.s_tx.subscribe(), self.s_tx.clone().downgrade());
+        let (d_tx, d_rx) = mpsc::channel(DIRECT_CAPACITY);
+        let (k_tx, k_rx) = mpsc::unbounded_channel();
+        let id = self.network.connect(d_tx, k_tx);
 
-        Box::pin(
-            IrcConnection {
-                storage: Arc::clone(&self.storage),
+        IrcConnection {
+            storage: Arc::clone(&self.storage),
+            network: Arc::clone(&self.network),
 
-                client_addr,
+            session: IrcSession::new(id, client_addr, transport),
 
-                r_rx: BufReader::new(r_rx),
-                r_tx,
+            r_rx: BufReader::new(r_rx),
+            r_tx: SendQueue::new(r_tx, self.network.config().limits.sendq),
+            partial: Vec::new(),
+            recvq: VecDeque::new(),
+            lag: FakeLag::default(),
 
-                s_rx,
-                s_tx,
+            s_rx,
+            s_tx,
 
-                c_tx: StreamMap::new(),
-                c_rx: StreamMap::new(),
+            c_tx: HashMap::new(),
+            c_rx: StreamMap::new(),
 
-                timeout: Box::pin(sleep(Duration::from_secs(30))),
-            }
-            .run(),
-        )
+            d_rx,
+            k_rx,
+
+            timeout: Box::pin(sleep(self.network.config().limits.idle_timeout())),
+        }
+        .run()
     }
 }
 
-pub struct IrcConnection<S> {
+impl<S> TlsHandler for IrcServer<S>
+where
+    S: Storage + 'static,
+{
+    type Future = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn handle(
+        &mut self,
+        stream: tokio_rustls::server::TlsStream<tokio::net::TcpStream>,
+        client_addr: SocketAddr,
+    ) -> Self::Future {
+        let certfp = tls::certfp(&stream);
+        Box::pin(self.connect(stream, client_addr, Transport::secure(certfp)))
+    }
+}
+
+pub struct IrcConnection<S, IO> {
     // Storage backend
     storage: Arc<S>,
 
-    // Remote host address
-    client_addr: SocketAddr,
+    // Server-wide shared state
+    network: Arc<Network>,
+
+    // State common to every typestate of this connection.
+    session: IrcSession,
 
     // I/O with the client this session is associated with.
-    r_rx: ClientSource,
-    r_tx: ClientSink,
+    r_rx: ClientSource<IO>,
+    r_tx: SendQueue,
+
+    // The start of a line from the client that has not been read whole.
+    partial: Vec<u8>,
+    // Lines read from the client, and when fake lag lets them through.
+    recvq: VecDeque<(Instant, Vec<u8>)>,
+    lag: FakeLag,
 
     // Spurious I/O from the server.
     s_rx: ServerSource,
@@ -93,15 +215,21 @@ pub struct IrcConnection<S> {
 
     // Channels the user is joined to.
     c_rx: StreamMap<ChannelName, ChannelSource>,
-    c_tx: StreamMap<ChannelName, ChannelSink>,
+    c_tx: HashMap<ChannelName, ChannelSink>,
+
+    // Messages addressed to this user alone.
+    d_rx: DirectSource,
+    // Orders for this connection, such as a KILL.
+    k_rx: ControlSource,
 
     // Inactivity timeout counter
     timeout: Pin<Box<Sleep>>,
 }
 
-impl<S> IrcConnection<S>
+impl<S, IO> IrcConnection<S, IO>
 where
     S: Storage,
+    IO: AsyncRead + AsyncWrite + Send + 'static,
 {
     pub async fn run(mut self) {
         match self.run_inner().await {
@@ -117,42 +245,51 @@ where
         // Stores data shared over pipe
         let mut ref_buf = Arc::new(Bytes::new());
 
-        let mut session = IrcSession::new();
+        // Stores data sent directly to us by other connections
+        let mut dm_buf = Arc::new(Bytes::new());
 
         // Helper macro to quickly create a context given a state variable.
         macro_rules! context {
             ($state:ident) => {
                 IrcContext::new(
                     self.storage.as_ref(),
-                    &mut session,
+                    self.network.as_ref(),
+                    &mut self.session,
                     &mut self.r_tx,
                     &mut self.s_tx,
+                    &mut self.c_rx,
                     &mut self.c_tx,
                     $state,
                 )
             };
         }
 
-        macro_rules! handle_signal {
-            ($ctx:expr, $signal:expr) => {};
-        }
-
         // Helper macro to quickly define a state machine loop that
         // only exits on a state /change/ or an error.
         macro_rules! state_machine {
             ($state:ident) => {
                 loop {
-                    let signal = self.next_incoming(&mut own_buf, &mut ref_buf).await?;
+                    let warning = self.network.config().limits.grant_warning();
+                    let grant_timer = $state.grant_timer(warning);
+                    let signal = self.next_incoming(&mut own_buf, &mut ref_buf, &mut dm_buf, grant_timer).await?;
 
                     let mut ctx = context!($state);
                     let res = match signal {
                         Signal::Timeout => {
-                            ctx.ping_keepalive().await.unwrap(); // TODO better error handling
+                            ctx.ping_keepalive().await?;
                             Ok(Old(ctx).into())
                         }
+                        Signal::Grant => GrantExpiry::on_grant_timer(ctx).await,
                         Signal::Client(msg) => command::route(ctx, msg).await,
-                        Signal::Server(msg) => todo!(),
-                        Signal::Channel(_name, msg) => {
+                        Signal::Server(msg) => {
+                            ctx.handle_server_message(msg).await?;
+                            Ok(Old(ctx).into())
+                        }
+                        Signal::ServerLagged(missed) => {
+                            ctx.server_lagged(missed).await?;
+                            Ok(Old(ctx).into())
+                        }
+                        Signal::Channel(name, raw, msg) => {
                             // For now, blindly assume the sender has performed
                             // the full burden of verification and that all messages
                             // sent over these IPC channels are valid and should
@@ -160,12 +297,37 @@ where
                             //
                             // ALSO assume the channel message has the proper name
                             // attached before sending.
-                            ctx.send_client(&msg).await?;
+                            if !ctx.session_mut().take_echo(&raw) {
+                                ctx.deliver(msg.input_raw()).await?;
+                            }
+                            if msg.command().as_str() == "KICK" {
+                                ctx.leave_if_kicked(&name);
+                            }
+                            Ok(Old(ctx).into())
+                        }
+                        Signal::ChannelLagged(name, missed) => {
+                            ctx.channel_lagged(&name, missed).await?;
+                            // The KICK may have been among the lost messages.
+                            ctx.leave_if_kicked(&name);
+                            Ok(Old(ctx).into())
+                        }
+                        Signal::Control(order) => {
+                            ctx.handle_control(order).await?;
+                            Ok(Old(ctx).into())
+                        }
+                        Signal::Direct(msg) => {
+                            // Same assumptions as channel messages.
+                            ctx.deliver(msg.input_raw()).await?;
                             Ok(Old(ctx).into())
                         }
                     };
 
-                    match res? {
+                    let res = res?;
+                    if let Some(profile) = self.session.take_profile() {
+                        self.network.publish(self.session.id(), profile);
+                    }
+
+                    match res {
                         MaybeTransition::Old(o) => $state = o,
                         MaybeTransition::New(n) => break n,
                     }
@@ -187,82 +349,232 @@ where
     /// The client timeout is only re-set on a message from the remote client. This
     /// timeout is DIFFERENT from [IrcSession::ping_deadline]. This keeps track of
     /// the last time we heard from the client, while `ping_deadline` tracks
-    /// PING/PONG response pairs.
+    /// PING/PONG response pairs. Once it fires, it is re-armed to fire again after
+    /// the ping timeout, so that an unanswered PING is noticed.
+    ///
+    /// Lines from the client are read as soon as they arrive, but only handed out
+    /// once [FakeLag] lets them through.
     async fn next_incoming<'a>(
         &mut self,
         own_buf: &'a mut Vec<u8>,
         ref_buf: &'a mut Arc<Bytes>,
+        dm_buf: &'a mut Arc<Bytes>,
+        grant_timer: Option<Instant>,
     ) -> IrcResult<Signal<'a>> {
-        select! {
-            _ = &mut self.timeout.as_mut() => Ok(Signal::Timeout),
-            msg = Self::next_client_msg(&mut self.r_rx, own_buf, b'\n', 10240) => Ok(Signal::Client(msg?)),
-            res = Self::next_channel_msg(&mut self.c_rx, ref_buf) => {
-                // Ordering here is important - first the response is destructured `?`
-                // so that any errors will cause the timer to NOT reset - bad/invalid messages
-                // from the remote client won't refresh their grace period.
-                let (name, msg) = res?;
-
-                self.timeout.as_mut().reset(Instant::now() + Duration::from_secs(30));
-
-                Ok(Signal::Channel(name, msg))
-            },
-            msg = Self::next_server_msg(&mut self.s_rx) => Ok(Signal::Server(msg?)),
+        let config = self.network.config();
+        let (max_line, idle_timeout) = (config.limits.max_line, config.limits.idle_timeout());
+        let ping_timeout = config.limits.ping_timeout();
+
+        // Created once, so that they may borrow the buffers for as long
+        // as the signal they produce does while reading goes on.
+        let grant = Self::wait_until(grant_timer);
+        let channel = Self::next_channel_msg(&mut self.c_rx, ref_buf);
+        let direct = Self::next_direct_msg(&mut self.d_rx, dm_buf);
+        let server = Self::next_server_msg(&mut self.s_rx);
+        tokio::pin!(grant, channel, direct, server);
+
+        loop {
+            let ready = self.recvq.front().map(|(at, _)| *at);
+
+            select! {
+                _ = &mut self.timeout.as_mut() => {
+                    self.timeout.as_mut().reset(Instant::now() + ping_timeout);
+                    return Ok(Signal::Timeout);
+                },
+                _ = &mut grant => return Ok(Signal::Grant),
+                _ = Self::wait_until(ready) => {
+                    let Some((_, line)) = self.recvq.pop_front() else {
+                        continue;
+                    };
+                    *own_buf = line;
+                    return Ok(Signal::Client(ircv3_parse::parse(str::from_utf8(own_buf)?)?));
+                },
+                line = Self::next_client_line(&mut self.r_rx, &mut self.partial, max_line) => {
+                    // Ordering here is important - first the line is unwrapped with `?`
+                    // so that any errors will cause the timer to NOT reset - bad/invalid lines
+                    // from the remote client won't refresh their grace period.
+                    let line = line?;
+
+                    self.timeout.as_mut().reset(Instant::now() + idle_timeout);
+
+                    let ready = if config.flood_exempt(self.session.oper(), self.session.account()) {
+                        Instant::now()
+                    } else {
+                        let command = command_of(&line);
+                        self.lag.charge(&config.flood, command).ok_or(IrcSessionError::ExcessFlood)?
+                    };
+                    self.recvq.push_back((ready, line));
+                },
+                res = &mut channel => return res,
+                msg = &mut direct => return Ok(Signal::Direct(msg?)),
+                order = Self::next_control(&mut self.k_rx) => return Ok(Signal::Control(order)),
+                msg = &mut server => return Ok(msg),
+            }
         }
     }
 
-    async fn next_client_msg<'a, R>(
-        reader: &mut R,
-        own_buf: &'a mut Vec<u8>,
-        delimiter: u8,
-        limit: usize,
-    ) -> IrcResult<Message<'a>>
+    /// Sleep until `deadline`, or forever if there is none.
+    async fn wait_until(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Read a whole line from the client. Whatever was read of it by
+    /// an earlier call that got cancelled is kept in `partial`.
+    async fn next_client_line<R>(reader: &mut R, partial: &mut Vec<u8>, limit: usize) -> IrcResult<Vec<u8>>
     where
         R: AsyncBufRead + Unpin,
     {
-        let start_len = own_buf.len();
-        reader
-            .take(limit as u64)
-            .read_until(delimiter, own_buf)
-            .await?;
+        let room = limit.saturating_sub(partial.len());
+        let bytes_read = reader.take(room as u64).read_until(b'\n', partial).await?;
 
-        let bytes_read = own_buf.len() - start_len;
+        if bytes_read == 0 && partial.is_empty() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
 
         // If we read the maximum allowed bytes but didn't find the delimiter, error
-        if bytes_read >= limit && own_buf.last() != Some(&delimiter) {
+        if partial.len() >= limit && partial.last() != Some(&b'\n') {
             return Err(IrcSessionError::MessageTooLong);
         }
 
-        Ok(ircv3_parse::parse(str::from_utf8(own_buf)?)?)
+        Ok(std::mem::take(partial))
     }
 
     async fn next_channel_msg<'a>(
         channels: &mut StreamMap<ChannelName, ChannelSource>,
         ref_buf: &'a mut Arc<Bytes>,
-    ) -> IrcResult<(Arc<str>, Message<'a>)> {
+    ) -> IrcResult<Signal<'a>> {
+        // An empty StreamMap yields None right away. Not being in any
+        // channel is not an error, so wait for the other signals instead.
+        if channels.is_empty() {
+            return std::future::pending().await;
+        }
+
         let Some((name, msgbuf)) = channels.next().await else {
             return Err(IrcSessionError::ChannelEOF);
         };
 
-        let _ = std::mem::replace(ref_buf, msgbuf?);
+        // Falling behind skips the oldest messages, but the stream carries
+        // on from the oldest one still buffered.
+        let msgbuf = match msgbuf {
+            Ok(msgbuf) => msgbuf,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => return Ok(Signal::ChannelLagged(name, missed)),
+        };
+
+        let _ = std::mem::replace(ref_buf, msgbuf);
 
+        let raw = Arc::clone(ref_buf);
         let msg = ircv3_parse::parse(str::from_utf8(ref_buf)?)?;
 
-        Ok((Arc::clone(&name), msg))
+        Ok(Signal::Channel(name, raw, msg))
     }
 
-    async fn next_server_msg(reader: &mut ServerSource) -> IrcResult<ServerMessage> {
-        Ok(reader.recv().await?)
+    async fn next_direct_msg<'a>(
+        reader: &mut DirectSource,
+        dm_buf: &'a mut Arc<Bytes>,
+    ) -> IrcResult<Message<'a>> {
+        // The network holds on to our sender until we disconnect.
+        let Some(msgbuf) = reader.recv().await else {
+            return std::future::pending().await;
+        };
+
+        let _ = std::mem::replace(dm_buf, msgbuf);
+
+        Ok(ircv3_parse::parse(str::from_utf8(dm_buf)?)?)
+    }
+
+    async fn next_control(reader: &mut ControlSource) -> Control {
+        // The network holds on to our sender until we disconnect.
+        match reader.recv().await {
+            Some(order) => order,
+            None => std::future::pending().await,
+        }
     }
 
-    async fn die_nice(self, _e: IrcSessionError) -> () {
-        // TODO handle graceful connection shutdown
-        let _ = self.r_rx.into_inner().unsplit(self.r_tx).shutdown().await;
+    /// Falling behind on the server bus is not fatal: the messages
+    /// missed are skipped, and the connection told to catch up.
+    async fn next_server_msg<'a>(reader: &mut ServerSource) -> Signal<'a> {
+        match reader.recv().await {
+            Ok(msg) => Signal::Server(msg),
+            Err(RecvError::Lagged(missed)) => Signal::ServerLagged(missed),
+            // Every IrcServer handle is gone, so nothing will be sent again.
+            Err(RecvError::Closed) => std::future::pending().await,
+        }
+    }
+
+    /// Say goodbye to the client and everyone sharing a channel with
+    /// it, then release everything the connection held.
+    async fn die_nice(mut self, e: IrcSessionError) -> () {
+        let id = self.session.id();
+        let reason = self.quit_reason(&e);
+        tracing::debug!(?id, %e, "closing connection");
+
+        // Peers are found through the channels, so tell them before parting.
+        if let Some(identity) = self.session.identity() {
+            let source = format!("{}!{}@{}", identity.nick, identity.user, self.session.host());
+            let line = Relay::Quit { reason: &reason }.render(&source);
+            let channels = self.c_tx.keys().map(|key| &**key);
+            self.network.send_peers(id, channels, Arc::new(line.into()));
+
+            self.network.record_whowas(Whowas {
+                nick: identity.nick.clone(),
+                user: identity.user.clone(),
+                host: self.session.host().to_owned(),
+                real: identity.real.clone(),
+                left: Utc::now(),
+            });
+        }
+
+        // Drop our senders first so channels we were the last member of
+        // can be collected.
+        self.c_rx.clear();
+        for (key, tx) in self.c_tx.drain() {
+            drop(tx);
+            self.network.channels().part(id, &key);
+        }
+
+        self.network.disconnect(id);
+
+        // The client may well be gone already, so errors are ignored.
+        let text = format!("Closing Link: {} ({reason})", self.session.host());
+        let line = Reply::Error { text: &text }.render(self.network.name(), "*");
+        self.r_tx.close(line.into()).await;
+    }
+
+    /// The reason shown in QUIT and ERROR for a connection that ended
+    /// because of `e`.
+    fn quit_reason(&self, e: &IrcSessionError) -> String {
+        match e {
+            IrcSessionError::ClientQUIT(reason) if reason.is_empty() => "Client Quit".to_owned(),
+            IrcSessionError::ClientQUIT(reason) => format!("Quit: {reason}"),
+            IrcSessionError::Timeout => {
+                let limits = &self.network.config().limits;
+                format!("Ping timeout: {} seconds", limits.idle_timeout + limits.ping_timeout)
+            }
+            IrcSessionError::IOError(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                "Remote host closed the connection".to_owned()
+            }
+            IrcSessionError::IOError(e) => format!("Read error: {e}"),
+            IrcSessionError::MessageTooLong => "Input line too long".to_owned(),
+            IrcSessionError::Killed(reason) | IrcSessionError::Shutdown(reason) => reason.clone(),
+            e => e.to_string(),
+        }
     }
 }
 
 enum Signal<'a> {
     Timeout,
+    /// The PDS grant is about to expire, or has expired.
+    Grant,
     Server(ServerMessage),
+    /// This many messages from the server bus were missed.
+    ServerLagged(u64),
     Client(Message<'a>),
-    Channel(ChannelName, Message<'a>),
+    Channel(ChannelName, Arc<Bytes>, Message<'a>),
+    /// This many messages broadcast to a channel were missed.
+    ChannelLagged(ChannelName, u64),
+    Direct(Message<'a>),
+    Control(Control),
 }
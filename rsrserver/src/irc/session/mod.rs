@@ -29,8 +29,15 @@
     of [TypeState]. This forces consumers of the state
     instance to handle state changes at compile time.
 */
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Weak},
+};
+
+use bytes::Bytes;
 use tokio::time::Instant;
-use crate::irc::Capabilities;
+use crate::irc::{Capabilities, ClientId, Profile, SaslExchange, Transport};
 
 mod machine;
 pub mod state;
@@ -38,23 +45,99 @@ pub mod state;
 /// [IrcSession] represents all state-related data that is
 /// common to all states.
 pub struct IrcSession {
+    id: ClientId,
+    client_addr: SocketAddr,
+    host: String,
+
     caps_version: u16,
     caps: Capabilities,
 
     ping_deadline: Option<(Instant, u64)>,
+
+    identity: Option<Identity>,
+
+    // Name of the oper block this session logged in with.
+    oper: Option<Box<str>>,
+    // User mode +w, receiving WALLOPS.
+    wallops: bool,
+    // User mode +i, hidden from WHO and counted apart in LUSERS.
+    invisible: bool,
+    // Away message, mirroring the typestate so that others can see it.
+    away: Option<Box<str>>,
+
+    // Account logged into through SASL.
+    account: Option<Box<str>>,
+    sasl: Option<SaslExchange>,
+
+    // Whether the connection is encrypted.
+    secure: bool,
+    // WEBIRC gateway the client connected through.
+    gateway: Option<Box<str>>,
+    // SHA-256 fingerprint of the client certificate, if one was presented.
+    certfp: Option<String>,
+
+    // Whether anything WHOIS shows changed since it was last published.
+    profile_changed: bool,
+
+    // Messages we broadcast to channels that must not be echoed back to us.
+    own_echoes: VecDeque<Weak<Bytes>>,
+}
+
+/// Who a registered client is. This mirrors the typestate, which is
+/// lost when a connection ends, so that QUIT and WHOWAS can still be
+/// sent and recorded afterwards.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub nick: String,
+    pub user: String,
+    pub real: String,
 }
 
+/// Upper bound on [IrcSession::expect_echo] entries. Echoes lost to
+/// channel lag would otherwise never be removed.
+const MAX_OWN_ECHOES: usize = 32;
+
 impl IrcSession {
     /// Create a new IrcSession with no capabilities
     /// enabled and a CAP version of 0.
-    pub fn new() -> Self {
+    pub fn new(id: ClientId, client_addr: SocketAddr, transport: Transport) -> Self {
         Self {
+            id,
+            client_addr,
+            host: host_of(&client_addr),
             caps_version: 0,
             caps: Capabilities::empty(),
             ping_deadline: None,
+            identity: None,
+            oper: None,
+            wallops: false,
+            invisible: false,
+            away: None,
+            account: None,
+            sasl: None,
+            secure: transport.secure,
+            gateway: None,
+            certfp: transport.certfp,
+            profile_changed: true,
+            own_echoes: VecDeque::new(),
         }
     }
 
+    /// The server-wide identifier of this connection.
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+
+    /// Address of the remote client.
+    pub fn client_addr(&self) -> &SocketAddr {
+        &self.client_addr
+    }
+
+    /// Hostname shown to other users in `nick!user@host`.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
     /// Retrieve the [Capabilities] bitfield
     /// for this session.
     pub fn caps(&self) -> &Capabilities {
@@ -85,6 +168,162 @@ impl IrcSession {
         self.caps_version
     }
 
+    /// Who the client registered as, with its current nick. None
+    /// until registration completes.
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+        self.profile_changed = true;
+    }
+
+    /// Follow a nick change of a registered client.
+    pub fn rename(&mut self, nick: &str) {
+        if let Some(identity) = &mut self.identity {
+            identity.nick = nick.to_owned();
+            self.profile_changed = true;
+        }
+    }
+
+    /// Name of the oper this session is logged in as, if any. What
+    /// it may do is looked up in the current configuration, so that
+    /// a rehash takes effect immediately.
+    pub fn oper(&self) -> Option<&str> {
+        self.oper.as_deref()
+    }
+
+    pub fn set_oper(&mut self, name: &str) {
+        self.oper = Some(name.into());
+        self.profile_changed = true;
+    }
+
+    pub fn clear_oper(&mut self) {
+        self.oper = None;
+        self.profile_changed = true;
+    }
+
+    /// Whether the client has user mode +w set, and so is sent WALLOPS.
+    pub fn wallops(&self) -> bool {
+        self.wallops
+    }
+
+    pub fn set_wallops(&mut self, wallops: bool) {
+        self.wallops = wallops;
+    }
+
+    /// Whether the client has user mode +i set, and so is hidden from
+    /// those who share no channel with it.
+    pub fn invisible(&self) -> bool {
+        self.invisible
+    }
+
+    pub fn set_invisible(&mut self, invisible: bool) {
+        self.invisible = invisible;
+        self.profile_changed = true;
+    }
+
+    pub fn set_away(&mut self, away: Option<&str>) {
+        self.away = away.map(Into::into);
+        self.profile_changed = true;
+    }
+
+    /// Account this session is logged into, if any.
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    pub fn set_account(&mut self, account: &str) {
+        self.account = Some(account.into());
+        self.profile_changed = true;
+    }
+
+    pub fn clear_account(&mut self) {
+        self.account = None;
+        self.profile_changed = true;
+    }
+
+    /// The AUTHENTICATE exchange currently in progress, if any.
+    pub fn sasl_mut(&mut self) -> &mut Option<SaslExchange> {
+        &mut self.sasl
+    }
+
+    /// Whether the connection is encrypted.
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Name of the WEBIRC gateway the client connected through.
+    pub fn gateway(&self) -> Option<&str> {
+        self.gateway.as_deref()
+    }
+
+    /// Take on the address of a client that connected through a
+    /// WEBIRC gateway, shown as `hostname` if the gateway resolved
+    /// one. The client only counts as secure if both its connection
+    /// to the gateway and the gateway's connection to us are.
+    pub fn set_gateway_client(&mut self, gateway: &str, ip: IpAddr, hostname: Option<&str>, secure: bool) {
+        self.client_addr = SocketAddr::new(ip, 0);
+        self.host = hostname.map_or_else(|| host_of(&self.client_addr), str::to_owned);
+        self.secure &= secure;
+        self.gateway = Some(gateway.into());
+        self.profile_changed = true;
+    }
+
+    /// Lowercase hex SHA-256 fingerprint of the client
+    /// certificate, if the client presented one.
+    pub fn certfp(&self) -> Option<&str> {
+        self.certfp.as_deref()
+    }
+
+    /// What WHOIS shows about this session, if it changed since the
+    /// last call. None until registration completes.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let identity = self.identity.as_ref().filter(|_| self.profile_changed)?;
+        let profile = Profile {
+            nick: identity.nick.clone(),
+            user: identity.user.clone(),
+            host: self.host.clone(),
+            real: identity.real.clone(),
+            account: self.account.as_deref().map(str::to_owned),
+            away: self.away.as_deref().map(str::to_owned),
+            oper: self.oper.is_some(),
+            invisible: self.invisible,
+            secure: self.secure,
+            certfp: self.certfp.clone(),
+        };
+
+        self.profile_changed = false;
+        Some(profile)
+    }
+
+    /// Remember a message this session broadcast to a channel, so that
+    /// its own copy can be recognised and dropped on the way back in.
+    pub fn expect_echo(&mut self, msg: &Arc<Bytes>) {
+        if self.own_echoes.len() >= MAX_OWN_ECHOES {
+            self.own_echoes.pop_front();
+        }
+
+        self.own_echoes.push_back(Arc::downgrade(msg));
+    }
+
+    /// Returns true, exactly once, if `msg` was registered through
+    /// [IrcSession::expect_echo]. Holding a [Weak] keeps the allocation
+    /// alive, so pointer equality can not match an unrelated message.
+    pub fn take_echo(&mut self, msg: &Arc<Bytes>) -> bool {
+        let Some(i) = self
+            .own_echoes
+            .iter()
+            .position(|own| std::ptr::eq(own.as_ptr(), Arc::as_ptr(msg)))
+        else {
+            return false;
+        };
+
+        self.own_echoes.remove(i);
+        true
+    }
+
     /// Borrow the internal ping deadline tracker.
     /// If None, then the server is not waiting on any
     /// PONG reply from the client.
@@ -95,3 +334,13 @@ impl IrcSession {
         &mut self.ping_deadline
     }
 }
+
+/// Render the client IP as a hostname. IPv6 addresses starting
+/// with `:` would otherwise be read as the start of a trailing
+/// parameter, so they are prefixed with a `0`.
+fn host_of(addr: &SocketAddr) -> String {
+    match addr.ip().to_string() {
+        ip if ip.starts_with(':') => format!("0{ip}"),
+        ip => ip,
+    }
+}
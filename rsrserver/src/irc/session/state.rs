@@ -8,6 +8,10 @@ pub struct Anonymous {
     pub nick: Option<String>,
     pub user: Option<String>,
     pub real: Option<String>,
+
+    /// Set while the client is negotiating capabilities,
+    /// which holds registration open until `CAP END`.
+    pub cap_pending: bool,
 }
 impl StateInto<Registered> for Anonymous {}
 
@@ -24,6 +28,20 @@ pub struct Registered {
 }
 impl StateInto<Authenticated> for Registered {}
 
+impl Registered {
+    /// Take on a PDS grant lasting until `expires`.
+    pub fn authenticate(self, expires: Instant) -> Authenticated {
+        Authenticated {
+            nick: self.nick,
+            user: self.user,
+            real: self.real,
+            expires,
+            warned: false,
+            away: self.away,
+        }
+    }
+}
+
 /// Typestate representing a connection that has
 /// fully authenticated itself via the RSR auth
 /// capability through its PDS and recieved a
@@ -34,7 +52,21 @@ pub struct Authenticated {
     pub user: String,
     pub real: String,
     pub expires: Instant,
+    /// Whether the client was told that the grant is about to expire.
+    pub warned: bool,
 
     pub away: Option<String>,
 }
 impl StateInto<Registered> for Authenticated {}
+
+impl Authenticated {
+    /// Fall back to a plain registered connection once the grant is gone.
+    pub fn expire(self) -> Registered {
+        Registered {
+            nick: self.nick,
+            user: self.user,
+            real: self.real,
+            away: self.away,
+        }
+    }
+}
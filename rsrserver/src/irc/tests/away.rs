@@ -0,0 +1,45 @@
+use crate::irc::tests::TestServer;
+
+#[tokio::test]
+async fn away_is_shown_to_others() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("AWAY :Gone fishing").await;
+    alice.expect(":irc.test 306 alice :You have been marked as being away").await;
+
+    bob.send("PRIVMSG alice :hello").await;
+    bob.expect(":irc.test 301 bob alice :Gone fishing").await;
+    alice.expect(":bob!bob@127.0.0.1 PRIVMSG alice :hello").await;
+
+    // NOTICE never triggers automatic replies.
+    bob.send("NOTICE alice :hello").await;
+    bob.expect_silence().await;
+    alice.expect(":bob!bob@127.0.0.1 NOTICE alice :hello").await;
+
+    bob.send("WHOIS alice").await;
+    bob.expect_all(&[
+        ":irc.test 311 bob alice alice 127.0.0.1 * :alice",
+        ":irc.test 312 bob alice irc.test :TestNet",
+        ":irc.test 301 bob alice :Gone fishing",
+        ":irc.test 318 bob alice :End of /WHOIS list",
+    ])
+    .await;
+
+    alice.send("AWAY").await;
+    alice.expect(":irc.test 305 alice :You are no longer marked as being away").await;
+
+    bob.send("PRIVMSG alice :back?").await;
+    bob.expect_silence().await;
+    alice.expect(":bob!bob@127.0.0.1 PRIVMSG alice :back?").await;
+}
+
+#[tokio::test]
+async fn away_requires_registration() {
+    let mut server = TestServer::new();
+    let mut client = server.connect();
+
+    client.send("AWAY :Gone fishing").await;
+    client.expect(":irc.test 451 * :You have not registered").await;
+}
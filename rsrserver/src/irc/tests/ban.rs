@@ -0,0 +1,90 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+
+use crate::{
+    irc::tests::{MemoryStorage, TestServer},
+    storage::{Ban, BanKind},
+};
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[caps.values]
+sasl = "PLAIN"
+
+[class.admin]
+privileges = ["ban"]
+
+[[oper]]
+name = "admin"
+password = "secret"
+class = "admin"
+"#;
+
+#[tokio::test]
+async fn ban_disconnects_and_refuses_matching_clients() {
+    let storage = MemoryStorage::default();
+    let stored = storage.bans();
+    let mut server = TestServer::with(CONFIG, storage);
+
+    let mut alice = server.register("alice").await;
+    alice.send("OPER admin secret").await;
+    alice.skip_until(":irc.test 381 alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("BAN HOST bob@* 1h :spamming").await;
+    alice.skip_until(":irc.test NOTICE alice :Added HOST ban on bob@* until").await;
+    assert_eq!(stored.lock().unwrap().len(), 1);
+
+    bob.expect(":irc.test 465 bob :You are banned from this server (spamming)").await;
+    bob.expect(":irc.test ERROR :Closing Link: 127.0.0.1 (Banned (spamming))").await;
+    bob.expect_closed().await;
+
+    let mut again = server.connect();
+    again.send("NICK bob").await;
+    again.send("USER bob 0 * :bob").await;
+    again.expect(":irc.test 465 bob :You are banned from this server (spamming)").await;
+    again.skip_until(":irc.test ERROR").await;
+    again.expect_closed().await;
+
+    alice.send("UNBAN HOST bob@*").await;
+    alice.expect(":irc.test NOTICE alice :Removed HOST ban on bob@*").await;
+    assert!(stored.lock().unwrap().is_empty());
+    server.register("bob").await;
+}
+
+#[tokio::test]
+async fn stored_account_bans_refuse_logins() {
+    let storage = MemoryStorage::default().with_password("carol", "hunter2").with_ban(Ban {
+        kind: BanKind::Account,
+        mask: "Carol".to_owned(),
+        reason: "ban evasion".to_owned(),
+        set_by: "admin".to_owned(),
+        set_at: Utc::now(),
+        expires: None,
+    });
+    let mut server = TestServer::with(CONFIG, storage);
+    server.load_bans().await;
+
+    let mut client = server.connect();
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", STANDARD.encode("\0carol\0hunter2"))).await;
+    client.expect(":irc.test 465 * :You are banned from this server (ban evasion)").await;
+    client.skip_until(":irc.test ERROR").await;
+    client.expect_closed().await;
+}
+
+#[tokio::test]
+async fn ban_requires_privilege() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+
+    alice.send("BAN IP 192.0.2.0/24 :spam").await;
+    alice.expect(":irc.test 481 alice :Permission Denied- You're not an IRC operator").await;
+}
@@ -0,0 +1,288 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::{
+    did::MemoryResolver,
+    irc::{
+        CapConfig, CapRegistry, Transport,
+        tests::{MemoryStorage, TestServer},
+    },
+};
+
+const SASL_CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[caps.values]
+sasl = "PLAIN,EXTERNAL"
+"#;
+
+#[tokio::test]
+async fn negotiation_holds_registration() {
+    let mut server = TestServer::new();
+    let mut client = server.connect();
+
+    client.send("CAP LS 302").await;
+    client.send("NICK alice").await;
+    client.send("USER alice 0 * :Alice").await;
+    client.skip_until(":irc.test CAP * LS :").await;
+    client.expect_silence().await;
+
+    client.send("CAP REQ :account-tag echo-message").await;
+    client.expect(":irc.test CAP alice ACK :account-tag echo-message").await;
+
+    client.send("CAP END").await;
+    client.expect_numeric("001").await;
+}
+
+#[tokio::test]
+async fn unknown_capability_is_refused() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("CAP REQ :echo-message example.org/nonsense").await;
+    alice.expect(":irc.test CAP alice NAK :echo-message example.org/nonsense").await;
+
+    alice.send("CAP LIST").await;
+    alice.expect(":irc.test CAP alice LIST :").await;
+}
+
+#[tokio::test]
+async fn overlong_request_is_refused_whole() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    let list = ["echo-message"; 35].join(" ");
+    alice.send(&format!("CAP REQ :{list}")).await;
+    alice.expect(&format!(":irc.test CAP alice NAK :{list}")).await;
+
+    alice.send("CAP LIST").await;
+    alice.expect(":irc.test CAP alice LIST :").await;
+}
+
+#[test]
+fn unknown_disabled_capability_lists_known_ones() {
+    let config = CapConfig { disabled: vec!["echo".into()], ..Default::default() };
+    let error = CapRegistry::new(&config).err().unwrap().to_string();
+
+    assert!(error.starts_with("Unknown capability: echo (expected one of: account-notify"));
+    assert!(error.contains(" echo-message "));
+}
+
+#[tokio::test]
+async fn only_implemented_capabilities_offered() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("CAP LS 302").await;
+    alice
+        .expect(":irc.test CAP alice LS :account-notify account-tag cap-notify echo-message sasl standard-replies")
+        .await;
+
+    alice.send("CAP REQ :batch").await;
+    alice.expect(":irc.test CAP alice NAK :batch").await;
+
+    alice.send("CAP REQ :rsr.chat/plc-oauthbearer").await;
+    alice.expect(":irc.test CAP alice NAK :rsr.chat/plc-oauthbearer").await;
+}
+
+#[tokio::test]
+async fn values_only_for_cap_302() {
+    let mut server = TestServer::with(SASL_CONFIG, MemoryStorage::default());
+
+    let mut old = server.connect();
+    old.send("CAP LS").await;
+    let ls = old.skip_until(":irc.test CAP * LS").await;
+    assert!(ls.split(' ').any(|cap| cap == "sasl"), "{ls}");
+
+    let mut new = server.connect();
+    new.send("CAP LS 302").await;
+    let ls = new.skip_until(":irc.test CAP * LS").await;
+    assert!(ls.split(' ').any(|cap| cap == "sasl=PLAIN,EXTERNAL"), "{ls}");
+}
+
+#[tokio::test]
+async fn plc_oauthbearer_needs_resolver() {
+    let mut server = TestServer::with_resolver(SASL_CONFIG, MemoryStorage::default(), MemoryResolver::default());
+    let mut alice = server.register("alice").await;
+
+    alice.send("CAP LS").await;
+    let ls = alice.skip_until(":irc.test CAP alice LS").await;
+    assert!(ls.ends_with(" rsr.chat/plc-oauthbearer"), "{ls}");
+
+    alice.send("CAP REQ :rsr.chat/plc-oauthbearer").await;
+    alice.expect(":irc.test CAP alice ACK :rsr.chat/plc-oauthbearer").await;
+}
+
+#[tokio::test]
+async fn sasl_plain() {
+    let storage = MemoryStorage::default().with_password("alice", "hunter2");
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+    let mut client = server.connect();
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+    client.send("NICK alice").await;
+    client.send("USER alice 0 * :Alice").await;
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", STANDARD.encode("\0alice\0hunter2"))).await;
+    client.expect(":irc.test 900 alice alice!alice@127.0.0.1 alice :You are now logged in as alice").await;
+    client.expect(":irc.test 903 alice :SASL authentication successful").await;
+
+    client.send("CAP END").await;
+    client.expect_numeric("001").await;
+}
+
+#[tokio::test]
+async fn sasl_plain_wrong_password() {
+    let storage = MemoryStorage::default().with_password("alice", "hunter2");
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+    let mut client = server.connect();
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", STANDARD.encode("\0alice\0hunter3"))).await;
+    client.expect(":irc.test 904 * :SASL authentication failed").await;
+}
+
+#[tokio::test]
+async fn sasl_external() {
+    let storage = MemoryStorage::default().with_certfp("alice", "ab12");
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+    let mut client = server.connect_with(Transport::secure(Some("ab12".to_owned())));
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    client.send("AUTHENTICATE EXTERNAL").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send("AUTHENTICATE +").await;
+    client.expect(":irc.test 900 * *!*@127.0.0.1 alice :You are now logged in as alice").await;
+    client.expect(":irc.test 903 * :SASL authentication successful").await;
+}
+
+#[tokio::test]
+async fn sasl_external_needs_certfp() {
+    let storage = MemoryStorage::default().with_certfp("alice", "ab12");
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+
+    for transport in [Transport::plaintext(), Transport::secure(None), Transport::secure(Some("cd34".to_owned()))] {
+        let mut client = server.connect_with(transport);
+        client.send("CAP REQ :sasl").await;
+        client.expect(":irc.test CAP * ACK :sasl").await;
+
+        client.send("AUTHENTICATE EXTERNAL").await;
+        client.expect(":irc.test AUTHENTICATE +").await;
+        client.send("AUTHENTICATE +").await;
+        client.expect(":irc.test 904 * :SASL authentication failed").await;
+    }
+}
+
+#[tokio::test]
+async fn sasl_chunked_payload() {
+    // 300 bytes encode to exactly one full 400 byte chunk, which must
+    // be followed by `+` to end the payload.
+    let password = "x".repeat(293);
+    let storage = MemoryStorage::default().with_password("alice", &password);
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+    let mut client = server.connect();
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    let payload = STANDARD.encode(format!("\0alice\0{password}"));
+    assert_eq!(payload.len(), 400);
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {payload}")).await;
+    client.expect_silence().await;
+    client.send("AUTHENTICATE +").await;
+    client.expect(":irc.test 900 * *!*@127.0.0.1 alice :You are now logged in as alice").await;
+    client.expect(":irc.test 903 * :SASL authentication successful").await;
+}
+
+#[tokio::test]
+async fn sasl_payload_too_long() {
+    let config = format!("{SASL_CONFIG}\n[flood]\nburst = 1000\n");
+    let mut server = TestServer::with(&config, MemoryStorage::default());
+    let mut client = server.connect();
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    // A single chunk over 400 bytes.
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", "A".repeat(404))).await;
+    client.expect(":irc.test 905 * :SASL message too long").await;
+
+    // Full chunks adding up to more than the whole payload may be.
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    let chunk = format!("AUTHENTICATE {}", "A".repeat(400));
+    for _ in 0..20 {
+        client.send(&chunk).await;
+    }
+    client.expect_silence().await;
+    client.send(&chunk).await;
+    client.expect(":irc.test 905 * :SASL message too long").await;
+}
+
+#[tokio::test]
+async fn sasl_abort() {
+    let storage = MemoryStorage::default().with_password("alice", "hunter2");
+    let mut server = TestServer::with(SASL_CONFIG, storage);
+    let mut client = server.connect();
+
+    client.send("CAP REQ :sasl").await;
+    client.expect(":irc.test CAP * ACK :sasl").await;
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", "A".repeat(400))).await;
+    client.send("AUTHENTICATE *").await;
+    client.expect(":irc.test 906 * :SASL authentication aborted").await;
+
+    // The next exchange starts from scratch.
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+    client.send(&format!("AUTHENTICATE {}", STANDARD.encode("\0alice\0hunter2"))).await;
+    client.expect_numeric("900").await;
+    client.expect_numeric("903").await;
+
+    client.send("AUTHENTICATE PLAIN").await;
+    client.expect(":irc.test 907 * :You have already authenticated using SASL").await;
+}
+
+#[tokio::test]
+async fn sts_policy() {
+    let config = r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [caps.sts]
+        port = 6697
+        duration = 300
+    "#;
+    let mut server = TestServer::with(config, MemoryStorage::default());
+
+    let mut plaintext = server.connect();
+    plaintext.send("CAP LS 302").await;
+    let ls = plaintext.skip_until(":irc.test CAP * LS").await;
+    assert!(ls.split(' ').any(|cap| cap == "sts=port=6697"), "{ls}");
+
+    let mut secure = server.connect_with(Transport::secure(None));
+    secure.send("CAP LS 302").await;
+    let ls = secure.skip_until(":irc.test CAP * LS").await;
+    assert!(ls.split(' ').any(|cap| cap == "sts=duration=300"), "{ls}");
+
+    secure.send("CAP REQ sts").await;
+    secure.skip_until(":irc.test CAP * NAK :sts").await;
+}
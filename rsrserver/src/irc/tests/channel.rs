@@ -0,0 +1,344 @@
+use crate::irc::tests::{MemoryStorage, TestServer};
+
+#[tokio::test]
+async fn join_is_seen_by_members() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("JOIN #x").await;
+    alice.expect_all(&[
+        ":alice!alice@127.0.0.1 JOIN #x",
+        ":irc.test 353 alice = #x :@alice",
+        ":irc.test 366 alice #x :End of /NAMES list",
+    ])
+    .await;
+
+    bob.send("JOIN #x").await;
+    bob.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+    let names = bob.expect_numeric("353").await;
+    let mut names: Vec<_> = names.rsplit_once(" :").unwrap().1.split(' ').collect();
+    names.sort();
+    assert_eq!(names, ["@alice", "bob"]);
+    bob.expect(":irc.test 366 bob #x :End of /NAMES list").await;
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+}
+
+#[tokio::test]
+async fn messages_reach_other_members() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("PRIVMSG #x :hello").await;
+    bob.expect(":alice!alice@127.0.0.1 PRIVMSG #x :hello").await;
+    alice.expect_silence().await;
+    carol.expect_silence().await;
+}
+
+#[tokio::test]
+async fn private_message() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("PRIVMSG bob :hi").await;
+    bob.expect(":alice!alice@127.0.0.1 PRIVMSG bob :hi").await;
+
+    alice.send("PRIVMSG nobody :hi").await;
+    alice.expect(":irc.test 401 alice nobody :No such nick/channel").await;
+}
+
+#[tokio::test]
+async fn part_nick_and_quit_reach_peers() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    for channel in ["#x", "#y"] {
+        alice.send(&format!("JOIN {channel}")).await;
+        alice.skip_until(":irc.test 366 ").await;
+        bob.send(&format!("JOIN {channel}")).await;
+        bob.skip_until(":irc.test 366 ").await;
+        alice.expect(&format!(":bob!bob@127.0.0.1 JOIN {channel}")).await;
+    }
+
+    // Sent once, even though bob shares two channels with alice.
+    bob.send("NICK robert").await;
+    bob.expect(":bob!bob@127.0.0.1 NICK :robert").await;
+    alice.expect(":bob!bob@127.0.0.1 NICK :robert").await;
+    alice.expect_silence().await;
+
+    bob.send("PART #x :later").await;
+    bob.expect(":robert!bob@127.0.0.1 PART #x :later").await;
+    alice.expect(":robert!bob@127.0.0.1 PART #x :later").await;
+
+    bob.send("QUIT :bye").await;
+    alice.expect(":robert!bob@127.0.0.1 QUIT :Quit: bye").await;
+    alice.expect_silence().await;
+}
+
+#[tokio::test]
+async fn lagging_member_is_told_and_kept() {
+    let config = r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [limits]
+        channel_capacity = 2
+
+        [flood]
+        burst = 1000
+    "#;
+    let mut server = TestServer::with(config, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    bob.send("CAP REQ standard-replies").await;
+    bob.expect(":irc.test CAP bob ACK :standard-replies").await;
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    // Far more than the channel buffers, faster than bob takes them.
+    for i in 0..100 {
+        alice.send(&format!("PRIVMSG #x :{i}")).await;
+    }
+
+    let note = bob.skip_until(":irc.test NOTE * MESSAGES_LOST :").await;
+    assert!(note.ends_with(" messages in #x were lost because you fell behind"), "{note}");
+
+    bob.send("PING :still here").await;
+    bob.skip_until(":irc.test PONG irc.test :still here").await;
+}
+
+#[tokio::test]
+async fn only_operators_change_channel_modes() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    bob.send("MODE #x +i").await;
+    bob.expect(":irc.test 482 bob #x :You're not channel operator").await;
+
+    alice.send("MODE #x +o nobody").await;
+    alice.expect(":irc.test 441 alice nobody #x :They aren't on that channel").await;
+
+    alice.send("MODE #x +o bob").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE #x +o bob").await;
+    bob.expect(":alice!alice@127.0.0.1 MODE #x +o bob").await;
+
+    bob.send("MODE #x +zi").await;
+    bob.expect(":irc.test 472 bob z :is unknown mode char to me").await;
+    bob.expect(":bob!bob@127.0.0.1 MODE #x +i").await;
+    alice.expect(":bob!bob@127.0.0.1 MODE #x +i").await;
+
+    // Setting a mode that is already set changes nothing.
+    bob.send("MODE #x +i").await;
+    bob.expect_silence().await;
+}
+
+#[tokio::test]
+async fn channel_modes_restrict_joining() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("MODE #x +kl secret 2").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE #x +kl secret 2").await;
+    bob.expect(":alice!alice@127.0.0.1 MODE #x +kl secret 2").await;
+
+    alice.send("MODE #x").await;
+    alice.expect(":irc.test 324 alice #x +kl secret 2").await;
+    alice.expect_numeric("329").await;
+
+    // Only members get to see the key.
+    carol.send("MODE #x").await;
+    carol.expect(":irc.test 324 carol #x +kl * 2").await;
+    carol.expect_numeric("329").await;
+
+    carol.send("JOIN #x").await;
+    carol.expect(":irc.test 475 carol #x :Cannot join channel (+k)").await;
+
+    carol.send("JOIN #x secret").await;
+    carol.expect(":irc.test 471 carol #x :Cannot join channel (+l)").await;
+
+    alice.send("MODE #x -l+i").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE #x -l+i").await;
+
+    carol.send("JOIN #x secret").await;
+    carol.expect(":irc.test 473 carol #x :Cannot join channel (+i)").await;
+
+    alice.send("MODE #x -ik secret").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE #x -ik *").await;
+
+    carol.send("JOIN #x").await;
+    carol.expect(":carol!carol@127.0.0.1 JOIN #x").await;
+}
+
+#[tokio::test]
+async fn invite_lets_past_invite_only_once() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    alice.send("JOIN #x").await;
+    alice.skip_until(":irc.test 366 ").await;
+    alice.send("MODE #x +i").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE #x +i").await;
+
+    bob.send("JOIN #x").await;
+    bob.expect(":irc.test 473 bob #x :Cannot join channel (+i)").await;
+
+    carol.send("INVITE bob #x").await;
+    carol.expect(":irc.test 442 carol #x :You're not on that channel").await;
+
+    alice.send("INVITE nobody #x").await;
+    alice.expect(":irc.test 401 alice nobody :No such nick/channel").await;
+
+    alice.send("INVITE bob #x").await;
+    alice.expect(":irc.test 341 alice bob #x").await;
+    bob.expect(":alice!alice@127.0.0.1 INVITE bob #x").await;
+
+    bob.send("JOIN #x").await;
+    bob.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+    bob.skip_until(":irc.test 366 ").await;
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("INVITE bob #x").await;
+    alice.expect(":irc.test 443 alice bob #x :is already on channel").await;
+
+    bob.send("INVITE carol #x").await;
+    bob.expect(":irc.test 482 bob #x :You're not channel operator").await;
+
+    // The invite is used up by joining.
+    bob.send("PART #x").await;
+    bob.expect(":bob!bob@127.0.0.1 PART #x").await;
+    bob.send("JOIN #x").await;
+    bob.expect(":irc.test 473 bob #x :Cannot join channel (+i)").await;
+}
+
+#[tokio::test]
+async fn topic_is_set_by_members_and_shown_to_anyone() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("TOPIC #x").await;
+    alice.expect(":irc.test 331 alice #x :No topic is set").await;
+
+    bob.send("TOPIC #x :hello world").await;
+    bob.expect(":bob!bob@127.0.0.1 TOPIC #x :hello world").await;
+    alice.expect(":bob!bob@127.0.0.1 TOPIC #x :hello world").await;
+
+    carol.send("TOPIC #x :mine now").await;
+    carol.expect(":irc.test 442 carol #x :You're not on that channel").await;
+
+    carol.send("TOPIC #x").await;
+    carol.expect(":irc.test 332 carol #x :hello world").await;
+    let who_time = carol.expect_numeric("333").await;
+    assert!(who_time.starts_with(":irc.test 333 carol #x bob "), "{who_time}");
+
+    carol.send("TOPIC #nope").await;
+    carol.expect(":irc.test 403 carol #nope :No such channel").await;
+
+    alice.send("TOPIC #x :").await;
+    alice.expect(":alice!alice@127.0.0.1 TOPIC #x :").await;
+    carol.send("TOPIC #x").await;
+    carol.expect(":irc.test 331 carol #x :No topic is set").await;
+}
+
+#[tokio::test]
+async fn kicked_member_leaves_the_channel() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    bob.send("KICK #x alice").await;
+    bob.expect(":irc.test 482 bob #x :You're not channel operator").await;
+
+    alice.send("KICK #x nobody").await;
+    alice.expect(":irc.test 441 alice nobody #x :They aren't on that channel").await;
+
+    alice.send("KICK #x bob :bye").await;
+    alice.expect(":alice!alice@127.0.0.1 KICK #x bob :bye").await;
+    bob.expect(":alice!alice@127.0.0.1 KICK #x bob :bye").await;
+
+    alice.send("NAMES #x").await;
+    alice.expect(":irc.test 353 alice = #x :@alice").await;
+    alice.send("PRIVMSG #x :hi").await;
+    bob.expect_silence().await;
+
+    bob.send("JOIN #x").await;
+    bob.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+}
+
+#[tokio::test]
+async fn list_shows_member_counts_and_topics() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    for name in ["#b", "#a"] {
+        alice.send(&format!("JOIN {name}")).await;
+        alice.skip_until(":irc.test 366 ").await;
+    }
+    bob.send("JOIN #a").await;
+    bob.skip_until(":irc.test 366 ").await;
+    alice.send("TOPIC #a :about a").await;
+    bob.expect(":alice!alice@127.0.0.1 TOPIC #a :about a").await;
+
+    bob.send("LIST").await;
+    bob.expect_all(&[
+        ":irc.test 321 bob Channel :Users  Name",
+        ":irc.test 322 bob #a 2 :about a",
+        ":irc.test 322 bob #b 1 :",
+        ":irc.test 323 bob :End of /LIST",
+    ])
+    .await;
+
+    bob.send("LIST #B,#nope").await;
+    bob.expect_all(&[
+        ":irc.test 321 bob Channel :Users  Name",
+        ":irc.test 322 bob #b 1 :",
+        ":irc.test 323 bob :End of /LIST",
+    ])
+    .await;
+}
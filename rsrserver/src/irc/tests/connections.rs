@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+
+use crate::{
+    config::ConnectionsConfig,
+    irc::Bans,
+    storage::{Ban, BanKind},
+    throttle::{ConnectionThrottle, Rejection},
+};
+
+fn throttle(config: ConnectionsConfig) -> Arc<ConnectionThrottle> {
+    Arc::new(ConnectionThrottle::new(&config, Arc::new(Bans::default())))
+}
+
+#[test]
+fn connections_per_ip_and_range() {
+    let throttle = throttle(ConnectionsConfig {
+        per_ip: 2,
+        per_range: 3,
+        ..ConnectionsConfig::default()
+    });
+
+    let first = throttle.admit("192.0.2.1".parse().unwrap()).unwrap();
+    let _second = throttle.admit("192.0.2.1".parse().unwrap()).unwrap();
+    assert_eq!(throttle.admit("192.0.2.1".parse().unwrap()).err(), Some(Rejection::TooManyFromHost));
+
+    // Another address in the same /24.
+    let _third = throttle.admit("192.0.2.2".parse().unwrap()).unwrap();
+    assert_eq!(throttle.admit("192.0.2.3".parse().unwrap()).err(), Some(Rejection::TooManyFromRange));
+    assert!(throttle.admit("198.51.100.1".parse().unwrap()).is_ok());
+
+    // Closing a connection frees its slot.
+    drop(first);
+    assert!(throttle.admit("192.0.2.1".parse().unwrap()).is_ok());
+}
+
+#[test]
+fn connections_throttled() {
+    let throttle = throttle(ConnectionsConfig {
+        throttle: 3,
+        exempt: vec!["10.0.0.0/8".parse().unwrap()],
+        ..ConnectionsConfig::default()
+    });
+
+    for _ in 0..3 {
+        assert!(throttle.admit("2001:db8::1".parse().unwrap()).is_ok());
+    }
+    assert_eq!(throttle.admit("2001:db8::1".parse().unwrap()).err(), Some(Rejection::Throttled));
+    assert_eq!(throttle.admit("2001:db8::1".parse().unwrap()).err(), Some(Rejection::Throttled));
+
+    for _ in 0..10 {
+        assert!(throttle.admit("10.1.2.3".parse().unwrap()).is_ok());
+    }
+}
+
+#[test]
+fn banned_addresses_refused() {
+    let bans = Arc::new(Bans::default());
+    let throttle = Arc::new(ConnectionThrottle::new(
+        &ConnectionsConfig {
+            exempt: vec!["192.0.2.0/24".parse().unwrap()],
+            ..ConnectionsConfig::default()
+        },
+        Arc::clone(&bans),
+    ));
+
+    bans.add(Ban {
+        kind: BanKind::Ip,
+        mask: "192.0.2.0/25".to_owned(),
+        reason: "spam".to_owned(),
+        set_by: "admin".to_owned(),
+        set_at: Utc::now(),
+        expires: None,
+    });
+
+    // Bans hold even for exempt ranges.
+    let rejection = throttle.admit("192.0.2.1".parse().unwrap()).err();
+    assert_eq!(rejection, Some(Rejection::Banned("spam".to_owned())));
+    assert!(throttle.admit("192.0.2.200".parse().unwrap()).is_ok());
+}
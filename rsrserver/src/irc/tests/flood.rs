@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::irc::tests::{MemoryStorage, TestServer};
+
+/// Ten lines a second after a burst of four, and no more than a second
+/// behind.
+const FLOOD_CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[flood]
+burst = 4
+rate = 10.0
+max_lag = 1
+costs = { JOIN = 3 }
+exempt_classes = ["bots"]
+
+[class.bots]
+
+[[oper]]
+name = "bot"
+password = "secret"
+class = "bots"
+"#;
+
+#[tokio::test]
+async fn lines_past_the_burst_are_lagged() {
+    let mut server = TestServer::with(FLOOD_CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+
+    let start = Instant::now();
+    for i in 0..6 {
+        alice.send(&format!("PING :{i}")).await;
+    }
+    for i in 0..6 {
+        alice.expect(&format!(":irc.test PONG irc.test :{i}")).await;
+    }
+
+    // Registration and the first PINGs spent the burst.
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn excess_flood() {
+    let mut server = TestServer::with(FLOOD_CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    alice.send("JOIN #test").await;
+    alice.skip_until(":irc.test 366 ").await;
+    bob.send("JOIN #test").await;
+    bob.skip_until(":irc.test 366 ").await;
+    alice.skip_until(":bob!").await;
+
+    for _ in 0..5 {
+        bob.send("JOIN #test").await;
+    }
+
+    bob.skip_until(":irc.test ERROR :Closing Link: 127.0.0.1 (Excess Flood)").await;
+    bob.expect_closed().await;
+    alice.expect(":bob!bob@127.0.0.1 QUIT :Excess Flood").await;
+}
+
+#[tokio::test]
+async fn exempt_classes_are_not_lagged() {
+    let mut server = TestServer::with(FLOOD_CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    alice.send("OPER bot secret").await;
+    alice.expect_numeric("381").await;
+
+    for i in 0..30 {
+        alice.send(&format!("PING :{i}")).await;
+    }
+    for i in 0..30 {
+        alice.expect(&format!(":irc.test PONG irc.test :{i}")).await;
+    }
+}
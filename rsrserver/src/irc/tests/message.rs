@@ -0,0 +1,100 @@
+use crate::irc::tests::TestServer;
+
+#[tokio::test]
+async fn privmsg_errors() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    bob.send("JOIN #x").await;
+    bob.skip_until(":irc.test 366 ").await;
+
+    alice.send("PRIVMSG").await;
+    alice.expect(":irc.test 411 alice :No recipient given (PRIVMSG)").await;
+    alice.send("PRIVMSG bob").await;
+    alice.expect(":irc.test 412 alice :No text to send").await;
+    alice.send("PRIVMSG bob :").await;
+    alice.expect(":irc.test 412 alice :No text to send").await;
+
+    alice.send("PRIVMSG nobody :hi").await;
+    alice.expect(":irc.test 401 alice nobody :No such nick/channel").await;
+    alice.send("PRIVMSG #nowhere :hi").await;
+    alice.expect(":irc.test 401 alice #nowhere :No such nick/channel").await;
+    alice.send("PRIVMSG #x :hi").await;
+    alice.expect(":irc.test 404 alice #x :Cannot send to channel").await;
+
+    alice.send("PRIVMSG a,b,c,d,bob :hi").await;
+    alice.expect(":irc.test 407 alice bob :Too many targets. No message delivered").await;
+    bob.expect_silence().await;
+}
+
+#[tokio::test]
+async fn notice_never_replies() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    bob.send("JOIN #x").await;
+    bob.skip_until(":irc.test 366 ").await;
+
+    for line in ["NOTICE", "NOTICE bob", "NOTICE nobody :hi", "NOTICE #x :hi", "NOTICE a,b,c,d,bob :hi"] {
+        alice.send(line).await;
+    }
+    alice.expect_silence().await;
+    bob.expect_silence().await;
+
+    alice.send("NOTICE bob :hello").await;
+    bob.expect(":alice!alice@127.0.0.1 NOTICE bob :hello").await;
+}
+
+#[tokio::test]
+async fn privmsg_to_several_targets() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    for client in [&mut alice, &mut carol] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":carol!carol@127.0.0.1 JOIN #x").await;
+
+    alice.send("PRIVMSG bob,#x,nobody :hi all").await;
+    bob.expect(":alice!alice@127.0.0.1 PRIVMSG bob :hi all").await;
+    carol.expect(":alice!alice@127.0.0.1 PRIVMSG #x :hi all").await;
+    alice.expect(":irc.test 401 alice nobody :No such nick/channel").await;
+}
+
+#[tokio::test]
+async fn echo_message() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("CAP REQ echo-message").await;
+    alice.expect(":irc.test CAP alice ACK :echo-message").await;
+    for client in [&mut alice, &mut bob] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("PRIVMSG #x :to the channel").await;
+    alice.expect(":alice!alice@127.0.0.1 PRIVMSG #x :to the channel").await;
+    bob.expect(":alice!alice@127.0.0.1 PRIVMSG #x :to the channel").await;
+
+    alice.send("NOTICE bob :to bob").await;
+    alice.expect(":alice!alice@127.0.0.1 NOTICE bob :to bob").await;
+    bob.expect(":alice!alice@127.0.0.1 NOTICE bob :to bob").await;
+
+    // Exactly once, even when alice is the recipient too.
+    alice.send("PRIVMSG alice :to myself").await;
+    alice.expect(":alice!alice@127.0.0.1 PRIVMSG alice :to myself").await;
+    alice.expect_silence().await;
+
+    // Without echo-message, nothing comes back.
+    bob.send("PRIVMSG #x :from bob").await;
+    alice.expect(":bob!bob@127.0.0.1 PRIVMSG #x :from bob").await;
+    bob.expect_silence().await;
+}
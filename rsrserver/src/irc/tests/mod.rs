@@ -0,0 +1,305 @@
+/*!
+    Drives a whole [IrcServer] through scripted clients connected
+    over in-memory pipes, so that tests can assert on the exact lines
+    each client sees.
+*/
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf, duplex, split},
+    time::timeout,
+};
+
+use crate::{
+    config::Config,
+    did::MemoryResolver,
+    irc::{IrcServer, Network, Transport},
+    storage::{Account, Ban, BanKind, Storage, StorageResult, Whois},
+};
+
+mod away;
+mod ban;
+mod cap;
+mod channel;
+mod connections;
+mod flood;
+mod message;
+mod oauthbearer;
+mod oper;
+mod query;
+mod registration;
+mod sendq;
+mod stats;
+mod webirc;
+mod whois;
+
+/// Name of the server under test, as it appears in replies.
+pub const SERVER: &str = "irc.test";
+
+/// How long a client waits for a line before the test fails.
+const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client listens for a line that must not arrive.
+const SILENCE: Duration = Duration::from_millis(100);
+
+/// Size of the in-memory pipe between a client and the server.
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+"#;
+
+/// Accounts and bans held in memory.
+#[derive(Default)]
+pub struct MemoryStorage {
+    passwords: HashMap<String, String>,
+    certfps: HashMap<String, String>,
+    // Shared, so that tests can look at what was stored.
+    bans: Arc<Mutex<Vec<Ban>>>,
+}
+
+impl MemoryStorage {
+    /// Add an account that logs in with `password`.
+    pub fn with_password(mut self, account: &str, password: &str) -> Self {
+        self.passwords.insert(account.to_owned(), password.to_owned());
+        self
+    }
+
+    /// Add an account that logs in with the certificate `certfp`.
+    pub fn with_certfp(mut self, account: &str, certfp: &str) -> Self {
+        self.certfps.insert(certfp.to_owned(), account.to_owned());
+        self
+    }
+
+    /// Store a ban, to be loaded by [TestServer::load_bans].
+    pub fn with_ban(self, ban: Ban) -> Self {
+        self.bans.lock().unwrap().push(ban);
+        self
+    }
+
+    /// The bans stored so far, as the server goes on to change them.
+    pub fn bans(&self) -> Arc<Mutex<Vec<Ban>>> {
+        Arc::clone(&self.bans)
+    }
+}
+
+impl Storage for MemoryStorage {
+    type Error = ();
+
+    // Nicks are registered when an account of the same name has a password.
+    async fn whois(&self, nick: &str) -> StorageResult<Option<Whois>, Self::Error> {
+        Ok(self.passwords.contains_key(nick).then_some(Whois {}))
+    }
+
+    async fn check_password(&self, account: &str, password: &str) -> StorageResult<Option<Account>, Self::Error> {
+        let matches = self.passwords.get(account).is_some_and(|p| p == password);
+        Ok(matches.then(|| Account { name: account.to_owned() }))
+    }
+
+    async fn account_by_certfp(&self, fingerprint: &str) -> StorageResult<Option<Account>, Self::Error> {
+        let account = self.certfps.get(fingerprint);
+        Ok(account.map(|name| Account { name: name.clone() }))
+    }
+
+    async fn bans(&self) -> StorageResult<Vec<Ban>, Self::Error> {
+        Ok(self.bans.lock().unwrap().clone())
+    }
+
+    async fn add_ban(&self, ban: &Ban) -> StorageResult<(), Self::Error> {
+        let mut bans = self.bans.lock().unwrap();
+        bans.retain(|b| !(b.kind == ban.kind && b.mask == ban.mask));
+        bans.push(ban.clone());
+        Ok(())
+    }
+
+    async fn remove_ban(&self, kind: BanKind, mask: &str) -> StorageResult<(), Self::Error> {
+        self.bans.lock().unwrap().retain(|b| !(b.kind == kind && b.mask == mask));
+        Ok(())
+    }
+}
+
+/// A server with nothing but in-memory clients attached.
+pub struct TestServer {
+    server: IrcServer<MemoryStorage>,
+    next_port: u16,
+}
+
+impl TestServer {
+    /// A server with the default test configuration and no accounts.
+    pub fn new() -> Self {
+        Self::with(CONFIG, MemoryStorage::default())
+    }
+
+    /// A server with the given configuration, which is parsed the
+    /// same way a configuration file is, and storage.
+    pub fn with(config: &str, storage: MemoryStorage) -> Self {
+        Self::with_network(network(config), storage)
+    }
+
+    /// A server like [TestServer::with] that resolves DIDs through
+    /// `resolver`, so that OAUTHBEARER logins are offered.
+    pub fn with_resolver(config: &str, storage: MemoryStorage, resolver: MemoryResolver) -> Self {
+        Self::with_network(network(config).with_resolver(resolver), storage)
+    }
+
+    /// A server like [TestServer::with] whose configuration is read
+    /// from `path`, so that REHASH can read it again.
+    pub fn load(path: &Path, storage: MemoryStorage) -> Self {
+        let config = Config::load(path).expect("test configuration is valid");
+        Self::with_network(Network::new(config).expect("test network can be created"), storage)
+    }
+
+    fn with_network(network: Network, storage: MemoryStorage) -> Self {
+        Self {
+            server: IrcServer::new(storage, network),
+            next_port: 1024,
+        }
+    }
+
+    /// Put the bans in storage into effect, as happens at startup.
+    pub async fn load_bans(&self) {
+        assert!(self.server.load_bans().await.is_ok(), "bans can be loaded");
+    }
+
+    /// Attach a new plaintext client.
+    pub fn connect(&mut self) -> TestClient {
+        self.connect_with(Transport::plaintext())
+    }
+
+    /// Attach a new client over a transport with the given properties.
+    pub fn connect_with(&mut self, transport: Transport) -> TestClient {
+        let (client, server) = duplex(PIPE_CAPACITY);
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, self.next_port));
+        self.next_port += 1;
+
+        tokio::spawn(self.server.connect(server, addr, transport));
+
+        let (rx, tx) = split(client);
+        TestClient {
+            rx: BufReader::new(rx),
+            tx,
+            nick: None,
+        }
+    }
+
+    /// Attach a new client and register it as `nick`, discarding the
+    /// welcome burst.
+    pub async fn register(&mut self, nick: &str) -> TestClient {
+        let mut client = self.connect();
+        client.register(nick).await;
+        client
+    }
+}
+
+fn network(config: &str) -> Network {
+    let config: Config = toml::from_str(config).expect("test configuration is valid");
+    Network::new(config).expect("test network can be created")
+}
+
+/// One end of a connection to the [TestServer], driven line by line.
+pub struct TestClient {
+    rx: BufReader<ReadHalf<DuplexStream>>,
+    tx: WriteHalf<DuplexStream>,
+    nick: Option<String>,
+}
+
+impl TestClient {
+    /// Send a single line. The line ending is added.
+    pub async fn send(&mut self, line: &str) {
+        let line = format!("{line}\r\n");
+        self.tx.write_all(line.as_bytes()).await.expect("server accepts input");
+    }
+
+    /// The next line from the server, without its line ending. Fails
+    /// the test if none arrives in time or the server hung up.
+    pub async fn recv(&mut self) -> String {
+        match self.try_recv(RECV_TIMEOUT).await {
+            Some(line) => line,
+            None => panic!("{} received nothing", self.name()),
+        }
+    }
+
+    /// Assert the next line from the server is exactly `expected`.
+    pub async fn expect(&mut self, expected: &str) {
+        let line = self.recv().await;
+        assert_eq!(line, expected, "{} received an unexpected line", self.name());
+    }
+
+    /// Assert the next lines from the server are exactly `expected`,
+    /// in order.
+    pub async fn expect_all(&mut self, expected: &[&str]) {
+        for line in expected {
+            self.expect(line).await;
+        }
+    }
+
+    /// Assert the next line from the server is the numeric `code`
+    /// addressed to this client, returning the whole line.
+    pub async fn expect_numeric(&mut self, code: &str) -> String {
+        let line = self.recv().await;
+        let prefix = format!(":{SERVER} {code} ");
+        assert!(line.starts_with(&prefix), "{} expected {code}, got {line:?}", self.name());
+        line
+    }
+
+    /// Skip lines until one starts with `prefix`, and return it.
+    pub async fn skip_until(&mut self, prefix: &str) -> String {
+        loop {
+            let line = self.recv().await;
+            if line.starts_with(prefix) {
+                return line;
+            }
+        }
+    }
+
+    /// Assert the server sends nothing more for a short while.
+    pub async fn expect_silence(&mut self) {
+        if let Some(line) = self.try_recv(SILENCE).await {
+            panic!("{} received {line:?} where nothing was expected", self.name());
+        }
+    }
+
+    /// Assert the server closed the connection.
+    pub async fn expect_closed(&mut self) {
+        let mut line = String::new();
+        let read = timeout(RECV_TIMEOUT, self.rx.read_line(&mut line)).await;
+        assert!(matches!(read, Ok(Ok(0))), "{} still connected, got {line:?}", self.name());
+    }
+
+    /// Register as `nick` and discard the welcome burst, which ends
+    /// with the MOTD.
+    pub async fn register(&mut self, nick: &str) {
+        self.send(&format!("NICK {nick}")).await;
+        self.send(&format!("USER {nick} 0 * :{nick}")).await;
+        self.nick = Some(nick.to_owned());
+
+        self.expect_numeric("001").await;
+        loop {
+            let line = self.recv().await;
+            if [" 376 ", " 422 "].iter().any(|end| line.contains(end)) {
+                break;
+            }
+        }
+    }
+
+    async fn try_recv(&mut self, wait: Duration) -> Option<String> {
+        let mut line = String::new();
+        match timeout(wait, self.rx.read_line(&mut line)).await {
+            Ok(Ok(0)) | Err(_) => None,
+            Ok(Ok(_)) => Some(line.trim_end_matches(['\r', '\n']).to_owned()),
+            Ok(Err(e)) => panic!("{} failed to read: {e}", self.name()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.nick.as_deref().unwrap_or("unregistered client")
+    }
+}
@@ -0,0 +1,378 @@
+use std::time::Duration;
+
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use chrono::{TimeDelta, Utc};
+use k256::ecdsa::signature::Signer;
+use serde_json::json;
+
+use crate::{
+    did::{DidDocument, Grant, MemoryResolver, VerificationMethod},
+    error::{DidError, TokenError},
+    irc::tests::{MemoryStorage, TestClient, TestServer},
+};
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+"#;
+
+const AUDIENCE: &str = "did:web:irc.test";
+
+const DID: &str = "did:plc:abcdefghijklmnopqrstuvwx";
+
+/// A key the user's PDS could sign tokens with.
+enum Key {
+    K256(k256::ecdsa::SigningKey),
+    P256(p256::ecdsa::SigningKey),
+}
+
+impl Key {
+    fn k256(seed: u8) -> Self {
+        Self::K256(k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    fn p256(seed: u8) -> Self {
+        Self::P256(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap())
+    }
+
+    /// The public key as a `Multikey`.
+    fn multikey(&self) -> String {
+        let bytes = match self {
+            Self::K256(key) => [&[0xe7, 0x01], key.verifying_key().to_encoded_point(true).as_bytes()].concat(),
+            Self::P256(key) => [&[0x80, 0x24], key.verifying_key().to_encoded_point(true).as_bytes()].concat(),
+        };
+        format!("z{}", bs58::encode(bytes).into_string())
+    }
+
+    /// A low-S `r || s` signature over `msg`.
+    fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            Self::K256(key) => {
+                let signature: k256::ecdsa::Signature = key.sign(msg);
+                signature.to_bytes().to_vec()
+            }
+            Self::P256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(msg);
+                signature.normalize_s().unwrap_or(signature).to_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// A resolver knowing only [DID], whose `#atproto` key is `key`.
+fn resolver(key: &Key) -> MemoryResolver {
+    let mut resolver = MemoryResolver::default();
+    resolver.insert(DidDocument {
+        id: DID.to_owned(),
+        also_known_as: vec!["at://alice.test".to_owned()],
+        verification_method: vec![VerificationMethod {
+            id: "#atproto".to_owned(),
+            kind: "Multikey".to_owned(),
+            controller: DID.to_owned(),
+            public_key_multibase: Some(key.multikey()),
+        }],
+    });
+    resolver
+}
+
+/// A token for [DID] signed with `key`, with `claims` replacing the
+/// defaults of a token valid for the next hour.
+fn token(key: &Key, alg: &str, claims: serde_json::Value) -> String {
+    let now = Utc::now().timestamp();
+    let mut all = json!({
+        "iss": DID,
+        "aud": AUDIENCE,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    all.as_object_mut().unwrap().extend(claims.as_object().unwrap().clone());
+
+    let header = URL_SAFE_NO_PAD.encode(json!({ "alg": alg, "typ": "JWT" }).to_string());
+    let claims = URL_SAFE_NO_PAD.encode(all.to_string());
+    let signed = format!("{header}.{claims}");
+    let signature = URL_SAFE_NO_PAD.encode(key.sign(signed.as_bytes()));
+
+    format!("{signed}.{signature}")
+}
+
+/// Register a client that may log in through OAUTHBEARER.
+async fn connect(server: &mut TestServer) -> TestClient {
+    let mut alice = server.register("alice").await;
+    alice.send("CAP REQ :sasl rsr.chat/plc-oauthbearer").await;
+    alice.expect(":irc.test CAP alice ACK :sasl rsr.chat/plc-oauthbearer").await;
+    alice
+}
+
+/// Send `token` in an OAUTHBEARER exchange, split into chunks.
+async fn authenticate(client: &mut TestClient, token: &str) {
+    client.send("AUTHENTICATE OAUTHBEARER").await;
+    client.expect(":irc.test AUTHENTICATE +").await;
+
+    let payload = STANDARD.encode(format!("n,,\x01auth=Bearer {token}\x01\x01"));
+    for chunk in payload.as_bytes().chunks(400) {
+        client.send(&format!("AUTHENTICATE {}", str::from_utf8(chunk).unwrap())).await;
+    }
+    if payload.len() % 400 == 0 {
+        client.send("AUTHENTICATE +").await;
+    }
+}
+
+/// Assert `token` is refused, both by the server and for the reason
+/// `expected` matches.
+async fn assert_refused(key: &Key, token: &str, expected: fn(&TokenError) -> bool) {
+    let error = Grant::verify(token, AUDIENCE, &resolver(key)).await.unwrap_err();
+    assert!(expected(&error), "unexpected error: {error}");
+
+    let mut server = TestServer::with_resolver(CONFIG, MemoryStorage::default(), resolver(key));
+    let mut alice = connect(&mut server).await;
+    authenticate(&mut alice, token).await;
+    alice.expect(":irc.test 904 alice :SASL authentication failed").await;
+}
+
+#[tokio::test]
+async fn oauthbearer_login() {
+    let key = Key::k256(1);
+    let mut server = TestServer::with_resolver(CONFIG, MemoryStorage::default(), resolver(&key));
+    let mut alice = connect(&mut server).await;
+
+    authenticate(&mut alice, &token(&key, "ES256K", json!({}))).await;
+    alice
+        .expect_all(&[
+            &format!(":irc.test 900 alice alice!alice@127.0.0.1 {DID} :You are now logged in as {DID}"),
+            ":irc.test 903 alice :SASL authentication successful",
+        ])
+        .await;
+}
+
+#[tokio::test]
+async fn oauthbearer_p256_login() {
+    let key = Key::p256(1);
+    let mut server = TestServer::with_resolver(CONFIG, MemoryStorage::default(), resolver(&key));
+    let mut alice = connect(&mut server).await;
+
+    authenticate(&mut alice, &token(&key, "ES256", json!({}))).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+}
+
+#[tokio::test]
+async fn expired_token_refused() {
+    let key = Key::k256(1);
+    let exp = (Utc::now() - TimeDelta::minutes(1)).timestamp();
+    let token = token(&key, "ES256K", json!({ "exp": exp }));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::Expired)).await;
+}
+
+#[tokio::test]
+async fn future_token_refused() {
+    let key = Key::k256(1);
+    let nbf = (Utc::now() + TimeDelta::minutes(10)).timestamp();
+    let token = token(&key, "ES256K", json!({ "nbf": nbf }));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::NotYetValid)).await;
+}
+
+#[tokio::test]
+async fn wrong_audience_refused() {
+    let key = Key::k256(1);
+    let token = token(&key, "ES256K", json!({ "aud": "did:web:irc.example" }));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::WrongAudience)).await;
+}
+
+#[tokio::test]
+async fn algorithm_must_match_key() {
+    let key = Key::k256(1);
+    let token = token(&key, "ES256", json!({}));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::UnsupportedAlgorithm(alg) if alg == "ES256")).await;
+}
+
+#[tokio::test]
+async fn bad_signature_refused() {
+    let key = Key::k256(1);
+    let token = token(&Key::k256(2), "ES256K", json!({}));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::BadSignature)).await;
+}
+
+#[tokio::test]
+async fn high_s_signature_refused() {
+    let key = Key::p256(1);
+    let token = token(&key, "ES256", json!({}));
+
+    // Flip the signature to its high-S twin, which is just as valid
+    // to P-256 but not to atproto.
+    let (signed, signature) = token.rsplit_once('.').unwrap();
+    let signature = p256::ecdsa::Signature::from_slice(&URL_SAFE_NO_PAD.decode(signature).unwrap()).unwrap();
+    let high = p256::ecdsa::Signature::from_scalars(signature.r(), -signature.s()).unwrap();
+    let token = format!("{signed}.{}", URL_SAFE_NO_PAD.encode(high.to_bytes()));
+
+    assert_refused(&key, &token, |e| matches!(e, TokenError::BadSignature)).await;
+}
+
+#[tokio::test]
+async fn grant_carries_handle() {
+    let key = Key::k256(1);
+    let grant = Grant::verify(&token(&key, "ES256K", json!({})), AUDIENCE, &resolver(&key)).await.unwrap();
+
+    assert_eq!(grant.did, DID);
+    assert_eq!(grant.handle.as_deref(), Some("alice.test"));
+}
+
+#[tokio::test]
+async fn delegated_key_refused() {
+    let key = Key::k256(1);
+    let mut document = DidDocument {
+        id: DID.to_owned(),
+        also_known_as: Vec::new(),
+        verification_method: vec![VerificationMethod {
+            id: "#atproto".to_owned(),
+            kind: "Multikey".to_owned(),
+            controller: "did:plc:zzzzzzzzzzzzzzzzzzzzzzzz".to_owned(),
+            public_key_multibase: Some(key.multikey()),
+        }],
+    };
+    assert!(matches!(document.signing_key(), Err(DidError::InvalidKey)));
+
+    document.verification_method[0].controller = DID.to_owned();
+    document.verification_method[0].kind = "EcdsaSecp256k1VerificationKey2019".to_owned();
+    assert!(matches!(document.signing_key(), Err(DidError::InvalidKey)));
+
+    document.verification_method[0].kind = "Multikey".to_owned();
+    assert!(document.signing_key().is_ok());
+}
+
+#[tokio::test]
+async fn mechanisms_follow_config() {
+    let mut server = TestServer::with_resolver(CONFIG, MemoryStorage::default(), MemoryResolver::default());
+    let mut alice = connect(&mut server).await;
+    alice.send("AUTHENTICATE SCRAM-SHA-256").await;
+    alice
+        .expect_all(&[
+            ":irc.test 908 alice PLAIN,EXTERNAL,OAUTHBEARER :are available SASL mechanisms",
+            ":irc.test 904 alice :SASL authentication failed",
+        ])
+        .await;
+
+    let config = format!("{CONFIG}\n[caps.values]\nsasl = \"PLAIN\"\n");
+    let mut server = TestServer::with_resolver(&config, MemoryStorage::default(), MemoryResolver::default());
+    let mut alice = connect(&mut server).await;
+    alice.send("AUTHENTICATE OAUTHBEARER").await;
+    alice
+        .expect_all(&[
+            ":irc.test 908 alice PLAIN :are available SASL mechanisms",
+            ":irc.test 904 alice :SASL authentication failed",
+        ])
+        .await;
+}
+
+/// A server whose grants warn a minute before they run out, and whose
+/// clients are never pinged while the clock is advanced.
+fn expiring_server(key: &Key) -> TestServer {
+    let config = format!("{CONFIG}\n[limits]\nidle_timeout = 86400\ngrant_warning = 60\n");
+    TestServer::with_resolver(&config, MemoryStorage::default(), resolver(key))
+}
+
+/// A token for [DID] which runs out in `secs` seconds.
+fn expiring_token(key: &Key, secs: i64) -> String {
+    token(key, "ES256K", json!({ "exp": Utc::now().timestamp() + secs }))
+}
+
+#[tokio::test(start_paused = true)]
+async fn grant_expiry_warns_then_logs_out() {
+    let key = Key::k256(1);
+    let mut server = expiring_server(&key);
+    let mut alice = connect(&mut server).await;
+    alice.send("CAP REQ standard-replies").await;
+    alice.expect(":irc.test CAP alice ACK :standard-replies").await;
+
+    let mut carol = server.register("carol").await;
+    carol.send("CAP REQ account-notify").await;
+    carol.expect(":irc.test CAP carol ACK :account-notify").await;
+    for client in [&mut alice, &mut carol] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    alice.expect(":carol!carol@127.0.0.1 JOIN #x").await;
+
+    authenticate(&mut alice, &expiring_token(&key, 120)).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+    carol.expect(&format!(":alice!alice@127.0.0.1 ACCOUNT {DID}")).await;
+
+    tokio::time::advance(Duration::from_secs(30)).await;
+    alice.expect_silence().await;
+
+    tokio::time::advance(Duration::from_secs(31)).await;
+    let line = alice.recv().await;
+    assert!(line.starts_with(":irc.test NOTE AUTHENTICATE GRANT_EXPIRING :Your PDS grant expires in "), "{line}");
+
+    tokio::time::advance(Duration::from_secs(60)).await;
+    alice
+        .expect_all(&[
+            ":irc.test FAIL AUTHENTICATE GRANT_EXPIRED :Your PDS grant has expired",
+            ":irc.test 901 alice alice!alice@127.0.0.1 :You are now logged out",
+        ])
+        .await;
+    carol.expect(":alice!alice@127.0.0.1 ACCOUNT *").await;
+
+    // Back to merely registered, alice may log in again.
+    authenticate(&mut alice, &expiring_token(&key, 3600)).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn grant_refresh_pushes_expiry_out() {
+    let key = Key::k256(1);
+    let mut server = expiring_server(&key);
+    let mut alice = connect(&mut server).await;
+
+    authenticate(&mut alice, &expiring_token(&key, 120)).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+
+    tokio::time::advance(Duration::from_secs(61)).await;
+    let line = alice.recv().await;
+    assert!(line.starts_with(":irc.test NOTICE alice :Your PDS grant expires in "), "{line}");
+
+    authenticate(&mut alice, &expiring_token(&key, 3600)).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+
+    tokio::time::advance(Duration::from_secs(120)).await;
+    alice.expect_silence().await;
+
+    alice.send("WHOIS alice").await;
+    alice.skip_until(&format!(":irc.test 330 alice alice {DID} ")).await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn grant_lifetime_is_capped() {
+    let key = Key::k256(1);
+    let config = format!("{CONFIG}\n[limits]\nidle_timeout = 86400\ngrant_warning = 60\nmax_grant = 300\n");
+    let mut server = TestServer::with_resolver(&config, MemoryStorage::default(), resolver(&key));
+    let mut alice = connect(&mut server).await;
+
+    authenticate(&mut alice, &expiring_token(&key, 7 * 24 * 3600)).await;
+    alice.expect_numeric("900").await;
+    alice.expect_numeric("903").await;
+
+    tokio::time::advance(Duration::from_secs(241)).await;
+    let line = alice.recv().await;
+    assert!(line.starts_with(":irc.test NOTICE alice :Your PDS grant expires in "), "{line}");
+
+    tokio::time::advance(Duration::from_secs(60)).await;
+    alice
+        .expect_all(&[
+            ":irc.test NOTICE alice :Your PDS grant has expired",
+            ":irc.test 901 alice alice!alice@127.0.0.1 :You are now logged out",
+        ])
+        .await;
+}
@@ -0,0 +1,273 @@
+use std::path::PathBuf;
+
+use crate::irc::tests::{MemoryStorage, TestClient, TestServer};
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[class.admin]
+privileges = ["kill", "wallops", "global_notice", "rehash"]
+
+[class.helper]
+privileges = []
+
+[[oper]]
+name = "admin"
+password = "secret"
+class = "admin"
+
+[[oper]]
+name = "helper"
+password = "secret"
+class = "helper"
+"#;
+
+/// Write `text` to a configuration file only this test uses, along
+/// with the listener every configuration file needs.
+fn config_file(test: &str, text: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rsrserver-{}-{test}.toml", std::process::id()));
+    std::fs::write(&path, format!("[[listen]]\naddr = \"127.0.0.1:6667\"\n{text}")).unwrap();
+    path
+}
+
+async fn oper(server: &mut TestServer, nick: &str, name: &str) -> TestClient {
+    let mut client = server.register(nick).await;
+    client.send(&format!("OPER {name} secret")).await;
+    client.skip_until(&format!(":irc.test 381 {nick}")).await;
+    client
+}
+
+#[tokio::test]
+async fn kill_needs_privilege() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut helper = oper(&mut server, "helper", "helper").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("KILL bob :bye").await;
+    alice.expect(":irc.test 481 alice :Permission Denied- You're not an IRC operator").await;
+
+    helper.send("KILL bob :bye").await;
+    helper.expect(":irc.test 481 helper :Permission Denied- You're not an IRC operator").await;
+
+    bob.expect_silence().await;
+}
+
+#[tokio::test]
+async fn kill_disconnects_target() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    for client in [&mut bob, &mut carol] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    bob.expect(":carol!carol@127.0.0.1 JOIN #x").await;
+
+    admin.send("KILL nobody :bye").await;
+    admin.expect(":irc.test 401 admin nobody :No such nick/channel").await;
+
+    admin.send("KILL bob :spamming").await;
+    bob.expect_all(&[
+        ":admin!admin@127.0.0.1 KILL bob :spamming",
+        ":irc.test ERROR :Closing Link: 127.0.0.1 (Killed (admin!admin@127.0.0.1 (spamming)))",
+    ])
+    .await;
+    bob.expect_closed().await;
+    carol.expect(":bob!bob@127.0.0.1 QUIT :Killed (admin!admin@127.0.0.1 (spamming))").await;
+
+    admin.send("WHOIS bob").await;
+    admin.expect(":irc.test 401 admin bob :No such nick/channel").await;
+}
+
+#[tokio::test]
+async fn wallops_reach_only_wallops_mode() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    bob.send("MODE bob +w").await;
+    bob.expect(":bob!bob@127.0.0.1 MODE bob :+w").await;
+    bob.send("MODE bob").await;
+    bob.expect(":irc.test 221 bob +w").await;
+
+    bob.send("WALLOPS :hello").await;
+    bob.expect(":irc.test 481 bob :Permission Denied- You're not an IRC operator").await;
+
+    admin.send("WALLOPS :server going down").await;
+    bob.expect(":admin!admin@127.0.0.1 WALLOPS :server going down").await;
+    carol.expect_silence().await;
+    admin.expect_silence().await;
+
+    bob.send("MODE bob -w").await;
+    bob.expect(":bob!bob@127.0.0.1 MODE bob :-w").await;
+    admin.send("WALLOPS :again").await;
+    bob.expect_silence().await;
+}
+
+#[tokio::test]
+async fn user_modes_only_for_self() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let _bob = server.register("bob").await;
+
+    alice.send("MODE bob +w").await;
+    alice.expect(":irc.test 502 alice :Cant change mode for other users").await;
+
+    alice.send("MODE nobody +w").await;
+    alice.expect(":irc.test 401 alice nobody :No such nick/channel").await;
+
+    alice.send("MODE alice +wz").await;
+    alice.expect(":irc.test 501 alice :Unknown MODE flag").await;
+    alice.expect(":alice!alice@127.0.0.1 MODE alice :+w").await;
+}
+
+#[tokio::test]
+async fn global_notice() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut bob = server.register("bob").await;
+    let mut unregistered = server.connect();
+
+    bob.send("NOTICE $* :sneaky").await;
+    bob.send("PRIVMSG $* :sneaky").await;
+    bob.expect(":irc.test 401 bob $* :No such nick/channel").await;
+    admin.expect_silence().await;
+
+    admin.send("PRIVMSG $* :wrong command").await;
+    admin.expect(":irc.test 401 admin $* :No such nick/channel").await;
+
+    admin.send("NOTICE $irc.example :wrong server").await;
+    admin.expect_silence().await;
+
+    admin.send("NOTICE $* :maintenance soon").await;
+    bob.expect(":admin!admin@127.0.0.1 NOTICE $* :maintenance soon").await;
+    admin.expect(":admin!admin@127.0.0.1 NOTICE $* :maintenance soon").await;
+    unregistered.expect_silence().await;
+}
+
+#[tokio::test]
+async fn rehash_needs_privilege() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut helper = oper(&mut server, "helper", "helper").await;
+
+    alice.send("REHASH").await;
+    alice.expect(":irc.test 481 alice :Permission Denied- You're not an IRC operator").await;
+
+    helper.send("REHASH").await;
+    helper.expect(":irc.test 481 helper :Permission Denied- You're not an IRC operator").await;
+}
+
+#[tokio::test]
+async fn rehash_applies_new_config() {
+    let path = config_file("rehash_applies", CONFIG);
+    let mut server = TestServer::load(&path, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut helper = oper(&mut server, "helper", "helper").await;
+    let mut bob = server.register("bob").await;
+
+    bob.send("OPER newbie secret").await;
+    bob.expect(":irc.test 464 bob :Password incorrect").await;
+
+    let without_helper = CONFIG.split("[[oper]]\nname = \"helper\"").next().unwrap();
+    let text = format!("{without_helper}[[oper]]\nname = \"newbie\"\npassword = \"secret\"\nclass = \"helper\"\n");
+    config_file("rehash_applies", &text);
+
+    admin.send("REHASH").await;
+    admin
+        .expect_all(&[
+            &format!(":irc.test 382 admin {} :Rehashing", path.display()),
+            ":irc.test NOTICE admin :admin!admin@127.0.0.1 rehashed the server configuration",
+        ])
+        .await;
+    helper.expect(":irc.test NOTICE helper :Your oper block was removed by a rehash").await;
+
+    bob.send("OPER newbie secret").await;
+    bob.skip_until(":irc.test 381 bob ").await;
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn rehash_keeps_config_when_invalid() {
+    let path = config_file("rehash_invalid", CONFIG);
+    let mut server = TestServer::load(&path, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut bob = server.register("bob").await;
+
+    config_file("rehash_invalid", &format!("{CONFIG}\n[limits]\nidle_timeout = 0\nmax_line = 100\n"));
+
+    admin.send("REHASH").await;
+    admin
+        .expect_all(&[
+            &format!(":irc.test 382 admin {} :Rehashing", path.display()),
+            ":irc.test NOTICE admin :Rehash failed: limits.idle_timeout must be greater than 0",
+            ":irc.test NOTICE admin :Rehash failed: limits.max_line must be at least 512",
+        ])
+        .await;
+
+    bob.send("OPER helper secret").await;
+    bob.skip_until(":irc.test 381 bob ").await;
+
+    std::fs::write(&path, "[server\n").unwrap();
+    admin.send("REHASH").await;
+    admin.skip_until(":irc.test 382 admin ").await;
+    let line = admin.recv().await;
+    assert!(line.starts_with(":irc.test NOTICE admin :Rehash failed: "), "{line}");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn rehash_announces_capability_changes() {
+    let path = config_file("rehash_caps", CONFIG);
+    let mut server = TestServer::load(&path, MemoryStorage::default());
+    let mut admin = oper(&mut server, "admin", "admin").await;
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("CAP REQ :cap-notify echo-message").await;
+    alice.expect(":irc.test CAP alice ACK :cap-notify echo-message").await;
+
+    config_file("rehash_caps", &format!("{CONFIG}\n[caps]\ndisabled = [\"echo-message\"]\n"));
+    admin.send("REHASH").await;
+    admin.skip_until(":irc.test NOTICE admin :admin!admin@127.0.0.1 rehashed").await;
+
+    // Only cap-notify clients are told, but everyone loses the capability.
+    alice.expect(":irc.test CAP alice DEL :echo-message").await;
+    alice.send("CAP LIST").await;
+    alice.expect(":irc.test CAP alice LIST :cap-notify").await;
+    bob.expect_silence().await;
+
+    config_file("rehash_caps", CONFIG);
+    admin.send("REHASH").await;
+    admin.skip_until(":irc.test NOTICE admin :admin!admin@127.0.0.1 rehashed").await;
+    alice.expect(":irc.test CAP alice NEW :echo-message").await;
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn server_link_commands_are_oper_only() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut helper = oper(&mut server, "helper", "helper").await;
+
+    for command in ["CONNECT hub.test", "SQUIT hub.test :bye", "RESTART"] {
+        alice.send(command).await;
+        alice.expect(":irc.test 481 alice :Permission Denied- You're not an IRC operator").await;
+    }
+
+    helper.send("CONNECT hub.test 6667").await;
+    helper.expect(":irc.test 402 helper hub.test :No such server").await;
+    helper.send("SQUIT hub.test :bye").await;
+    helper.expect(":irc.test 402 helper hub.test :No such server").await;
+    helper.send("RESTART").await;
+    helper.expect(":irc.test NOTICE helper :This server can not be restarted over IRC").await;
+}
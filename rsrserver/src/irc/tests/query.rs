@@ -0,0 +1,145 @@
+use crate::irc::tests::{MemoryStorage, TestServer};
+
+#[tokio::test]
+async fn version_repeats_isupport() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("VERSION").await;
+    alice.expect(":irc.test 351 alice rsr-0.1.0 irc.test :rsr.chat").await;
+    let line = alice.expect_numeric("005").await;
+    assert!(line.contains(" NETWORK=TestNet "), "{line}");
+    alice.expect_silence().await;
+}
+
+#[tokio::test]
+async fn userhost_lists_known_nicks() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    bob.send("AWAY :lunch").await;
+    bob.expect_numeric("306").await;
+
+    alice.send("USERHOST alice nobody bob").await;
+    alice.expect(":irc.test 302 alice :alice=+alice@127.0.0.1 bob=-bob@127.0.0.1").await;
+
+    alice.send("USERHOST").await;
+    alice.expect(":irc.test 461 alice USERHOST :Not enough parameters").await;
+}
+
+#[tokio::test]
+async fn who_lists_channel_members_and_nick_masks() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let _carol = server.register("carol").await;
+
+    alice.send("JOIN #x").await;
+    alice.skip_until(":irc.test 366 ").await;
+    bob.send("JOIN #x").await;
+    bob.skip_until(":irc.test 366 ").await;
+    bob.send("AWAY :lunch").await;
+    bob.expect_numeric("306").await;
+    alice.expect(":bob!bob@127.0.0.1 JOIN #x").await;
+
+    alice.send("WHO #x").await;
+    let mut entries = vec![alice.recv().await, alice.recv().await];
+    entries.sort();
+    assert_eq!(entries, [
+        ":irc.test 352 alice #x alice 127.0.0.1 irc.test alice H@ :0 alice",
+        ":irc.test 352 alice #x bob 127.0.0.1 irc.test bob G :0 bob",
+    ]);
+    alice.expect(":irc.test 315 alice #x :End of WHO list").await;
+
+    alice.send("WHO c*").await;
+    alice
+        .expect_all(&[
+            ":irc.test 352 alice * carol 127.0.0.1 irc.test carol H :0 carol",
+            ":irc.test 315 alice c* :End of WHO list",
+        ])
+        .await;
+
+    alice.send("WHO #nowhere").await;
+    alice.expect(":irc.test 315 alice #nowhere :End of WHO list").await;
+}
+
+#[tokio::test]
+async fn invisible_users_are_hidden_and_counted() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+    let mut carol = server.register("carol").await;
+
+    carol.send("MODE carol +iw").await;
+    carol.expect(":carol!carol@127.0.0.1 MODE carol :+i+w").await;
+    carol.send("MODE carol").await;
+    carol.expect(":irc.test 221 carol +iw").await;
+
+    alice.send("LUSERS").await;
+    alice.expect(":irc.test 251 alice :There are 2 users and 1 invisible on 1 servers").await;
+    alice.skip_until(":irc.test 266 ").await;
+
+    alice.send("WHO *").await;
+    alice
+        .expect_all(&[
+            ":irc.test 352 alice * alice 127.0.0.1 irc.test alice H :0 alice",
+            ":irc.test 352 alice * bob 127.0.0.1 irc.test bob H :0 bob",
+            ":irc.test 315 alice * :End of WHO list",
+        ])
+        .await;
+
+    // Sharing a channel makes carol visible to bob.
+    for client in [&mut bob, &mut carol] {
+        client.send("JOIN #x").await;
+        client.skip_until(":irc.test 366 ").await;
+    }
+    bob.expect(":carol!carol@127.0.0.1 JOIN #x").await;
+
+    bob.send("WHO c*").await;
+    bob.expect(":irc.test 352 bob * carol 127.0.0.1 irc.test carol H :0 carol").await;
+    bob.expect(":irc.test 315 bob c* :End of WHO list").await;
+
+    alice.send("WHO #x").await;
+    alice.expect(":irc.test 352 alice #x bob 127.0.0.1 irc.test bob H@ :0 bob").await;
+    alice.expect(":irc.test 315 alice #x :End of WHO list").await;
+}
+
+#[tokio::test]
+async fn server_queries_are_answered() {
+    let config = r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [admin]
+        location = "Somewhere"
+        institution = "Test Institute"
+        email = "admin@irc.test"
+    "#;
+    let mut server = TestServer::with(config, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+
+    alice.send("ADMIN").await;
+    alice
+        .expect_all(&[
+            ":irc.test 256 alice irc.test :Administrative info",
+            ":irc.test 257 alice :Somewhere",
+            ":irc.test 258 alice :Test Institute",
+            ":irc.test 259 alice :admin@irc.test",
+        ])
+        .await;
+
+    alice.send("TIME").await;
+    let time = alice.expect_numeric("391").await;
+    assert!(time.starts_with(":irc.test 391 alice irc.test "), "{time}");
+
+    alice.send("INFO").await;
+    alice.skip_until(":irc.test 374 alice :End of INFO list").await;
+
+    alice.send("LINKS").await;
+    alice.expect(":irc.test 365 alice * :End of /LINKS list").await;
+
+    alice.send("MOTD").await;
+    alice.expect(":irc.test 422 alice :MOTD File is missing").await;
+}
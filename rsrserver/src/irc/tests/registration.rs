@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use crate::irc::tests::{MemoryStorage, TestServer};
+
+#[tokio::test]
+async fn welcome_burst() {
+    let mut server = TestServer::new();
+    let mut alice = server.connect();
+
+    alice.send("NICK alice").await;
+    alice.send("USER alice 0 * :Alice").await;
+
+    alice.expect(":irc.test 001 alice :Welcome to the TestNet Network, alice!alice@127.0.0.1").await;
+    alice.expect(":irc.test 002 alice :Your host is irc.test, running version rsr-0.1.0").await;
+    alice.expect_numeric("003").await;
+    alice.expect(":irc.test 004 alice irc.test rsr-0.1.0 iw iklo klo").await;
+    alice.expect_numeric("005").await;
+    alice.expect_all(&[
+        ":irc.test 251 alice :There are 1 users and 0 invisible on 1 servers",
+        ":irc.test 255 alice :I have 1 clients and 0 servers",
+        ":irc.test 265 alice 1 1 :Current local users 1, max 1",
+        ":irc.test 266 alice 1 1 :Current global users 1, max 1",
+        ":irc.test 422 alice :MOTD File is missing",
+    ])
+    .await;
+    alice.expect_silence().await;
+}
+
+#[tokio::test]
+async fn commands_before_registration() {
+    let mut server = TestServer::new();
+    let mut client = server.connect();
+
+    client.send("JOIN #x").await;
+    client.expect(":irc.test 451 * :You have not registered").await;
+}
+
+#[tokio::test]
+async fn nick_in_use() {
+    let mut server = TestServer::new();
+    let _alice = server.register("alice").await;
+    let mut client = server.connect();
+
+    client.send("NICK alice").await;
+    client.expect(":irc.test 433 * alice :Nickname is already in use").await;
+
+    client.register("bob").await;
+}
+
+#[tokio::test]
+async fn nick_and_user_are_checked() {
+    let mut server = TestServer::new();
+    let mut client = server.connect();
+
+    client.send("NICK").await;
+    client.expect(":irc.test 431 * :No nickname given").await;
+    client.send("NICK 1alice").await;
+    client.expect(":irc.test 432 * 1alice :Erroneous nickname").await;
+
+    client.register("alice").await;
+    client.send("USER alice 0 * :Alice").await;
+    client.expect(":irc.test 462 alice :You may not reregister").await;
+
+    client.send("NICK Alice2").await;
+    client.expect(":alice!alice@127.0.0.1 NICK :Alice2").await;
+}
+
+#[tokio::test]
+async fn unknown_command() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("FOO bar").await;
+    alice.expect(":irc.test 421 alice FOO :Unknown command").await;
+}
+
+#[tokio::test]
+async fn need_more_params() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("JOIN").await;
+    alice.expect(":irc.test 461 alice JOIN :Not enough parameters").await;
+}
+
+#[tokio::test]
+async fn ping() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("PING :token").await;
+    alice.expect(":irc.test PONG irc.test :token").await;
+}
+
+#[tokio::test]
+async fn quit() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("QUIT :bye").await;
+    alice.expect(":irc.test ERROR :Closing Link: 127.0.0.1 (Quit: bye)").await;
+    alice.expect_closed().await;
+}
+
+#[tokio::test]
+async fn whowas_after_quit() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("QUIT").await;
+    alice.expect(":irc.test ERROR :Closing Link: 127.0.0.1 (Client Quit)").await;
+    alice.expect_closed().await;
+
+    bob.send("WHOWAS alice").await;
+    bob.expect(":irc.test 314 bob alice alice 127.0.0.1 * :alice").await;
+    bob.expect_numeric("312").await;
+    bob.expect(":irc.test 369 bob alice :End of WHOWAS").await;
+}
+
+const WEBIRC_CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[[webirc]]
+name = "gateway"
+password = "secret"
+hosts = ["127.0.0.*"]
+"#;
+
+#[tokio::test]
+async fn webirc_replaces_the_address() {
+    let mut server = TestServer::with(WEBIRC_CONFIG, MemoryStorage::default());
+    let mut client = server.connect();
+
+    client.send("WEBIRC secret gateway user.example.org 192.0.2.7").await;
+    client.send("NICK alice").await;
+    client.send("USER alice 0 * :Alice").await;
+    client.expect(":irc.test 001 alice :Welcome to the TestNet Network, alice!alice@user.example.org").await;
+}
+
+#[tokio::test]
+async fn webirc_without_hostname() {
+    let mut server = TestServer::with(WEBIRC_CONFIG, MemoryStorage::default());
+    let mut client = server.connect();
+
+    client.send("WEBIRC secret gateway 2001:db8::7 2001:db8::7").await;
+    client.send("NICK alice").await;
+    client.send("USER alice 0 * :Alice").await;
+    client.expect(":irc.test 001 alice :Welcome to the TestNet Network, alice!alice@2001:db8::7").await;
+}
+
+#[tokio::test]
+async fn webirc_wrong_password() {
+    let mut server = TestServer::with(WEBIRC_CONFIG, MemoryStorage::default());
+    let mut client = server.connect();
+
+    client.send("WEBIRC guess gateway user.example.org 192.0.2.7").await;
+    client.expect(":irc.test NOTICE * :WEBIRC is not allowed from this host").await;
+    client.send("NICK alice").await;
+    client.send("USER alice 0 * :Alice").await;
+    client.expect(":irc.test 001 alice :Welcome to the TestNet Network, alice!alice@127.0.0.1").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_clients_are_pinged() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    tokio::time::advance(Duration::from_secs(31)).await;
+    let ping = alice.recv().await;
+    let token = ping.strip_prefix(":irc.test PING ").expect("the server sends the PING");
+    alice.send(&format!("PONG :{token}")).await;
+    alice.send("PING :sync").await;
+    alice.expect(":irc.test PONG irc.test :sync").await;
+
+    // Answering in time keeps the client connected, so the next idle
+    // period brings another PING rather than a ping timeout.
+    tokio::time::advance(Duration::from_secs(31)).await;
+    alice.expect_numeric("PING").await;
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use crate::irc::{
+    Control,
+    tests::{MemoryStorage, TestServer, network},
+};
+
+#[tokio::test]
+async fn sendq_exceeded() {
+    let config = r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [limits]
+        sendq = 10240
+
+        [flood]
+        burst = 1000
+    "#;
+    let mut server = TestServer::with(config, MemoryStorage::default());
+    let mut alice = server.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    // Far more than the pipe to bob holds, while bob is not reading.
+    let text = "x".repeat(400);
+    for _ in 0..300 {
+        alice.send(&format!("PRIVMSG bob :{text}")).await;
+    }
+
+    bob.skip_until(":irc.test ERROR :Closing Link: 127.0.0.1 (SendQ exceeded)").await;
+    bob.expect_closed().await;
+
+    alice.send("WHOIS bob").await;
+    alice.expect(":irc.test 401 alice bob :No such nick/channel").await;
+}
+
+
+#[tokio::test]
+async fn busy_senders_do_not_disconnect_a_reader() {
+    let config = r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [flood]
+        burst = 1000
+    "#;
+    let mut server = TestServer::with(config, MemoryStorage::default());
+    let mut bob = server.register("bob").await;
+    let mut senders = Vec::new();
+    for nick in ["alice", "carol", "dave", "erin"] {
+        senders.push(server.register(nick).await);
+    }
+
+    // Together the senders queue more lines for bob than his direct
+    // queue holds at once, while bob keeps reading.
+    let send = async {
+        for i in 0..100 {
+            for sender in &mut senders {
+                sender.send(&format!("PRIVMSG bob :{i}")).await;
+            }
+        }
+    };
+    let read = async {
+        for _ in 0..400 {
+            let line = bob.recv().await;
+            assert!(line.contains(" PRIVMSG bob :"), "bob received {line:?}");
+        }
+    };
+    tokio::join!(send, read);
+
+    bob.send("PING :still here").await;
+    bob.expect(":irc.test PONG irc.test :still here").await;
+}
+
+#[test]
+fn full_direct_queue_disconnects() {
+    let network = network("[server]\nname = \"irc.test\"\nnetwork = \"TestNet\"\n");
+    let (tx, _rx) = mpsc::channel(1);
+    let (ctl, mut orders) = mpsc::unbounded_channel();
+    let id = network.connect(tx, ctl);
+
+    let line = Arc::new(Bytes::from_static(b":alice!alice@127.0.0.1 PRIVMSG bob :hi\r\n"));
+    assert!(network.send_direct(id, Arc::clone(&line)));
+    assert!(orders.try_recv().is_err());
+
+    assert!(network.send_direct(id, line));
+    assert!(matches!(orders.try_recv(), Ok(Control::SendQExceeded)));
+}
@@ -0,0 +1,18 @@
+use crate::irc::tests::TestServer;
+
+#[tokio::test]
+async fn stats_counts_channel_lag() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("STATS z").await;
+    alice
+        .expect_all(&[
+            ":irc.test 249 alice z :Channel lag: 0 times, 0 messages lost",
+            ":irc.test 219 alice z :End of /STATS report",
+        ])
+        .await;
+
+    alice.send("STATS").await;
+    alice.expect(":irc.test 461 alice STATS :Not enough parameters").await;
+}
@@ -0,0 +1,78 @@
+use crate::irc::tests::{MemoryStorage, TestServer};
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+network = "TestNet"
+
+[[webirc]]
+name = "webchat"
+password = "secret"
+hosts = ["127.0.0.*"]
+
+[[webirc]]
+name = "bouncer"
+password = "other"
+hosts = ["10.0.0.5"]
+"#;
+
+#[tokio::test]
+async fn webirc_sets_real_host() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut bob = server.register("bob").await;
+
+    let mut alice = server.connect();
+    alice.send("WEBIRC secret webchat user.example.com 203.0.113.7").await;
+    alice.register("alice").await;
+
+    let mut carol = server.connect();
+    carol.send("WEBIRC secret webchat 203.0.113.8 203.0.113.8 :secure").await;
+    carol.register("carol").await;
+
+    bob.send("WHOIS alice").await;
+    bob.expect_all(&[
+        ":irc.test 311 bob alice alice user.example.com * :alice",
+        ":irc.test 312 bob alice irc.test :TestNet",
+        ":irc.test 318 bob alice :End of /WHOIS list",
+    ])
+    .await;
+
+    // The gateway found no hostname, and its own plaintext connection
+    // is not made secure by the option.
+    bob.send("WHOIS carol").await;
+    bob.expect_all(&[
+        ":irc.test 311 bob carol carol 203.0.113.8 * :carol",
+        ":irc.test 312 bob carol irc.test :TestNet",
+        ":irc.test 318 bob carol :End of /WHOIS list",
+    ])
+    .await;
+
+    alice.send("WEBIRC secret webchat user.example.com 203.0.113.7").await;
+    alice.expect(":irc.test 462 alice :You may not reregister").await;
+}
+
+#[tokio::test]
+async fn webirc_refused() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default());
+    let mut client = server.connect();
+    client.send("CAP REQ standard-replies").await;
+    client.expect(":irc.test CAP * ACK :standard-replies").await;
+
+    client.send("WEBIRC wrong webchat user.example.com 203.0.113.7").await;
+    client.expect(":irc.test FAIL WEBIRC INVALID_CREDENTIALS :WEBIRC is not allowed from this host").await;
+
+    // The right password, but for a gateway on another host.
+    client.send("WEBIRC other bouncer user.example.com 203.0.113.7").await;
+    client.expect(":irc.test FAIL WEBIRC INVALID_CREDENTIALS :WEBIRC is not allowed from this host").await;
+
+    client.send("WEBIRC secret webchat user.example.com not-an-ip").await;
+    client.expect(":irc.test FAIL WEBIRC INVALID_PARAMS :Invalid IP address").await;
+
+    client.send("WEBIRC secret webchat").await;
+    client.expect(":irc.test 461 * WEBIRC :Not enough parameters").await;
+
+    client.send("WEBIRC secret webchat user.example.com 203.0.113.7").await;
+    client.expect_silence().await;
+    client.send("WEBIRC secret webchat user.example.com 203.0.113.7").await;
+    client.expect(":irc.test FAIL WEBIRC INVALID_REQUEST :WEBIRC may only be sent once").await;
+}
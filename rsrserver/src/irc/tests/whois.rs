@@ -0,0 +1,104 @@
+use crate::irc::{
+    Transport,
+    tests::{CONFIG, MemoryStorage, TestServer},
+};
+
+const FINGERPRINT: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+#[tokio::test]
+async fn whois_shows_certfp_to_self_only() {
+    let mut server = TestServer::new();
+    let mut alice = server.connect_with(Transport::secure(Some(FINGERPRINT.to_owned())));
+    alice.register("alice").await;
+    let mut bob = server.register("bob").await;
+
+    alice.send("WHOIS alice").await;
+    alice
+        .expect_all(&[
+            ":irc.test 311 alice alice alice 127.0.0.1 * :alice",
+            ":irc.test 312 alice alice irc.test :TestNet",
+            ":irc.test 671 alice alice :is using a secure connection",
+            &format!(":irc.test 276 alice alice :has client certificate fingerprint {FINGERPRINT}"),
+            ":irc.test 318 alice alice :End of /WHOIS list",
+        ])
+        .await;
+
+    bob.send("WHOIS alice").await;
+    bob.expect_all(&[
+        ":irc.test 311 bob alice alice 127.0.0.1 * :alice",
+        ":irc.test 312 bob alice irc.test :TestNet",
+        ":irc.test 671 bob alice :is using a secure connection",
+        ":irc.test 318 bob alice :End of /WHOIS list",
+    ])
+    .await;
+}
+
+#[tokio::test]
+async fn whois_shows_registered_nicks() {
+    let mut server = TestServer::with(CONFIG, MemoryStorage::default().with_password("alice", "secret"));
+    let mut alice = server.register("alice").await;
+    let _bob = server.register("bob").await;
+
+    alice.send("WHOIS alice").await;
+    alice
+        .expect_all(&[
+            ":irc.test 311 alice alice alice 127.0.0.1 * :alice",
+            ":irc.test 312 alice alice irc.test :TestNet",
+            ":irc.test 307 alice alice :is a registered nick",
+            ":irc.test 318 alice alice :End of /WHOIS list",
+        ])
+        .await;
+
+    alice.send("WHOIS bob").await;
+    alice
+        .expect_all(&[
+            ":irc.test 311 alice bob bob 127.0.0.1 * :bob",
+            ":irc.test 312 alice bob irc.test :TestNet",
+            ":irc.test 318 alice bob :End of /WHOIS list",
+        ])
+        .await;
+}
+
+#[tokio::test]
+async fn whois_unknown_nick() {
+    let mut server = TestServer::new();
+    let mut alice = server.register("alice").await;
+
+    alice.send("WHOIS nobody").await;
+    alice
+        .expect_all(&[
+            ":irc.test 401 alice nobody :No such nick/channel",
+            ":irc.test 318 alice nobody :End of /WHOIS list",
+        ])
+        .await;
+}
+
+#[tokio::test]
+async fn oper_requires_certfp() {
+    let config = format!(
+        r#"
+        [server]
+        name = "irc.test"
+        network = "TestNet"
+
+        [class.admin]
+        privileges = ["rehash"]
+
+        [[oper]]
+        name = "admin"
+        password = "secret"
+        class = "admin"
+        certfp = "{FINGERPRINT}"
+        "#
+    );
+    let mut server = TestServer::with(&config, MemoryStorage::default());
+
+    let mut alice = server.register("alice").await;
+    alice.send("OPER admin secret").await;
+    alice.expect(":irc.test 464 alice :Password incorrect").await;
+
+    let mut bob = server.connect_with(Transport::secure(Some(FINGERPRINT.to_uppercase())));
+    bob.register("bob").await;
+    bob.send("OPER admin secret").await;
+    bob.expect(":irc.test 381 bob :You are now an IRC operator").await;
+}
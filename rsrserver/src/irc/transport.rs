@@ -0,0 +1,25 @@
+/// What the listener knows about the byte stream a connection runs
+/// over. The IRC engine itself never looks below [tokio::io::AsyncRead]
+/// and [tokio::io::AsyncWrite], so anything it needs to know about the
+/// transport is handed over here.
+#[derive(Debug, Clone, Default)]
+pub struct Transport {
+    /// Whether the stream is encrypted, e.g. TLS.
+    pub secure: bool,
+    /// Lowercase hex SHA-256 fingerprint of the client certificate,
+    /// if the client presented one.
+    pub certfp: Option<String>,
+}
+
+impl Transport {
+    /// A plaintext stream.
+    pub fn plaintext() -> Self {
+        Self::default()
+    }
+
+    /// An encrypted stream, with the fingerprint of the client
+    /// certificate if there was one.
+    pub fn secure(certfp: Option<String>) -> Self {
+        Self { secure: true, certfp }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use color_eyre::eyre::{Report, Result, WrapErr};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    net::{TcpListener, TcpStream, lookup_host},
+    sync::broadcast::error::RecvError,
+    task::JoinHandle,
+    time::{interval, timeout},
+};
+
+use crate::{
+    config::{Config, ListenConfig},
+    irc::{IrcServer, ServerMessage, Transport},
+    proxy::ProxyProtocol,
+    storage::Storage,
+    throttle::ConnectionThrottle,
+    tls::{CertResolver, TlsServer, TlsServerConfig},
+    websocket::{WebSocketHandler, WebSocketSettings},
+};
+
+/// Connections waiting to be accepted on a single listener.
+const BACKLOG: i32 = 1024;
+
+/// How often certificate and key files are checked for changes.
+const CERT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bind a TCP listener. IPv6 listeners only accept IPv6, so that the
+/// same port can be bound separately on `0.0.0.0` and `[::]`.
+pub fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// The listeners of a server, kept in line with `[[listen]]`.
+pub struct Listeners<S> {
+    server: IrcServer<S>,
+    bound: HashMap<ListenConfig, Bound>,
+    throttle: Arc<ConnectionThrottle>,
+}
+
+/// The tasks accepting on every address of a listener, and the
+/// certificates they present.
+struct Bound {
+    tasks: Vec<JoinHandle<()>>,
+    certs: Option<Arc<CertResolver>>,
+}
+
+impl<S> Listeners<S>
+where
+    S: Storage + 'static,
+{
+    pub fn new(server: IrcServer<S>) -> Self {
+        let network = server.network();
+        let throttle = ConnectionThrottle::new(&network.config().connections, Arc::clone(network.bans()));
+        Self {
+            server,
+            bound: HashMap::new(),
+            throttle: Arc::new(throttle),
+        }
+    }
+
+    /// Close the listeners that are no longer configured, reload the
+    /// certificates of the ones that stay and bind the ones that are
+    /// new. Connections accepted by a closed listener stay open.
+    /// Listeners that fail to bind are returned, and are tried again on
+    /// the next call, as are certificates that fail to load. Connection
+    /// limits apply to every listener at once.
+    pub async fn apply(&mut self, config: &Config) -> Vec<Report> {
+        self.throttle.configure(&config.connections);
+
+        let removed: Vec<_> = self.bound.keys().filter(|l| !config.listen.contains(l)).cloned().collect();
+        for listener in removed {
+            for task in self.bound.remove(&listener).into_iter().flat_map(|bound| bound.tasks) {
+                task.abort();
+                // The socket is only closed once the task is gone.
+                let _ = task.await;
+            }
+            tracing::info!(addr = listener.addr, "closed listener");
+        }
+
+        let mut errors = Vec::new();
+        for listener in &config.listen {
+            if let Some(bound) = self.bound.get(listener) {
+                if let Some(certs) = &bound.certs
+                    && let Err(e) = certs.reload(&config.certificates)
+                {
+                    errors.push(e.wrap_err(format!("cannot reload certificates of {}", listener.addr)));
+                }
+                continue;
+            }
+
+            match self.bind(listener, config).await {
+                Ok(bound) => {
+                    tracing::info!(
+                        addr = listener.addr,
+                        tls = listener.is_tls(),
+                        websocket = listener.websocket,
+                        "listening"
+                    );
+                    self.bound.insert(listener.clone(), bound);
+                }
+                Err(e) => errors.push(e.wrap_err(format!("cannot listen on {}", listener.addr))),
+            }
+        }
+
+        errors
+    }
+
+    /// Reload the certificates of the listeners whose certificate or
+    /// key files were modified since they were last read.
+    pub fn reload_changed_certs(&self, config: &Config) -> Vec<Report> {
+        let mut errors = Vec::new();
+        for (listener, bound) in &self.bound {
+            let Some(certs) = bound.certs.as_ref().filter(|certs| certs.changed()) else {
+                continue;
+            };
+
+            match certs.reload(&config.certificates) {
+                Ok(()) => tracing::info!(addr = listener.addr, "reloaded certificates"),
+                Err(e) => errors.push(e.wrap_err(format!("cannot reload certificates of {}", listener.addr))),
+            }
+        }
+
+        errors
+    }
+
+    /// Apply `[[listen]]` again after every rehash, and reload
+    /// certificates as their files change, for as long as the server
+    /// runs.
+    pub async fn follow_rehashes(mut self) {
+        let mut bus = self.server.subscribe();
+        let mut poll = interval(CERT_POLL_INTERVAL);
+
+        loop {
+            let errors = tokio::select! {
+                msg = bus.recv() => match msg {
+                    // A missed message may have been a rehash.
+                    Ok(ServerMessage::Rehashed { .. }) | Err(RecvError::Lagged(_)) => {
+                        let config = self.server.network().config();
+                        self.apply(&config).await
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Closed) => return,
+                },
+                _ = poll.tick() => self.reload_changed_certs(&self.server.network().config()),
+            };
+
+            for e in errors {
+                tracing::error!("{e:#}");
+            }
+        }
+    }
+
+    /// Bind every address of a listener, returning the tasks accepting
+    /// on them. Either all of them are bound or none are.
+    async fn bind(&self, listener: &ListenConfig, config: &Config) -> Result<Bound> {
+        let addrs: Vec<_> = lookup_host(&listener.addr).await.wrap_err("cannot resolve address")?.collect();
+        let certs = match (&listener.cert, &listener.key) {
+            (Some(cert), Some(key)) => Some(Arc::new(CertResolver::new(cert.clone(), key.clone(), &config.certificates)?)),
+            _ => None,
+        };
+
+        let mut tasks = Vec::new();
+        for addr in addrs {
+            match self.bind_one(listener, addr, config, certs.clone()).await {
+                Ok(task) => tasks.push(task),
+                Err(e) => {
+                    tasks.iter().for_each(JoinHandle::abort);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(Bound { tasks, certs })
+    }
+
+    async fn bind_one(
+        &self,
+        listener: &ListenConfig,
+        addr: SocketAddr,
+        config: &Config,
+        certs: Option<Arc<CertResolver>>,
+    ) -> Result<JoinHandle<()>> {
+        let server = self.server.clone();
+        let throttle = Arc::clone(&self.throttle);
+        let handshake_timeout = config.limits.handshake_timeout();
+        let proxy = listener.proxy_protocol.then(|| ProxyProtocol::new(&listener.proxies));
+        let websocket = listener.websocket.then(|| {
+            WebSocketHandler::new(server.clone(), WebSocketSettings {
+                origins: listener.origins.clone(),
+                proxies: listener.proxies.clone(),
+                handshake_timeout,
+                max_line: config.limits.max_line,
+            })
+        });
+
+        let Some(certs) = certs else {
+            let listener = bind(addr).wrap_err_with(|| format!("cannot bind {addr}"))?;
+            let accept = Accept {
+                proxy,
+                throttle,
+                handshake_timeout,
+                explain: websocket.is_none(),
+            };
+            let task = match websocket {
+                Some(handler) => tokio::spawn(serve_plaintext(listener, accept, move |stream, addr| {
+                    handler.connect(stream, addr, Transport::plaintext())
+                })),
+                None => tokio::spawn(serve_plaintext(listener, accept, move |stream, addr| {
+                    server.connect(stream, addr, Transport::plaintext())
+                })),
+            };
+            return Ok(task);
+        };
+
+        let mut tls = TlsServer::create(TlsServerConfig {
+            addr,
+            certs,
+            handshake_timeout,
+            max_handshakes: config.limits.max_handshakes,
+            websocket: websocket.is_some(),
+            proxy,
+            throttle,
+        })
+        .await?;
+
+        Ok(match websocket {
+            Some(handler) => tokio::spawn(async move { tls.serve(handler).await }),
+            None => tokio::spawn(async move { tls.serve(server).await }),
+        })
+    }
+}
+
+/// What happens to a plaintext connection before it is handed on.
+struct Accept {
+    proxy: Option<ProxyProtocol>,
+    throttle: Arc<ConnectionThrottle>,
+    handshake_timeout: Duration,
+    /// Tell clients turned away by `throttle` why.
+    explain: bool,
+}
+
+/// Accept plaintext connections until the listener is closed, running
+/// each through `connect` once its PROXY protocol header, if any, has
+/// been read and it is within its connection limits.
+async fn serve_plaintext<F, Fut>(listener: TcpListener, accept: Accept, connect: F)
+where
+    F: Fn(TcpStream, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("failed to accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let proxy = accept.proxy.clone();
+        let throttle = Arc::clone(&accept.throttle);
+        let (handshake_timeout, explain) = (accept.handshake_timeout, accept.explain);
+        let connect = connect.clone();
+        tokio::spawn(async move {
+            let client_addr = match proxy {
+                Some(proxy) => match timeout(handshake_timeout, proxy.accept(&mut stream, peer)).await {
+                    Ok(Ok(client_addr)) => client_addr,
+                    Ok(Err(e)) => return tracing::debug!(%peer, "PROXY protocol header rejected: {e}"),
+                    Err(_) => return tracing::debug!(%peer, "PROXY protocol header timed out"),
+                },
+                None => peer,
+            };
+
+            if let Some(_admitted) = throttle.admit_stream(&mut stream, client_addr, explain).await {
+                connect(stream, client_addr).await;
+            }
+        });
+    }
+}
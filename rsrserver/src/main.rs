@@ -1,56 +1,81 @@
-use std::io;
-use std::net::ToSocketAddrs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use std::error::Error as StdError;
 
 use argh::FromArgs;
 use color_eyre::eyre::Result;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing_subscriber::filter::LevelFilter;
 
-use crate::{irc::IrcServer, tls::{TlsServer, TlsServerConfig}};
+use crate::{config::Config, did::FileResolver, irc::{IrcServer, Network}, listen::Listeners};
 
+mod cidr;
+mod config;
+mod did;
 mod ext;
 mod error;
+mod listen;
+mod proxy;
 mod storage;
+mod throttle;
 mod tls;
+mod websocket;
 mod irc;
 
-/// Tokio Rustls server example
+/// rsr.chat IRC server
 #[derive(FromArgs)]
 struct Options {
-    /// bind addr
+    /// configuration file
     #[argh(positional)]
-    addr: String,
-
-    /// cert file
-    #[argh(option, short = 'c')]
-    cert: PathBuf,
-
-    /// key file
-    #[argh(option, short = 'k')]
-    key: PathBuf,
+    config: PathBuf,
 }
 
 lazy_static::lazy_static! {
     static ref OPTIONS: Options = argh::from_env();
 }
 
+/// Log to stderr at the level named by `RUST_LOG`, `info` if unset.
+fn init_tracing() {
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::INFO);
+
+    tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).init();
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn StdError + Send + Sync + 'static>> {
-    let addr = OPTIONS
-        .addr
-        .to_socket_addrs()?
-        .next()
-        .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
-
-    TlsServer::create(TlsServerConfig {
-        addr,
-        cert: OPTIONS.cert.clone(),
-        key: OPTIONS.key.clone(),
-    })
-    .await?
-    .serve(IrcServer::new(()))
-    .await;
+    init_tracing();
+
+    let config = Config::load(&OPTIONS.config)?;
+    let documents = config.auth.documents.as_ref().map(|dir| config.relative(dir));
+
+    let mut network = Network::new(config)?;
+    if let Some(documents) = documents {
+        network = network.with_resolver(FileResolver::new(documents));
+    }
+
+    let server = IrcServer::new((), network);
+    let bans = server.load_bans().await.map_err(|_| "cannot load bans from storage")?;
+    tracing::info!(bans, "loaded bans");
+    server.rehash_on_sighup()?;
+
+    let mut listeners = Listeners::new(server.clone());
+    if let Some(e) = listeners.apply(&server.network().config()).await.into_iter().next() {
+        return Err(e.into());
+    }
+    tokio::spawn(listeners.follow_rehashes());
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+
+    tracing::info!("shutting down");
+    server.shutdown("Server shutting down", Duration::from_secs(5)).await;
 
     Ok(())
 }
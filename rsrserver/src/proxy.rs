@@ -0,0 +1,221 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature every PROXY protocol v2 header starts with.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest possible PROXY protocol v1 header, including CRLF.
+const V1_MAX: usize = 107;
+
+/// Reads the PROXY protocol header (v1 or v2) that a load balancer
+/// sends ahead of everything else, carrying the address of the client
+/// it accepted the connection from.
+#[derive(Clone)]
+pub struct ProxyProtocol {
+    proxies: Arc<[IpAddr]>,
+}
+
+impl ProxyProtocol {
+    /// Accept headers from `proxies`, or from any peer if empty.
+    pub fn new(proxies: &[IpAddr]) -> Self {
+        Self { proxies: proxies.into() }
+    }
+
+    /// Read the header from the start of `stream`, returning the
+    /// address of the client. Nothing past the header is consumed.
+    /// Health checks sent by the proxy itself keep the `peer` address.
+    pub async fn accept<R>(&self, stream: &mut R, peer: SocketAddr) -> io::Result<SocketAddr>
+    where
+        R: AsyncRead + Unpin,
+    {
+        if !self.proxies.is_empty() && !self.proxies.contains(&peer.ip()) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "peer is not a trusted proxy"));
+        }
+
+        // Both versions are at least this long.
+        let mut head = [0u8; 16];
+        stream.read_exact(&mut head[..8]).await?;
+
+        if head.starts_with(b"PROXY ") {
+            return read_v1(stream, &head[..8], peer).await;
+        }
+
+        if head[..8] == V2_SIGNATURE[..8] {
+            stream.read_exact(&mut head[8..]).await?;
+            if head[..12] == V2_SIGNATURE {
+                return read_v2(stream, &head, peer).await;
+            }
+        }
+
+        Err(invalid("not a PROXY protocol header"))
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n`, or
+/// `PROXY UNKNOWN ...\r\n`.
+async fn read_v1<R>(stream: &mut R, start: &[u8], peer: SocketAddr) -> io::Result<SocketAddr>
+where
+    R: AsyncRead + Unpin,
+{
+    // Read a byte at a time, so that nothing after the header is lost.
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX {
+            return Err(invalid("PROXY v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("PROXY v1 header is not ASCII"))?;
+    let fields: Vec<_> = line.split(' ').collect();
+
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(peer),
+        ["PROXY", "TCP4" | "TCP6", src, _dst, src_port, _dst_port] => {
+            let ip = src.parse().map_err(|_| invalid("bad PROXY v1 source address"))?;
+            let port = src_port.parse().map_err(|_| invalid("bad PROXY v1 source port"))?;
+            Ok(SocketAddr::new(ip, port))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+/// The binary header: signature, version and command, address family,
+/// length, then the addresses and any TLVs, which are skipped.
+async fn read_v2<R>(stream: &mut R, head: &[u8; 16], peer: SocketAddr) -> io::Result<SocketAddr>
+where
+    R: AsyncRead + Unpin,
+{
+    let (version, command) = (head[12] >> 4, head[12] & 0x0f);
+    let family = head[13] >> 4;
+    let len = u16::from_be_bytes([head[14], head[15]]) as usize;
+
+    if version != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+
+    match (command, family) {
+        // LOCAL: a connection made by the proxy itself.
+        (0, _) => Ok(peer),
+        (1, 1) if len >= 12 => {
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&body[0..4]).unwrap());
+            let port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(SocketAddr::new(ip.into(), port))
+        }
+        (1, 2) if len >= 36 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&body[0..16]).unwrap());
+            let port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(SocketAddr::new(ip.into(), port))
+        }
+        // Unix sockets and unspecified families carry no IP address.
+        (1, 0 | 3) => Ok(peer),
+        _ => Err(invalid("malformed PROXY v2 header")),
+    }
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "10.0.0.1:40000";
+
+    /// Run `header` followed by a line of IRC through [ProxyProtocol],
+    /// checking that the line is left unread.
+    async fn accept(proxies: &[IpAddr], header: &[u8]) -> io::Result<SocketAddr> {
+        let input = [header, b"NICK alice\r\n"].concat();
+        let mut stream = &input[..];
+
+        let addr = ProxyProtocol::new(proxies).accept(&mut stream, PEER.parse().unwrap()).await?;
+        assert_eq!(stream, b"NICK alice\r\n");
+        Ok(addr)
+    }
+
+    fn v2_header(command: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() as u16).to_be_bytes();
+        [&V2_SIGNATURE[..], &[0x20 | command, family << 4 | 1], &len, body].concat()
+    }
+
+    #[tokio::test]
+    async fn v1_addresses() {
+        let addr = accept(&[], b"PROXY TCP4 192.0.2.1 10.0.0.2 51000 6667\r\n").await.unwrap();
+        assert_eq!(addr, "192.0.2.1:51000".parse().unwrap());
+
+        let addr = accept(&[], b"PROXY TCP6 2001:db8::1 2001:db8::2 51000 6667\r\n").await.unwrap();
+        assert_eq!(addr, "[2001:db8::1]:51000".parse().unwrap());
+
+        let addr = accept(&[], b"PROXY UNKNOWN\r\n").await.unwrap();
+        assert_eq!(addr, PEER.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn v1_malformed() {
+        for header in [
+            &b"PROXY TCP4 192.0.2.1 10.0.0.2 51000\r\n"[..],
+            b"PROXY TCP4 example.com 10.0.0.2 51000 6667\r\n",
+            b"PROXY TCP4 192.0.2.1 10.0.0.2 65536 6667\r\n",
+            b"NICK alice\r\n",
+        ] {
+            let error = accept(&[], header).await.unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+        }
+
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(V1_MAX));
+        let mut stream = long.as_bytes();
+        let error = ProxyProtocol::new(&[]).accept(&mut stream, PEER.parse().unwrap()).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+    }
+
+    #[tokio::test]
+    async fn v2_addresses() {
+        // Addresses, ports, then a TLV that is skipped.
+        let ports = [&51000u16.to_be_bytes()[..], &6667u16.to_be_bytes()].concat();
+        let body = [&[192, 0, 2, 1, 10, 0, 0, 2][..], &ports, &[0x04, 0, 1, 0]].concat();
+        let addr = accept(&[], &v2_header(1, 1, &body)).await.unwrap();
+        assert_eq!(addr, "192.0.2.1:51000".parse().unwrap());
+
+        let src: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let body = [&src.octets()[..], &[0; 16], &ports].concat();
+        let addr = accept(&[], &v2_header(1, 2, &body)).await.unwrap();
+        assert_eq!(addr, "[2001:db8::1]:51000".parse().unwrap());
+
+        // Health checks from the proxy itself.
+        let addr = accept(&[], &v2_header(0, 0, &[])).await.unwrap();
+        assert_eq!(addr, PEER.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn v2_malformed() {
+        // An IPv4 body too short for its addresses.
+        let error = accept(&[], &v2_header(1, 1, &[192, 0, 2, 1])).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+
+        let mut header = v2_header(1, 1, &[0; 12]);
+        header[12] = 0x11;
+        let error = accept(&[], &header).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+    }
+
+    #[tokio::test]
+    async fn only_trusted_proxies() {
+        let header = b"PROXY TCP4 192.0.2.1 10.0.0.2 51000 6667\r\n";
+
+        let trusted = ["10.0.0.1".parse().unwrap()];
+        assert_eq!(accept(&trusted, header).await.unwrap(), "192.0.2.1:51000".parse().unwrap());
+
+        let other = ["10.0.0.9".parse().unwrap()];
+        let error = accept(&other, header).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied, "{error}");
+    }
+}
@@ -1,4 +1,63 @@
+use chrono::{DateTime, Utc};
 
 pub struct Whois {
     
-}
\ No newline at end of file
+}
+
+/// A registered account.
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// Canonical account name, as shown in `account-tag`
+    /// and `account-notify`.
+    pub name: String,
+}
+
+/// A server ban set by an oper. Clients it matches are refused, and
+/// any already connected are disconnected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    pub kind: BanKind,
+    /// What is banned, in the form [Ban::kind] calls for.
+    pub mask: String,
+    pub reason: String,
+    /// `nick!user@host` of the oper who set the ban.
+    pub set_by: String,
+    pub set_at: DateTime<Utc>,
+    /// The ban is permanent if unset.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// What a [Ban] matches clients by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BanKind {
+    /// A `user@host` mask, matched against both the hostname and the
+    /// IP address of a client. A K-line.
+    Host,
+    /// An IP address or range. Checked before anything is read from a
+    /// connection. A D-line.
+    Ip,
+    /// An account name.
+    Account,
+    /// A DID, which is the account name of OAUTHBEARER logins.
+    Did,
+    /// The SHA-256 fingerprint of a client certificate.
+    Certfp,
+}
+
+impl BanKind {
+    pub const ALL: [BanKind; 5] = [BanKind::Host, BanKind::Ip, BanKind::Account, BanKind::Did, BanKind::Certfp];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BanKind::Host => "HOST",
+            BanKind::Ip => "IP",
+            BanKind::Account => "ACCOUNT",
+            BanKind::Did => "DID",
+            BanKind::Certfp => "CERTFP",
+        }
+    }
+}
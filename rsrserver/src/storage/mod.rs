@@ -3,6 +3,7 @@ use std::sync::Arc;
 use crate::error::StorageError;
 
 mod irc_model;
+pub use irc_model::{Account, Ban, BanKind, Whois};
 
 pub type StorageResult<T, E> = Result<T, StorageError<E>>;
 
@@ -11,7 +12,32 @@ pub trait Storage: Send + Sync {
 
     /// Retrieve WHOIS information for the given nick, or None if
     /// the Nick is not registered.
-    async fn whois(&self, nick: &str) -> StorageResult<Option<irc_model::Whois>, Self::Error>;
+    fn whois(&self, nick: &str) -> impl Future<Output = StorageResult<Option<Whois>, Self::Error>> + Send;
+
+    /// Check the password of an account, returning the account if
+    /// it exists and the password matches.
+    fn check_password(
+        &self,
+        account: &str,
+        password: &str,
+    ) -> impl Future<Output = StorageResult<Option<Account>, Self::Error>> + Send;
+
+    /// Find the account a client certificate, identified by its
+    /// SHA-256 fingerprint in lowercase hex, is registered to.
+    fn account_by_certfp(
+        &self,
+        fingerprint: &str,
+    ) -> impl Future<Output = StorageResult<Option<Account>, Self::Error>> + Send;
+
+    /// Every ban that has been added and not removed, expired or not.
+    /// Read once at startup.
+    fn bans(&self) -> impl Future<Output = StorageResult<Vec<Ban>, Self::Error>> + Send;
+
+    /// Store a ban, replacing any other of the same kind and mask.
+    fn add_ban(&self, ban: &Ban) -> impl Future<Output = StorageResult<(), Self::Error>> + Send;
+
+    /// Remove the ban of `kind` on `mask`, if there is one.
+    fn remove_ban(&self, kind: BanKind, mask: &str) -> impl Future<Output = StorageResult<(), Self::Error>> + Send;
 }
 
 // () is a dummy provider that no-ops everything.
@@ -19,14 +45,61 @@ pub trait Storage: Send + Sync {
 impl Storage for () {
     type Error = ();
 
-    async fn whois(&self, _nick: &str) -> StorageResult<Option<irc_model::Whois>, Self::Error> {
-        todo!()
+    async fn whois(&self, _nick: &str) -> StorageResult<Option<Whois>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn check_password(&self, _account: &str, _password: &str) -> StorageResult<Option<Account>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn account_by_certfp(&self, _fingerprint: &str) -> StorageResult<Option<Account>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn bans(&self) -> StorageResult<Vec<Ban>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    async fn add_ban(&self, _ban: &Ban) -> StorageResult<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn remove_ban(&self, _kind: BanKind, _mask: &str) -> StorageResult<(), Self::Error> {
+        Ok(())
     }
 }
 
 impl<T> Storage for Arc<T> where T: Storage {
     type Error = T::Error;
-    async fn whois(&self, nick: &str) -> StorageResult<Option<irc_model::Whois>, Self::Error> {
-        self.as_ref().whois(nick).await
+    fn whois(&self, nick: &str) -> impl Future<Output = StorageResult<Option<Whois>, Self::Error>> + Send {
+        self.as_ref().whois(nick)
+    }
+
+    fn check_password(
+        &self,
+        account: &str,
+        password: &str,
+    ) -> impl Future<Output = StorageResult<Option<Account>, Self::Error>> + Send {
+        self.as_ref().check_password(account, password)
     }
-}
\ No newline at end of file
+
+    fn account_by_certfp(
+        &self,
+        fingerprint: &str,
+    ) -> impl Future<Output = StorageResult<Option<Account>, Self::Error>> + Send {
+        self.as_ref().account_by_certfp(fingerprint)
+    }
+
+    fn bans(&self) -> impl Future<Output = StorageResult<Vec<Ban>, Self::Error>> + Send {
+        self.as_ref().bans()
+    }
+
+    fn add_ban(&self, ban: &Ban) -> impl Future<Output = StorageResult<(), Self::Error>> + Send {
+        self.as_ref().add_ban(ban)
+    }
+
+    fn remove_ban(&self, kind: BanKind, mask: &str) -> impl Future<Output = StorageResult<(), Self::Error>> + Send {
+        self.as_ref().remove_ban(kind, mask)
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+
+use crate::{cidr::Cidr, config::ConnectionsConfig, irc::Bans};
+
+/// Turns away connections from addresses that are banned, that already
+/// have too many open, or that connect too often, before anything is
+/// read from them. Shared by every listener, so limits hold across all
+/// of them.
+pub struct ConnectionThrottle {
+    state: Mutex<State>,
+    bans: Arc<Bans>,
+}
+
+#[derive(Default)]
+struct State {
+    config: ConnectionsConfig,
+    // Open connections per address, and per range of addresses.
+    open: HashMap<Cidr, usize>,
+    recent: HashMap<IpAddr, Recent>,
+    swept: Option<Instant>,
+}
+
+/// Connections from a single address in the current window.
+struct Recent {
+    since: Instant,
+    count: u32,
+    banned_until: Option<Instant>,
+}
+
+/// Why a connection was turned away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// An IP ban, for this reason.
+    Banned(String),
+    TooManyFromHost,
+    TooManyFromRange,
+    Throttled,
+}
+
+impl Rejection {
+    pub fn reason(&self) -> String {
+        match self {
+            Rejection::Banned(reason) => format!("Banned ({reason})"),
+            Rejection::TooManyFromHost => "Too many connections from your host".to_owned(),
+            Rejection::TooManyFromRange => "Too many connections from your network".to_owned(),
+            Rejection::Throttled => "Connecting too fast, try again later".to_owned(),
+        }
+    }
+
+    /// Tell the client why before closing the connection on it.
+    async fn send<W>(&self, stream: &mut W, addr: IpAddr)
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let line = format!("ERROR :Closing Link: {addr} ({})\r\n", self.reason());
+        // The connection is dropped either way.
+        let _ = stream.write_all(line.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+/// A connection counted against the limits of its address until this
+/// is dropped.
+pub struct Admitted {
+    throttle: Arc<ConnectionThrottle>,
+    ranges: Vec<Cidr>,
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        let mut state = self.throttle.state.lock().unwrap();
+        for range in &self.ranges {
+            if let Some(count) = state.open.get_mut(range) {
+                *count -= 1;
+                if *count == 0 {
+                    state.open.remove(range);
+                }
+            }
+        }
+    }
+}
+
+impl ConnectionThrottle {
+    pub fn new(config: &ConnectionsConfig, bans: Arc<Bans>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                config: config.clone(),
+                ..State::default()
+            }),
+            bans,
+        }
+    }
+
+    /// Put new limits into effect. Connections already open stay open.
+    pub fn configure(&self, config: &ConnectionsConfig) {
+        self.state.lock().unwrap().config = config.clone();
+    }
+
+    /// Count a new connection from `addr`, unless it is banned or over
+    /// one of the limits. Exempt addresses are admitted unless banned,
+    /// and not counted.
+    pub fn admit(self: &Arc<Self>, addr: IpAddr) -> Result<Admitted, Rejection> {
+        let addr = addr.to_canonical();
+        if let Some(ban) = self.bans.find_ip(addr) {
+            return Err(Rejection::Banned(ban.reason));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let config = &state.config;
+
+        if config.exempt.iter().any(|range| range.contains(addr)) {
+            return Ok(Admitted {
+                throttle: Arc::clone(self),
+                ranges: Vec::new(),
+            });
+        }
+
+        let host = Cidr::host(addr);
+        let range = match addr {
+            IpAddr::V4(_) => Cidr::new(addr, config.ipv4_range),
+            IpAddr::V6(_) => Cidr::new(addr, config.ipv6_range),
+        };
+        let (per_ip, per_range) = (config.per_ip, config.per_range);
+        let (window, ban) = (config.throttle_window(), config.throttle_ban());
+        let throttle = config.throttle;
+
+        let now = Instant::now();
+        state.sweep(now, window);
+
+        let recent = state.recent.entry(addr).or_insert_with(|| Recent::new(now));
+
+        // Start over once the window or the ban is over.
+        match recent.banned_until {
+            Some(until) if now < until => return Err(Rejection::Throttled),
+            Some(_) => recent.reset(now),
+            None if now.duration_since(recent.since) >= window => recent.reset(now),
+            None => {}
+        }
+
+        recent.count += 1;
+        if recent.count > throttle {
+            recent.banned_until = Some(now + ban);
+            tracing::info!(%addr, "connection throttled");
+            return Err(Rejection::Throttled);
+        }
+
+        if state.open.get(&host).copied().unwrap_or(0) >= per_ip {
+            return Err(Rejection::TooManyFromHost);
+        }
+
+        if state.open.get(&range).copied().unwrap_or(0) >= per_range {
+            return Err(Rejection::TooManyFromRange);
+        }
+
+        // A host is its own range when the range is as narrow as can be.
+        let mut ranges = vec![host];
+        if range != host {
+            ranges.push(range);
+        }
+        for range in &ranges {
+            *state.open.entry(*range).or_default() += 1;
+        }
+
+        Ok(Admitted {
+            throttle: Arc::clone(self),
+            ranges,
+        })
+    }
+
+    /// [ConnectionThrottle::admit] a connection from `addr`. A client
+    /// that is turned away is told why in an `ERROR` line if `explain`,
+    /// which only makes sense for plain IRC.
+    pub async fn admit_stream<W>(self: &Arc<Self>, stream: &mut W, addr: SocketAddr, explain: bool) -> Option<Admitted>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self.admit(addr.ip()) {
+            Ok(admitted) => Some(admitted),
+            Err(rejection) => {
+                tracing::debug!(%addr, "connection refused: {}", rejection.reason());
+                if explain {
+                    rejection.send(stream, addr.ip()).await;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl State {
+    /// Forget addresses whose window and ban are both over, at most
+    /// once every `window`.
+    fn sweep(&mut self, now: Instant, window: Duration) {
+        if self.swept.is_some_and(|swept| now.duration_since(swept) < window) {
+            return;
+        }
+
+        self.swept = Some(now);
+        self.recent.retain(|_, recent| {
+            now.duration_since(recent.since) < window || recent.banned_until.is_some_and(|until| now < until)
+        });
+    }
+}
+
+impl Recent {
+    fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            count: 0,
+            banned_until: None,
+        }
+    }
+
+    fn reset(&mut self, now: Instant) {
+        *self = Self::new(now);
+    }
+}
@@ -1,84 +1,323 @@
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::path::PathBuf;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
 
 use std::sync::Arc;
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Result, WrapErr};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
 use rustls::pki_types::pem::PemObject;
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
+use sha2::{Digest, Sha256};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
 use tokio_rustls::server::TlsStream;
 use tokio_rustls::{TlsAcceptor, rustls};
 
+use crate::config::CertificateConfig;
+use crate::proxy::ProxyProtocol;
+use crate::throttle::ConnectionThrottle;
+
 pub struct TlsServerConfig {
     pub addr: SocketAddr,
-    pub cert: PathBuf,
-    pub key: PathBuf,
-}
-
-impl Default for TlsServerConfig {
-    fn default() -> Self {
-        Self {
-            addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 443)),
-            cert: PathBuf::new(),
-            key: PathBuf::new(),
-        }
-    }
+    /// Certificates to present, shared by every listener that is
+    /// reloaded together.
+    pub certs: Arc<CertResolver>,
+    /// Time a client has to complete its handshake.
+    pub handshake_timeout: Duration,
+    /// Handshakes in progress at once.
+    pub max_handshakes: usize,
+    /// Read a PROXY protocol header ahead of the handshake.
+    pub proxy: Option<ProxyProtocol>,
+    /// Turns away clients over their connection limits before the
+    /// handshake.
+    pub throttle: Arc<ConnectionThrottle>,
+    /// Clients speak WebSocket rather than IRC, so those turned away
+    /// are not sent an `ERROR` line.
+    pub websocket: bool,
 }
 
 pub struct TlsServer {
     listener: TcpListener,
     acceptor: TlsAcceptor,
+    handshake_timeout: Duration,
+    handshakes: Arc<Semaphore>,
+    proxy: Option<ProxyProtocol>,
+    throttle: Arc<ConnectionThrottle>,
+    websocket: bool,
 }
 
 impl TlsServer {
     pub async fn create(cfg: TlsServerConfig) -> Result<Self> {
-        let certs = CertificateDer::pem_file_iter(cfg.cert)?.collect::<Result<Vec<_>, _>>()?;
-        let key = PrivateKeyDer::from_pem_file(cfg.key)?;
-
-        // Build the TLS side of the server.
-        let config = rustls::ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, key)?;
+        // Build the TLS side of the server. The certificate is picked
+        // anew for every handshake, so a reload takes effect at once.
+        let builder = rustls::ServerConfig::builder();
+        let verifier = AnyClientCert {
+            provider: Arc::clone(builder.crypto_provider()),
+        };
+        let config = builder
+            .with_client_cert_verifier(Arc::new(verifier))
+            .with_cert_resolver(cfg.certs);
         let acceptor = TlsAcceptor::from(Arc::new(config));
 
         // Create the server listener.
-        let listener: TcpListener = TcpListener::bind(&cfg.addr).await?;
+        let listener: TcpListener = crate::listen::bind(cfg.addr)?;
 
         Ok(Self {
             listener,
             acceptor,
+            handshake_timeout: cfg.handshake_timeout,
+            handshakes: Arc::new(Semaphore::new(cfg.max_handshakes)),
+            proxy: cfg.proxy,
+            throttle: cfg.throttle,
+            websocket: cfg.websocket,
         })
     }
 
-    pub async fn serve<H>(&mut self, mut handler: H)
+    pub async fn serve<H>(&mut self, handler: H)
     where
         H: TlsHandler,
     {
         // Main server loop to process incoming requests.
         loop {
-            if let Err(e) = self.serve_once::<H>(&mut handler).await {
-                println!("Error: {e}"); // TODO: Enhance error reporting
+            if let Err(e) = self.serve_once::<H>(&handler).await {
+                tracing::warn!("failed to accept a connection: {e}");
             }
         }
     }
 
-    async fn serve_once<H>(&mut self, handler: &mut H) -> Result<()>
+    /// Accept a single connection, and hand it to a task of its own
+    /// that runs the handshake and then the handler. Once
+    /// `max_handshakes` are in progress, no more connections are
+    /// accepted until one of them finishes.
+    async fn serve_once<H>(&mut self, handler: &H) -> Result<()>
     where
         H: TlsHandler,
     {
-        let (stream, client_addr) = self.listener.accept().await?;
+        let permit = Arc::clone(&self.handshakes).acquire_owned().await?;
+        let (mut stream, peer) = self.listener.accept().await?;
+
         let acceptor = self.acceptor.clone();
+        let handshake_timeout = self.handshake_timeout;
+        let proxy = self.proxy.clone();
+        let throttle = Arc::clone(&self.throttle);
+        let explain = !self.websocket;
+        let mut handler = handler.clone();
 
-        let accepted_stream = acceptor.accept(stream).await?;
+        tokio::spawn(async move {
+            // The PROXY protocol header comes before the TLS handshake,
+            // and connection limits are checked in between.
+            let handshake = async {
+                let client_addr = match &proxy {
+                    Some(proxy) => proxy.accept(&mut stream, peer).await?,
+                    None => peer,
+                };
+                let Some(admitted) = throttle.admit_stream(&mut stream, client_addr, explain).await else {
+                    return Ok(None);
+                };
+                Ok::<_, std::io::Error>(Some((acceptor.accept(stream).await?, client_addr, admitted)))
+            };
+            let accepted = timeout(handshake_timeout, handshake).await;
+            drop(permit);
 
-        tokio::spawn(handler.handle(accepted_stream, client_addr));
+            match accepted {
+                Ok(Ok(Some((stream, client_addr, _admitted)))) => handler.handle(stream, client_addr).await,
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => tracing::debug!(%peer, "TLS handshake failed: {e}"),
+                Err(_) => tracing::debug!(%peer, "TLS handshake timed out"),
+            }
+        });
 
         Ok(())
     }
 }
 
-pub trait TlsHandler: Send + Sync + 'static {
+/// SHA-256 fingerprint of the certificate the client presented, in
+/// lowercase hex.
+pub fn certfp(stream: &TlsStream<TcpStream>) -> Option<String> {
+    let cert = stream.get_ref().1.peer_certificates()?.first()?;
+    Some(Sha256::digest(cert).iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Asks clients for a certificate without requiring one, and accepts
+/// any certificate that is offered, self-signed or not. Clients are
+/// only ever told apart by the fingerprint of their certificate, so
+/// there is nothing to check it against, but they still have to prove
+/// that they hold its key.
+#[derive(Debug)]
+struct AnyClientCert {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ClientCertVerifier for AnyClientCert {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Picks the certificate for a handshake by the server name the client
+/// asks for, falling back to the listener's own `cert` and `key`.
+/// [CertResolver::reload] swaps the certificates out without touching
+/// connections that are already established.
+#[derive(Debug)]
+pub struct CertResolver {
+    cert: PathBuf,
+    key: PathBuf,
+    provider: Arc<CryptoProvider>,
+    loaded: RwLock<Arc<LoadedCerts>>,
+}
+
+#[derive(Debug)]
+struct LoadedCerts {
+    default: Arc<CertifiedKey>,
+    named: Vec<(Vec<String>, Arc<CertifiedKey>)>,
+    /// Every file that was read, and when it was last modified.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl CertResolver {
+    pub fn new(cert: PathBuf, key: PathBuf, certificates: &[CertificateConfig]) -> Result<Self> {
+        // The provider servers are built with, which keys have to be
+        // loaded by as well.
+        let provider = Arc::clone(rustls::ServerConfig::builder().crypto_provider());
+        let loaded = LoadedCerts::load(&provider, &cert, &key, certificates)?;
+
+        Ok(Self {
+            cert,
+            key,
+            provider,
+            loaded: RwLock::new(Arc::new(loaded)),
+        })
+    }
+
+    /// Read every certificate again, taking `certificates` in place of
+    /// the ones given before. The certificates in use are kept if any
+    /// of them can not be loaded.
+    pub fn reload(&self, certificates: &[CertificateConfig]) -> Result<()> {
+        let loaded = LoadedCerts::load(&self.provider, &self.cert, &self.key, certificates)?;
+        *self.loaded.write().unwrap() = Arc::new(loaded);
+
+        Ok(())
+    }
+
+    /// Whether any certificate or key file was modified since it was
+    /// last read.
+    pub fn changed(&self) -> bool {
+        let loaded = Arc::clone(&self.loaded.read().unwrap());
+        loaded.files.iter().any(|(path, modified)| modified_at(path) != *modified)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let loaded = self.loaded.read().unwrap();
+        let named = client_hello.server_name().and_then(|name| {
+            loaded.named.iter().find(|(names, _)| names.iter().any(|pattern| name_matches(pattern, name)))
+        });
+
+        Some(Arc::clone(named.map_or(&loaded.default, |(_, key)| key)))
+    }
+}
+
+impl LoadedCerts {
+    fn load(provider: &CryptoProvider, cert: &Path, key: &Path, certificates: &[CertificateConfig]) -> Result<Self> {
+        let mut files = Vec::new();
+        let default = load_key(provider, cert, key, &mut files)?;
+        let named = certificates
+            .iter()
+            .map(|c| Ok((c.names.clone(), load_key(provider, &c.cert, &c.key, &mut files)?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { default, named, files })
+    }
+}
+
+fn load_key(
+    provider: &CryptoProvider,
+    cert: &Path,
+    key: &Path,
+    files: &mut Vec<(PathBuf, Option<SystemTime>)>,
+) -> Result<Arc<CertifiedKey>> {
+    // Modification times are taken first, so that a file written while
+    // it is read is read again.
+    files.push((cert.to_owned(), modified_at(cert)));
+    files.push((key.to_owned(), modified_at(key)));
+
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .wrap_err_with(|| format!("cannot read {}", cert.display()))?;
+    let key_der = PrivateKeyDer::from_pem_file(key).wrap_err_with(|| format!("cannot read {}", key.display()))?;
+    let certified = CertifiedKey::from_der(chain, key_der, provider)
+        .wrap_err_with(|| format!("cannot use {} with {}", key.display(), cert.display()))?;
+
+    Ok(Arc::new(certified))
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether a server name matches a name of a `[[certificate]]`, where
+/// `*.` stands for a single label.
+fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(domain)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+/// Runs a connection once its handshake completes. Every connection
+/// gets a clone of the handler given to [TlsServer::serve].
+pub trait TlsHandler: Clone + Send + Sync + 'static {
     type Future: AsyncFuture<()>;
 
     fn handle(
@@ -92,7 +331,7 @@ pub trait TlsHandler: Send + Sync + 'static {
 impl<F, Fut> TlsHandler for F
 where
     Fut: AsyncFuture<()>,
-    F: Fn(TlsStream<TcpStream>, SocketAddr) -> Fut + Send + Sync + 'static,
+    F: Fn(TlsStream<TcpStream>, SocketAddr) -> Fut + Clone + Send + Sync + 'static,
 {
     type Future = Fut;
 
@@ -105,8 +344,7 @@ where
     }
 }
 
-pub trait AsyncFriendly: Send + Sync + 'static {}
-impl<T> AsyncFriendly for T where T: Send + Sync + 'static {}
-
-pub trait AsyncFuture<T>: Future<Output = T> + AsyncFriendly {}
-impl<T, O> AsyncFuture<O> for T where T: Future<Output = O> + Send + Sync + 'static {}
+// Futures only ever need to be Send to be spawned, which leaves
+// handlers free to hold non-Sync state across await points.
+pub trait AsyncFuture<T>: Future<Output = T> + Send + 'static {}
+impl<T, O> AsyncFuture<O> for T where T: Future<Output = O> + Send + 'static {}
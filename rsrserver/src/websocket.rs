@@ -0,0 +1,418 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream, WriteHalf, duplex, split},
+    net::TcpStream,
+    select,
+    time::timeout,
+};
+use tokio_rustls::server::TlsStream;
+use tokio_tungstenite::{
+    WebSocketStream, accept_hdr_async_with_config,
+    tungstenite::{
+        self, Message,
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{HeaderMap, HeaderValue, StatusCode},
+        protocol::WebSocketConfig,
+    },
+};
+
+use crate::{
+    irc::{IrcServer, Transport},
+    storage::Storage,
+    tls::{self, TlsHandler},
+};
+
+/// Subprotocols of the IRCv3 WebSocket transport.
+const TEXT_PROTOCOL: &str = "text.ircv3.net";
+const BINARY_PROTOCOL: &str = "binary.ircv3.net";
+
+/// How IRC lines are put in frames, as negotiated through
+/// `Sec-WebSocket-Protocol`. Clients that ask for neither get text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Text,
+    Binary,
+}
+
+/// Settings of a single WebSocket listener.
+pub struct WebSocketSettings {
+    /// Origins browsers may connect from. Any origin if empty.
+    pub origins: Vec<String>,
+    /// Reverse proxies whose forwarding headers are trusted.
+    pub proxies: Vec<IpAddr>,
+    /// Time a client has to complete the HTTP upgrade.
+    pub handshake_timeout: Duration,
+    /// Largest frame accepted from a client.
+    pub max_line: usize,
+}
+
+/// What the upgrade request told us about the client.
+struct Upgrade {
+    framing: Framing,
+    client_addr: SocketAddr,
+    // The reverse proxy terminated TLS for the client.
+    forwarded_secure: bool,
+}
+
+/// Runs IRC over WebSockets, one message per frame. Serves `ws://`
+/// listeners directly and `wss://` ones as a [TlsHandler].
+pub struct WebSocketHandler<S> {
+    server: IrcServer<S>,
+    settings: Arc<WebSocketSettings>,
+}
+
+impl<S> Clone for WebSocketHandler<S> {
+    fn clone(&self) -> Self {
+        Self {
+            server: self.server.clone(),
+            settings: Arc::clone(&self.settings),
+        }
+    }
+}
+
+impl<S> WebSocketHandler<S>
+where
+    S: Storage + 'static,
+{
+    pub fn new(server: IrcServer<S>, settings: WebSocketSettings) -> Self {
+        Self {
+            server,
+            settings: Arc::new(settings),
+        }
+    }
+
+    /// Run the WebSocket handshake over `stream`, then IRC over the
+    /// WebSocket until the connection ends.
+    pub fn connect<IO>(
+        &self,
+        stream: IO,
+        peer: SocketAddr,
+        transport: Transport,
+    ) -> impl Future<Output = ()> + Send + use<S, IO>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let server = self.server.clone();
+        let settings = Arc::clone(&self.settings);
+
+        async move {
+            let (ws, upgrade) = match timeout(settings.handshake_timeout, handshake(stream, peer, &settings)).await {
+                Ok(Ok(accepted)) => accepted,
+                Ok(Err(e)) => return tracing::debug!(%peer, "WebSocket handshake failed: {e}"),
+                Err(_) => return tracing::debug!(%peer, "WebSocket handshake timed out"),
+            };
+
+            let transport = Transport {
+                secure: transport.secure || upgrade.forwarded_secure,
+                ..transport
+            };
+
+            // The IRC engine reads and writes lines on one end of the
+            // pipe, and the pump turns them into frames on the other.
+            let (engine, pipe) = duplex(2 * settings.max_line);
+            tokio::spawn(pump(ws, pipe, upgrade.framing));
+
+            server.connect(engine, upgrade.client_addr, transport).await
+        }
+    }
+}
+
+impl<S> TlsHandler for WebSocketHandler<S>
+where
+    S: Storage + 'static,
+{
+    type Future = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    fn handle(&mut self, stream: TlsStream<TcpStream>, client_addr: SocketAddr) -> Self::Future {
+        let certfp = tls::certfp(&stream);
+        Box::pin(self.connect(stream, client_addr, Transport::secure(certfp)))
+    }
+}
+
+/// Answer the HTTP upgrade request, refusing origins that are not
+/// allowed and picking a subprotocol.
+async fn handshake<IO>(
+    stream: IO,
+    peer: SocketAddr,
+    settings: &WebSocketSettings,
+) -> Result<(WebSocketStream<IO>, Upgrade), tungstenite::Error>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut upgrade = Upgrade {
+        framing: Framing::Text,
+        client_addr: peer,
+        forwarded_secure: false,
+    };
+
+    let callback = |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        let headers = request.headers();
+
+        if !origin_allowed(headers, &settings.origins) {
+            return Err(refuse(StatusCode::FORBIDDEN, "Origin not allowed"));
+        }
+
+        if let Some((protocol, framing)) = negotiate(headers) {
+            response
+                .headers_mut()
+                .insert("sec-websocket-protocol", HeaderValue::from_static(protocol));
+            upgrade.framing = framing;
+        }
+
+        if settings.proxies.contains(&peer.ip()) {
+            if let Some(ip) = forwarded_for(headers, &settings.proxies) {
+                upgrade.client_addr = SocketAddr::new(ip, 0);
+            }
+            upgrade.forwarded_secure = forwarded_proto(headers).is_some_and(|proto| proto == "https");
+        }
+
+        Ok(response)
+    };
+
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(settings.max_line))
+        .max_frame_size(Some(settings.max_line));
+    let ws = accept_hdr_async_with_config(stream, callback, Some(config)).await?;
+
+    Ok((ws, upgrade))
+}
+
+fn refuse(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_owned()));
+    *response.status_mut() = status;
+    response
+}
+
+/// Whether the `Origin` of the upgrade request is one of `origins`.
+/// Any origin is allowed if there are none.
+fn origin_allowed(headers: &HeaderMap, origins: &[String]) -> bool {
+    if origins.is_empty() {
+        return true;
+    }
+
+    let origin = headers.get("origin").and_then(|origin| origin.to_str().ok());
+    origin.is_some_and(|origin| origins.iter().any(|allowed| allowed == origin))
+}
+
+/// The first IRCv3 subprotocol the client offers, in its order of
+/// preference.
+fn negotiate(headers: &HeaderMap) -> Option<(&'static str, Framing)> {
+    headers
+        .get_all("sec-websocket-protocol")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| match protocol.trim() {
+            TEXT_PROTOCOL => Some((TEXT_PROTOCOL, Framing::Text)),
+            BINARY_PROTOCOL => Some((BINARY_PROTOCOL, Framing::Binary)),
+            _ => None,
+        })
+}
+
+/// The address of the client according to `X-Forwarded-For`: the last
+/// hop that is not a trusted proxy. Earlier hops could be forged by
+/// the client itself.
+fn forwarded_for(headers: &HeaderMap, proxies: &[IpAddr]) -> Option<IpAddr> {
+    let hops: Vec<_> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    hops.into_iter()
+        .rev()
+        .map(|hop| hop.parse::<IpAddr>().ok())
+        .find(|ip| ip.is_none_or(|ip| !proxies.contains(&ip)))
+        .flatten()
+}
+
+/// The scheme the client used to reach the outermost proxy.
+fn forwarded_proto(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get("x-forwarded-proto")?.to_str().ok()?;
+    value.split(',').next().map(str::trim)
+}
+
+/// Carry lines between the WebSocket and the pipe to the IRC engine
+/// until either side goes away.
+async fn pump<IO>(ws: WebSocketStream<IO>, pipe: DuplexStream, framing: Framing)
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (pipe_rx, mut pipe_tx) = split(pipe);
+    let mut pipe_rx = BufReader::new(pipe_rx);
+    let mut line = Vec::new();
+
+    loop {
+        select! {
+            frame = ws_rx.next() => {
+                let forwarded = match frame {
+                    Some(Ok(Message::Text(text))) => forward(&mut pipe_tx, text.as_bytes()).await,
+                    Some(Ok(Message::Binary(bytes))) => forward(&mut pipe_tx, &bytes).await,
+                    // Pings are answered by tungstenite itself.
+                    Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => Ok(()),
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                };
+
+                if forwarded.is_err() {
+                    break;
+                }
+            }
+            read = pipe_rx.read_until(b'\n', &mut line) => {
+                if !matches!(read, Ok(1..)) {
+                    break;
+                }
+
+                let text = trim_line(&line);
+                let message = match framing {
+                    Framing::Text => Message::text(String::from_utf8_lossy(text).into_owned()),
+                    Framing::Binary => Message::binary(text.to_vec()),
+                };
+                line.clear();
+
+                if ws_tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = ws_tx.close().await;
+}
+
+/// Hand the payload of a frame to the IRC engine as a single line.
+/// A frame holding more than one line ends the connection.
+async fn forward(pipe: &mut WriteHalf<DuplexStream>, payload: &[u8]) -> std::io::Result<()> {
+    let line = trim_line(payload);
+    if line.iter().any(|b| matches!(b, b'\r' | b'\n')) {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+
+    pipe.write_all(line).await?;
+    pipe.write_all(b"\r\n").await
+}
+
+/// Strip the line ending, which is not sent over WebSockets.
+fn trim_line(line: &[u8]) -> &[u8] {
+    let end = line.iter().rposition(|b| !matches!(b, b'\r' | b'\n')).map_or(0, |i| i + 1);
+    &line[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    /// A client WebSocket whose server end is pumped into the returned
+    /// pipe, as the IRC engine would see it.
+    async fn pumped(framing: Framing) -> (WebSocketStream<DuplexStream>, BufReader<DuplexStream>) {
+        let (client, server) = duplex(4096);
+        let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+
+        let (engine, pipe) = duplex(4096);
+        tokio::spawn(pump(server, pipe, framing));
+        (client, BufReader::new(engine))
+    }
+
+    async fn read_line(engine: &mut BufReader<DuplexStream>) -> String {
+        let mut line = String::new();
+        engine.read_line(&mut line).await.unwrap();
+        line
+    }
+
+    #[test]
+    fn origins_are_allow_listed() {
+        let origins = ["https://rsr.chat".to_owned()];
+
+        assert!(origin_allowed(&headers(&[("origin", "https://rsr.chat")]), &origins));
+        assert!(!origin_allowed(&headers(&[("origin", "https://evil.example")]), &origins));
+        assert!(!origin_allowed(&headers(&[]), &origins));
+        assert!(origin_allowed(&headers(&[("origin", "https://evil.example")]), &[]));
+    }
+
+    #[test]
+    fn subprotocol_in_client_order() {
+        let offered = headers(&[("sec-websocket-protocol", "chat, binary.ircv3.net, text.ircv3.net")]);
+        assert_eq!(negotiate(&offered), Some((BINARY_PROTOCOL, Framing::Binary)));
+
+        let offered = headers(&[("sec-websocket-protocol", "text.ircv3.net"), ("sec-websocket-protocol", "binary.ircv3.net")]);
+        assert_eq!(negotiate(&offered), Some((TEXT_PROTOCOL, Framing::Text)));
+
+        assert_eq!(negotiate(&headers(&[("sec-websocket-protocol", "chat")])), None);
+    }
+
+    #[test]
+    fn forwarded_for_skips_trusted_proxies() {
+        let proxies = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        let forwarded = headers(&[("x-forwarded-for", "192.0.2.1, 198.51.100.7, 10.0.0.2")]);
+        assert_eq!(forwarded_for(&forwarded, &proxies), "198.51.100.7".parse().ok());
+
+        // A hop that is not an address can not be trusted any further.
+        let forwarded = headers(&[("x-forwarded-for", "192.0.2.1, unknown, 10.0.0.1")]);
+        assert_eq!(forwarded_for(&forwarded, &proxies), None);
+
+        let forwarded = headers(&[("x-forwarded-proto", "https, http")]);
+        assert_eq!(forwarded_proto(&forwarded), Some("https"));
+    }
+
+    #[tokio::test]
+    async fn text_framing() {
+        let (mut client, mut engine) = pumped(Framing::Text).await;
+
+        client.send(Message::text("NICK alice")).await.unwrap();
+        assert_eq!(read_line(&mut engine).await, "NICK alice\r\n");
+
+        // Binary frames are taken either way, and a line ending is
+        // dropped rather than doubled.
+        client.send(Message::binary(&b"USER alice 0 * :Alice\r\n"[..])).await.unwrap();
+        assert_eq!(read_line(&mut engine).await, "USER alice 0 * :Alice\r\n");
+
+        engine.get_mut().write_all(b":irc.test PING :x\r\n").await.unwrap();
+        match client.next().await {
+            Some(Ok(Message::Text(text))) => assert_eq!(text.as_str(), ":irc.test PING :x"),
+            other => panic!("expected a text frame, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn binary_framing() {
+        let (mut client, mut engine) = pumped(Framing::Binary).await;
+
+        engine.get_mut().write_all(b":irc.test PING :x\r\n").await.unwrap();
+        match client.next().await {
+            Some(Ok(Message::Binary(bytes))) => assert_eq!(&bytes[..], b":irc.test PING :x"),
+            other => panic!("expected a binary frame, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn one_line_per_frame() {
+        let (mut client, mut engine) = pumped(Framing::Text).await;
+
+        client.send(Message::text("NICK alice\r\nUSER alice 0 * :Alice")).await.unwrap();
+        let mut rest = Vec::new();
+        engine.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty(), "{rest:?}");
+    }
+}